@@ -1,222 +1,1429 @@
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{bail, Context, Result};
+use crossterm::event::{KeyCode, KeyEvent};
+use globset::{Glob, GlobMatcher};
+
+use crate::command::{self, Command, GotoTarget, Prompt};
+use crate::config::Config;
+use crate::export;
+use crate::git::GitBackend;
+use crate::help::{help_lines, HelpView};
+use crate::highlight::HighlightCache;
+use crate::input::{
+    format_sequence, map_help_key, map_prompt_key, Action, HelpKey, KeyChord, Keymap, MouseInput,
+    MouseKind, MouseTarget, PromptKey, Resolution,
+};
+use crate::layout::LayoutSettings;
+use crate::logging;
+use crate::mode::{CompareMode, ModeBackend};
+use crate::model::{
+    row_for_line, AlignedRow, ChangedFile, ContentData, FileStatus, LoadLimits, RowKind, Side,
+    TreeRow,
+};
+use crate::theme::{ColorDepth, Theme};
+use crate::tree;
+use crate::watch::{Changes, RepoWatcher};
+use crate::worker::{LoadTicket, Loader};
 
-use anyhow::Result;
+/// Largest count prefix accepted; further digits are ignored.
+const MAX_COUNT: usize = 999_999;
 
-use crate::diff::align_full_file;
-use crate::git;
-use crate::input::Action;
-use crate::model::{AlignedRow, ChangedFile, ContentData, TreeRow};
-use crate::tree;
+/// Lines (or columns, sideways) moved per mouse wheel notch.
+const WHEEL_LINES: usize = 3;
+const WHEEL_COLUMNS: usize = 4;
+
+/// Percentage points moved by one resize key press.
+const RESIZE_STEP: u16 = 2;
+
+/// Keys typed towards the next action: an optional count, then the start of a key sequence.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PendingInput {
+    pub count: Option<usize>,
+    pub keys: Vec<KeyChord>,
+}
+
+impl PendingInput {
+    pub fn is_empty(&self) -> bool {
+        self.count.is_none() && self.keys.is_empty()
+    }
+
+    /// Shown in the status area while a count or sequence is incomplete, e.g. `12` or `3g`.
+    pub fn describe(&self) -> String {
+        let count = self.count.map(|n| n.to_string()).unwrap_or_default();
+        format!("{}{}", count, format_sequence(&self.keys).replace(' ', ""))
+    }
+}
+
+/// `:filter` glob restricting which files the tree shows.
+#[derive(Debug, Clone)]
+pub struct FileFilter {
+    pub pattern: String,
+    matcher: GlobMatcher,
+}
+
+impl FileFilter {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let matcher = Glob::new(pattern)
+            .with_context(|| format!("invalid filter {:?}", pattern))?
+            .compile_matcher();
+        Ok(Self {
+            pattern: pattern.to_string(),
+            matcher,
+        })
+    }
+
+    /// A pattern without `/` also matches file names, so `*.rs` finds `src/main.rs`.
+    pub fn matches(&self, path: &Path) -> bool {
+        self.matcher.is_match(path)
+            || (!self.pattern.contains('/')
+                && path
+                    .file_name()
+                    .is_some_and(|name| self.matcher.is_match(name)))
+    }
+}
+
+/// File and line for `:open`, carried out by the event loop, which owns the terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenRequest {
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+/// A line to scroll to once `path` is loaded: the top line captured before a refresh, or a
+/// `:` jump target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScrollAnchor {
+    pub path: PathBuf,
+    pub side: Side,
+    pub line: usize,
+    /// Map the line through the reload's `line_remap`, so it tracks edits made meanwhile.
+    pub follow_edits: bool,
+}
+
+/// Feedback shown in the status bar until the next key or click.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub text: String,
+    pub is_error: bool,
+}
+
+impl Message {
+    pub fn info(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            is_error: false,
+        }
+    }
+
+    pub fn error(err: &anyhow::Error) -> Self {
+        Self {
+            text: format!("{:#}", err),
+            is_error: true,
+        }
+    }
+}
+
+/// Where the view is, for the status bar. Positions are 1-based.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Status {
+    /// Selected file among the files in the tree, and their count.
+    pub file: Option<(usize, usize)>,
+    /// Last change block starting at or above the top row (`None` above the first), and the
+    /// number of blocks; `None` until the file is loaded.
+    pub hunk: Option<(Option<usize>, usize)>,
+    /// `HEAD` and worktree line numbers of the top row, or the next row that has one.
+    pub left_line: Option<usize>,
+    pub right_line: Option<usize>,
+    /// Added and deleted lines of the selected file; a changed row counts as one of each.
+    pub stats: Option<(usize, usize)>,
+    /// What is compared, then any filter, paused updates or degraded rendering.
+    pub modes: Vec<String>,
+}
+
+/// What a held left button is moving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Drag {
+    Scrollbar,
+    TreeBorder,
+    SplitBorder,
+}
 
 pub struct App {
-    pub repo_root: PathBuf,
+    /// The source as opened; `git` wraps it for comparison modes other than the worktree.
+    pub repo: Arc<dyn GitBackend>,
+    pub git: Arc<dyn GitBackend>,
+    pub compare_mode: CompareMode,
     pub files: Vec<ChangedFile>,
     pub tree_rows: Vec<TreeRow>,
     pub show_tree: bool,
     pub tree_h_scroll: usize,
+    pub tree_v_scroll: usize,
+    pub tree_viewport_rows: usize,
+    pub layout: LayoutSettings,
+    /// Directories whose contents are hidden in the tree.
+    pub collapsed_dirs: HashSet<PathBuf>,
+    /// Selected file the tree last scrolled into view; the tree follows the selection only
+    /// when it changes, so the wheel can look elsewhere.
+    pub revealed_file: Option<usize>,
     pub selected_file_idx: usize,
     pub v_scroll: usize,
     pub h_scroll: usize,
     pub viewport_rows: usize,
+    /// Characters of text per diff line, which long lines wrap at when `wrap` is set.
+    pub text_width: usize,
+    /// Wrap long lines instead of scrolling sideways; `v_scroll` still counts rows.
+    pub wrap: bool,
+    pub drag: Option<Drag>,
     pub highlight_epoch: u64,
-    pub g_prefix_pending: bool,
+    pub limits: LoadLimits,
+    pub highlights: HighlightCache,
+    pub loader: Loader,
+    pub pending_load: Option<LoadTicket>,
+    pub pending_anchor: Option<ScrollAnchor>,
+    pub prefetches: Vec<LoadTicket>,
+    /// Indices of files holding loaded contents, most recently viewed first.
+    pub resident: VecDeque<usize>,
+    pub watcher: Option<RepoWatcher>,
+    /// Apply watcher batches as they arrive; when false they are only noted in `missed_updates`.
+    pub live_updates: bool,
+    pub missed_updates: bool,
+    pub keymap: Keymap,
+    pub theme: Theme,
+    /// What the terminal can show; theme colors are reduced to it when drawing.
+    pub color_depth: ColorDepth,
+    /// The theme from the config file, with its overrides, restored when `:theme` names it.
+    pub configured_theme: Theme,
+    pub pending: PendingInput,
+    /// Open `:` command line, which receives keys instead of the keymap.
+    pub prompt: Option<Prompt>,
+    /// Open `?` overlay; scroll actions move it and other actions are ignored until it closes.
+    pub help: Option<HelpView>,
+    pub filter: Option<FileFilter>,
+    pub open_request: Option<OpenRequest>,
+    /// A running `:export patch`, which reports the message to show when done.
+    pub export: Option<Receiver<Result<String>>>,
+    /// Ctrl-Z: the event loop hands the terminal back and stops the process until `fg`.
+    pub suspend_requested: bool,
+    /// Feedback for the last command, such as a refresh or a `:` error; cleared by the next key.
+    pub message: Option<Message>,
+    /// Where [`App::report`] appends errors; `None` logs nothing.
+    pub log_path: Option<PathBuf>,
     pub should_quit: bool,
 }
 
 impl App {
-    pub fn new(repo_root: PathBuf) -> Result<Self> {
-        let files = git::collect_changed_files(&repo_root)?;
-        let tree = tree::build_tree(&files);
-        let tree_rows = tree::flatten_tree(&tree, &files);
+    pub fn new(
+        git: Arc<dyn GitBackend>,
+        limits: LoadLimits,
+        config: Config,
+        layout: LayoutSettings,
+        color_depth: ColorDepth,
+    ) -> Result<Self> {
+        let files = git.collect_changed_files()?;
+        let tree_rows = visible_tree_rows(&files, None, &HashSet::new());
+        let highlights = HighlightCache::spawn(Arc::clone(&config.theme.syntax));
+        let loader = Loader::spawn(
+            Arc::clone(&git),
+            limits,
+            Some(load_anyway_hint(&config.keymap)),
+        );
+        // Live updates are a convenience; without a watcher `R` still refreshes manually.
+        let watcher = git
+            .git_dir()
+            .and_then(|git_dir| RepoWatcher::spawn(git.repo_root(), &git_dir))
+            .ok();
 
         let mut app = Self {
-            repo_root,
+            repo: Arc::clone(&git),
+            git,
+            compare_mode: CompareMode::default(),
             files,
             tree_rows,
             show_tree: true,
             tree_h_scroll: 0,
+            tree_v_scroll: 0,
+            tree_viewport_rows: 1,
+            layout,
+            collapsed_dirs: HashSet::new(),
+            revealed_file: None,
             selected_file_idx: 0,
             v_scroll: 0,
             h_scroll: 0,
             viewport_rows: 1,
+            text_width: 1,
+            wrap: false,
+            drag: None,
             highlight_epoch: 0,
-            g_prefix_pending: false,
+            limits,
+            highlights,
+            loader,
+            pending_load: None,
+            pending_anchor: None,
+            prefetches: Vec::new(),
+            resident: VecDeque::new(),
+            live_updates: watcher.is_some(),
+            watcher,
+            missed_updates: false,
+            keymap: config.keymap,
+            theme: config.theme.clone(),
+            color_depth,
+            configured_theme: config.theme,
+            pending: PendingInput::default(),
+            prompt: None,
+            help: None,
+            filter: None,
+            open_request: None,
+            export: None,
+            suspend_requested: false,
+            message: None,
+            log_path: logging::log_path(),
             should_quit: false,
         };
 
-        if !app.files.is_empty() {
-            app.ensure_selected_loaded()?;
-        }
+        app.ensure_selected_loaded();
 
         Ok(app)
     }
 
-    pub fn on_action(&mut self, action: Action) -> Result<()> {
-        if matches!(action, Action::PrefixG) {
-            if self.g_prefix_pending {
-                self.go_top();
-                self.g_prefix_pending = false;
-            } else {
-                self.g_prefix_pending = true;
+    pub fn on_key(&mut self, key: KeyEvent) -> Result<()> {
+        self.message = None;
+        if self.prompt.is_some() {
+            self.on_prompt_key(map_prompt_key(key));
+            return Ok(());
+        }
+        if let Some(help) = self.help.as_mut() {
+            if help.searching {
+                self.on_help_search_key(map_prompt_key(key));
+                return Ok(());
             }
-            self.clamp_scroll();
+            if let Some(help_key) = map_help_key(key) {
+                self.on_help_key(help_key, 1);
+                return Ok(());
+            }
+        }
+
+        let chord = KeyChord::from_event(key);
+        if self.accept_count_digit(chord) {
+            return Ok(());
+        }
+
+        self.pending.keys.push(chord);
+        let mut resolution = self.keymap.resolve(&self.pending.keys);
+        // A key that breaks a sequence starts over on its own, so `g j` still scrolls.
+        if resolution == Resolution::Unbound && self.pending.keys.len() > 1 {
+            self.pending.keys = vec![chord];
+            resolution = self.keymap.resolve(&self.pending.keys);
+        }
+
+        match resolution {
+            Resolution::Pending => Ok(()),
+            Resolution::Action(action) => {
+                self.pending.keys.clear();
+                self.on_action(action)
+            }
+            Resolution::Unbound => {
+                self.pending = PendingInput::default();
+                Ok(())
+            }
+        }
+    }
+
+    /// Digits not bound to anything build up a count for the next action; `0` only continues one.
+    fn accept_count_digit(&mut self, chord: KeyChord) -> bool {
+        let KeyCode::Char(digit @ '0'..='9') = chord.code else {
+            return false;
+        };
+        if !chord.modifiers.is_empty()
+            || !self.pending.keys.is_empty()
+            || (digit == '0' && self.pending.count.is_none())
+            || self.keymap.resolve(&[chord]) != Resolution::Unbound
+        {
+            return false;
+        }
+
+        let digit = digit.to_digit(10).unwrap_or(0) as usize;
+        let count = self.pending.count.unwrap_or(0);
+        self.pending.count = Some(
+            count
+                .saturating_mul(10)
+                .saturating_add(digit)
+                .min(MAX_COUNT),
+        );
+        true
+    }
+
+    fn on_prompt_key(&mut self, key: PromptKey) {
+        let Some(prompt) = self.prompt.as_mut() else {
+            return;
+        };
+        match key {
+            PromptKey::Insert(c) => prompt.edit().push(c),
+            // Deleting past the `:` closes the prompt, as in vim.
+            PromptKey::Backspace if prompt.input.is_empty() => self.prompt = None,
+            PromptKey::Backspace => {
+                prompt.edit().pop();
+            }
+            PromptKey::ClearLine => prompt.edit().clear(),
+            PromptKey::Complete | PromptKey::CompletePrev => {
+                let paths: Vec<String> = self
+                    .files
+                    .iter()
+                    .filter(|file| {
+                        self.filter
+                            .as_ref()
+                            .is_none_or(|filter| filter.matches(&file.path))
+                    })
+                    .map(|file| file.path.to_string_lossy().into_owned())
+                    .collect();
+                prompt.cycle_completion(key == PromptKey::Complete, |input| {
+                    command::complete(input, paths.iter().map(String::as_str))
+                });
+            }
+            PromptKey::Cancel => self.prompt = None,
+            PromptKey::Submit => {
+                let input = self.prompt.take().map(|prompt| prompt.input);
+                if let Err(err) = self.run_command_line(input.as_deref().unwrap_or_default()) {
+                    self.message = Some(Message::error(&err));
+                }
+                self.clamp_scroll();
+            }
+            PromptKey::Ignore => {}
+        }
+    }
+
+    fn run_command_line(&mut self, input: &str) -> Result<()> {
+        if input.trim().is_empty() {
             return Ok(());
         }
+        match command::parse(input)? {
+            Command::Action(action) => self.on_action(action),
+            Command::Goto(target) => self.go_to(target),
+            Command::Filter(pattern) => self.set_filter(pattern.as_deref()),
+            Command::Open => self.request_open(),
+            Command::Mode(mode) => self.set_compare_mode(mode),
+            Command::ExportPatch(file) => self.export_patch(file.as_deref().unwrap_or("fdf.patch")),
+            Command::Theme(name) => self.set_theme(&name),
+        }
+    }
 
-        if !matches!(action, Action::None) {
-            self.g_prefix_pending = false;
+    /// Writes the files in the tree to `file` on a background thread, reading each in full.
+    fn export_patch(&mut self, file: &str) -> Result<()> {
+        if self.export.is_some() {
+            bail!("an export is still running");
+        }
+        let files: Vec<ChangedFile> = self
+            .files
+            .iter()
+            .filter(|file| self.passes_filter(file))
+            .map(|file| {
+                let mut copy = ChangedFile::new(file.path.clone(), file.status.clone());
+                copy.renamed_from = file.renamed_from.clone();
+                copy
+            })
+            .collect();
+        if files.is_empty() {
+            bail!("no changed files to export");
         }
 
+        let git = Arc::clone(&self.git);
+        let path = PathBuf::from(file);
+        let (done, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let outcome = export::write_patch(git.as_ref(), files, &path).map(|summary| {
+                let mut text = format!(
+                    "exported {} {} to {}",
+                    summary.files,
+                    if summary.files == 1 { "file" } else { "files" },
+                    path.display()
+                );
+                if summary.skipped > 0 {
+                    text.push_str(&format!(
+                        " ({} binary or known only as hunks left out)",
+                        summary.skipped
+                    ));
+                }
+                text
+            });
+            let _ = done.send(outcome);
+        });
+        self.export = Some(receiver);
+        self.message = Some(Message::info(format!("exporting to {}...", file)));
+        Ok(())
+    }
+
+    /// Lists and loads the files again comparing the sides of `mode`.
+    fn set_compare_mode(&mut self, mode: CompareMode) -> Result<()> {
+        if mode != CompareMode::Worktree {
+            self.repo
+                .git_dir()
+                .with_context(|| format!("mode {} needs a git repository", mode.name()))?;
+        }
+        let git = ModeBackend::wrap(Arc::clone(&self.repo), mode);
+        let files = git.collect_changed_files()?;
+        self.loader = Loader::spawn(
+            Arc::clone(&git),
+            self.limits,
+            Some(load_anyway_hint(&self.keymap)),
+        );
+        self.git = git;
+        self.compare_mode = mode;
+
+        // Loads from the other mode hold other sides, so nothing is carried over.
+        self.apply_refreshed_files(files);
+        self.reset_scroll();
+        self.ensure_selected_loaded();
+        let [left, right] = self.git.side_names();
+        self.message = Some(Message::info(format!(
+            "comparing {}..{}: {} changed {}",
+            left,
+            right,
+            self.files.len(),
+            if self.files.len() == 1 {
+                "file"
+            } else {
+                "files"
+            }
+        )));
+        Ok(())
+    }
+
+    fn set_theme(&mut self, name: &str) -> Result<()> {
+        self.theme = if name == self.configured_theme.name {
+            self.configured_theme.clone()
+        } else {
+            Theme::builtin(name)?
+        };
+        self.highlights.set_theme(Arc::clone(&self.theme.syntax));
+        self.highlight_epoch = self.highlight_epoch.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Shows only files matching `pattern` in the tree (all files for `None`), moving the
+    /// selection to the first shown file if it was filtered out.
+    fn set_filter(&mut self, pattern: Option<&str>) -> Result<()> {
+        let filter = pattern.map(FileFilter::new).transpose()?;
+        if let Some(filter) = &filter {
+            if !self.files.iter().any(|file| filter.matches(&file.path)) {
+                bail!("no changed file matches {}", filter.pattern);
+            }
+        }
+        self.filter = filter;
+        self.rebuild_tree();
+
+        let selected_passes = self
+            .selected_file()
+            .is_some_and(|file| self.passes_filter(file));
+        if !selected_passes {
+            let first = (0..self.files.len()).find(|&idx| self.passes_filter(&self.files[idx]));
+            if let Some(first) = first {
+                self.select_file(first);
+            }
+        }
+        Ok(())
+    }
+
+    fn request_open(&mut self) -> Result<()> {
+        let Some(file) = self.selected_file() else {
+            bail!("no changed files");
+        };
+        let line = self.scroll_anchor().map_or(1, |anchor| anchor.line);
+        self.open_request = Some(OpenRequest {
+            path: file.path.clone(),
+            line,
+        });
+        Ok(())
+    }
+
+    /// Called by the event loop once the editor for `:open` has exited.
+    pub fn finish_open(&mut self, outcome: Result<()>) -> Result<()> {
+        self.report(outcome);
+        // Live updates pick up the edit on their own.
+        if self.watcher.is_none() || !self.live_updates {
+            self.refresh()?;
+        }
+        Ok(())
+    }
+
+    /// Selects the target's file (if given) and scrolls its line to the top, now or once loaded.
+    fn go_to(&mut self, target: GotoTarget) -> Result<()> {
+        if let Some(query) = target.path.as_deref() {
+            let idx = self.find_file(query)?;
+            if idx != self.selected_file_idx {
+                self.select_file(idx);
+            }
+        }
+        let Some(file) = self.selected_file() else {
+            bail!("no changed files");
+        };
+        let loaded = file.is_loaded();
+
+        self.pending_anchor = Some(ScrollAnchor {
+            path: file.path.clone(),
+            side: target.side,
+            line: target.line,
+            follow_edits: false,
+        });
+        if loaded {
+            self.restore_anchor();
+        }
+        Ok(())
+    }
+
+    /// Index of the changed file named by `query`: a repository-relative or absolute path, or
+    /// a unique trailing part of one (`foo.rs` for `src/foo.rs`).
+    fn find_file(&self, query: &str) -> Result<usize> {
+        let query = Path::new(query);
+        let query = query.strip_prefix(self.git.repo_root()).unwrap_or(query);
+        let query = query.strip_prefix(".").unwrap_or(query);
+
+        if let Some(idx) = self.files.iter().position(|file| file.path == query) {
+            return Ok(idx);
+        }
+        let matches: Vec<usize> = (0..self.files.len())
+            .filter(|&idx| self.files[idx].path.ends_with(query))
+            .collect();
+        match matches.as_slice() {
+            [idx] => Ok(*idx),
+            [] => bail!("no changed file matches {}", query.display()),
+            [first, second, ..] => bail!(
+                "{} matches several changed files ({}, {}{})",
+                query.display(),
+                self.files[*first].path.display(),
+                self.files[*second].path.display(),
+                if matches.len() > 2 { ", ..." } else { "" }
+            ),
+        }
+    }
+
+    fn on_help_search_key(&mut self, key: PromptKey) {
+        let Some(help) = self.help.as_mut() else {
+            return;
+        };
+        match key {
+            PromptKey::Insert(c) => help.query.push(c),
+            PromptKey::Backspace => {
+                help.query.pop();
+            }
+            PromptKey::ClearLine => help.query.clear(),
+            PromptKey::Submit => help.searching = false,
+            PromptKey::Cancel => {
+                help.query.clear();
+                help.searching = false;
+            }
+            PromptKey::Complete | PromptKey::CompletePrev | PromptKey::Ignore => {}
+        }
+        help.scroll = 0;
+    }
+
+    fn on_help_key(&mut self, key: HelpKey, times: usize) {
+        let Some(help) = self.help.as_mut() else {
+            return;
+        };
+        let page = (self.viewport_rows / 2).max(1).saturating_mul(times);
+        match key {
+            HelpKey::Close => self.help = None,
+            HelpKey::Search => {
+                help.searching = true;
+                help.query.clear();
+                help.scroll = 0;
+            }
+            HelpKey::ScrollDown => help.scroll = help.scroll.saturating_add(times),
+            HelpKey::ScrollUp => help.scroll = help.scroll.saturating_sub(times),
+            HelpKey::PageDown => help.scroll = help.scroll.saturating_add(page),
+            HelpKey::PageUp => help.scroll = help.scroll.saturating_sub(page),
+            HelpKey::Top => help.scroll = 0,
+            HelpKey::Bottom => help.scroll = usize::MAX,
+        }
+        self.clamp_help_scroll();
+    }
+
+    /// Keymap actions while help is open: motions scroll it, `help`/`quit` close it.
+    fn on_help_action(&mut self, action: Action, times: usize) {
+        let key = match action {
+            Action::Help | Action::Quit => HelpKey::Close,
+            Action::ScrollDown => HelpKey::ScrollDown,
+            Action::ScrollUp => HelpKey::ScrollUp,
+            Action::PageDown => HelpKey::PageDown,
+            Action::PageUp => HelpKey::PageUp,
+            Action::GoTop => HelpKey::Top,
+            Action::GoBottom => HelpKey::Bottom,
+            _ => return,
+        };
+        self.on_help_key(key, times);
+    }
+
+    fn clamp_help_scroll(&mut self) {
+        let Some(help) = self.help.as_mut() else {
+            return;
+        };
+        let lines = help_lines(&self.keymap, &help.query).len();
+        help.scroll = help.scroll.min(lines.saturating_sub(1));
+    }
+
+    /// Runs `action`, consuming any pending count: motions repeat `count` times, while
+    /// `G`/`gg` jump to worktree line `count`. Other actions ignore it.
+    pub fn on_action(&mut self, action: Action) -> Result<()> {
+        let count = self.pending.count.take();
+        let times = count.unwrap_or(1);
+        if self.help.is_some() {
+            self.on_help_action(action, times);
+            return Ok(());
+        }
         match action {
-            Action::SelectPrevFile => self.select_prev_file()?,
-            Action::SelectNextFile => self.select_next_file()?,
+            Action::SelectPrevFile => self.select_file_by(times, false),
+            Action::SelectNextFile => self.select_file_by(times, true),
             Action::ToggleTree => {
                 self.show_tree = !self.show_tree;
             }
-            Action::Refresh => self.refresh()?,
+            Action::ToggleWrap => self.wrap = !self.wrap,
+            Action::ToggleWhitespace => self.toggle_whitespace(),
+            Action::Refresh => {
+                self.refresh()?;
+                self.message = Some(Message::info(format!(
+                    "refreshed: {} changed {}",
+                    self.files.len(),
+                    if self.files.len() == 1 {
+                        "file"
+                    } else {
+                        "files"
+                    }
+                )));
+            }
+            Action::ToggleLiveUpdates => self.toggle_live_updates()?,
+            Action::LoadAnyway => self.load_selected_anyway(),
             Action::TreeScrollLeft => {
-                self.tree_h_scroll = self.tree_h_scroll.saturating_sub(1);
+                self.tree_h_scroll = self.tree_h_scroll.saturating_sub(times);
             }
             Action::TreeScrollRight => {
-                self.tree_h_scroll = self.tree_h_scroll.saturating_add(1);
+                self.tree_h_scroll = self.tree_h_scroll.saturating_add(times);
+            }
+            Action::GrowTree => self.resize(false, true, times),
+            Action::ShrinkTree => self.resize(false, false, times),
+            Action::SplitLeft => self.resize(true, false, times),
+            Action::SplitRight => self.resize(true, true, times),
+            Action::CycleTreePosition => {
+                self.layout.tree_position = self.layout.tree_position.next();
+                self.show_tree = true;
             }
+            Action::ResetLayout => self.layout = LayoutSettings::default(),
             Action::ScrollDown => {
-                self.v_scroll = self.v_scroll.saturating_add(1);
+                self.v_scroll = self.v_scroll.saturating_add(times);
             }
             Action::ScrollUp => {
-                self.v_scroll = self.v_scroll.saturating_sub(1);
+                self.v_scroll = self.v_scroll.saturating_sub(times);
             }
-            Action::PageDown => self.page_down(),
-            Action::PageUp => self.page_up(),
+            Action::PageDown => self.page_down(times),
+            Action::PageUp => self.page_up(times),
             Action::ScrollLeft => {
-                self.h_scroll = self.h_scroll.saturating_sub(1);
+                self.h_scroll = self.h_scroll.saturating_sub(times);
             }
             Action::ScrollRight => {
-                self.h_scroll = self.h_scroll.saturating_add(1);
+                self.h_scroll = self.h_scroll.saturating_add(times);
+            }
+            Action::GoTop | Action::GoBottom if count.is_some() => self.go_to_line(times),
+            Action::GoTop => self.go_top(),
+            Action::GoBottom => self.go_bottom(),
+            Action::NextChange => self.jump_next_change(times),
+            Action::PrevChange => self.jump_prev_change(times),
+            Action::CommandLine => self.prompt = Some(Prompt::default()),
+            Action::Help => self.help = Some(HelpView::default()),
+            Action::Suspend if cfg!(unix) => self.suspend_requested = true,
+            Action::Suspend => bail!("suspending needs job control, which this platform lacks"),
+            Action::Quit => self.should_quit = true,
+            Action::None => {}
+        }
+        self.clamp_scroll();
+        Ok(())
+    }
+
+    pub fn selected_file(&self) -> Option<&ChangedFile> {
+        self.files.get(self.selected_file_idx)
+    }
+
+    pub fn status(&self) -> Status {
+        let file_indices: Vec<usize> = self.tree_rows.iter().filter_map(|r| r.file_index).collect();
+        let file = file_indices
+            .iter()
+            .position(|idx| *idx == self.selected_file_idx)
+            .map(|pos| (pos + 1, file_indices.len()));
+
+        let [left, right] = self.git.side_names();
+        let mut modes = vec![format!("{}..{}", left, right)];
+        if self.loader.options.ignore_whitespace {
+            // Rows taken from a patch's hunks are shown as the patch has them.
+            if self.selected_file().is_some_and(|file| file.hunks_only) {
+                modes.push("ignore whitespace (not in hunks)".to_string());
+            } else {
+                modes.push("ignore whitespace".to_string());
+            }
+        }
+        if self.wrap {
+            modes.push("wrap".to_string());
+        }
+        if let Some(filter) = &self.filter {
+            modes.push(format!("filter {}", filter.pattern));
+        }
+        if self.watcher.is_some() && !self.live_updates {
+            modes.push("paused".to_string());
+        }
+        if self.selected_file().is_some_and(|file| file.degraded) {
+            modes.push("degraded".to_string());
+        }
+
+        let mut status = Status {
+            file,
+            modes,
+            ..Status::default()
+        };
+        let Some(rows) = self.selected_rows() else {
+            return status;
+        };
+
+        let starts = change_block_starts(rows);
+        let current = starts.iter().rposition(|start| *start <= self.v_scroll);
+        status.hunk = Some((current.map(|idx| idx + 1), starts.len()));
+
+        let below = rows.get(self.v_scroll..).unwrap_or_default();
+        status.left_line = below.iter().find_map(|row| row.left_line_no);
+        status.right_line = below.iter().find_map(|row| row.right_line_no);
+
+        let (mut adds, mut dels) = (0, 0);
+        for row in rows {
+            match row.kind {
+                RowKind::Equal => {}
+                RowKind::Insert => adds += 1,
+                RowKind::Delete => dels += 1,
+                RowKind::Changed => {
+                    adds += 1;
+                    dels += 1;
+                }
+            }
+        }
+        status.stats = Some((adds, dels));
+        status
+    }
+
+    pub fn selected_rows(&self) -> Option<&Vec<AlignedRow>> {
+        self.selected_file().and_then(|f| f.aligned_rows.as_ref())
+    }
+
+    /// Called before every frame with the text rows and columns of the diff panes and the
+    /// text rows of the tree.
+    pub fn set_viewport(&mut self, diff_rows: usize, text_width: usize, tree_rows: usize) {
+        self.viewport_rows = diff_rows.max(1);
+        self.text_width = text_width.max(1);
+        self.tree_viewport_rows = tree_rows.max(1);
+        self.clamp_scroll();
+        self.clamp_tree_scroll();
+    }
+
+    pub fn on_mouse(&mut self, input: MouseInput) -> Result<()> {
+        // The command line keeps focus until it is submitted or cancelled.
+        if self.prompt.is_some() {
+            return Ok(());
+        }
+        if input.kind == MouseKind::Press {
+            self.message = None;
+        }
+        if self.help.is_some() {
+            match input.kind {
+                MouseKind::WheelDown => self.on_help_key(HelpKey::ScrollDown, WHEEL_LINES),
+                MouseKind::WheelUp => self.on_help_key(HelpKey::ScrollUp, WHEEL_LINES),
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        let on_tree = matches!(
+            input.target,
+            Some(MouseTarget::Tree | MouseTarget::TreeRow(_))
+        );
+        match input.kind {
+            MouseKind::Release => self.drag = None,
+            MouseKind::Drag => match self.drag {
+                Some(Drag::Scrollbar) => self.scroll_to_track(input.track),
+                Some(Drag::TreeBorder) => self.layout.set_tree_percent(input.tree_percent),
+                Some(Drag::SplitBorder) => self.layout.set_split_percent(input.split_percent),
+                None => {}
+            },
+            MouseKind::Press => match input.target {
+                Some(MouseTarget::TreeRow(row)) => self.click_tree_row(row),
+                Some(MouseTarget::Scrollbar) => {
+                    self.drag = Some(Drag::Scrollbar);
+                    self.scroll_to_track(input.track);
+                }
+                Some(MouseTarget::TreeBorder) => self.drag = Some(Drag::TreeBorder),
+                Some(MouseTarget::SplitBorder) => self.drag = Some(Drag::SplitBorder),
+                _ => {}
+            },
+            MouseKind::WheelDown if on_tree => {
+                self.tree_v_scroll = self.tree_v_scroll.saturating_add(WHEEL_LINES);
+            }
+            MouseKind::WheelUp if on_tree => {
+                self.tree_v_scroll = self.tree_v_scroll.saturating_sub(WHEEL_LINES);
+            }
+            MouseKind::WheelLeft if on_tree => {
+                self.tree_h_scroll = self.tree_h_scroll.saturating_sub(WHEEL_COLUMNS);
+            }
+            MouseKind::WheelRight if on_tree => {
+                self.tree_h_scroll = self.tree_h_scroll.saturating_add(WHEEL_COLUMNS);
+            }
+            _ if input.target.is_none() => {}
+            MouseKind::WheelDown => self.v_scroll = self.v_scroll.saturating_add(WHEEL_LINES),
+            MouseKind::WheelUp => self.v_scroll = self.v_scroll.saturating_sub(WHEEL_LINES),
+            MouseKind::WheelLeft => self.h_scroll = self.h_scroll.saturating_sub(WHEEL_COLUMNS),
+            MouseKind::WheelRight => self.h_scroll = self.h_scroll.saturating_add(WHEEL_COLUMNS),
+        }
+        self.clamp_scroll();
+        self.clamp_tree_scroll();
+        Ok(())
+    }
+
+    /// Selects a clicked file, or collapses/expands a clicked directory.
+    fn click_tree_row(&mut self, row: usize) {
+        let Some(row) = self.tree_rows.get(row) else {
+            return;
+        };
+        if let Some(idx) = row.file_index {
+            if idx != self.selected_file_idx {
+                self.select_file(idx);
+            }
+        } else if row.is_dir {
+            let path = row.path.clone();
+            if !self.collapsed_dirs.remove(&path) {
+                self.collapsed_dirs.insert(path);
+            }
+            self.rebuild_tree();
+        }
+    }
+
+    /// Scrolls so the rows at the pointer's place in the scrollbar are centered in the view.
+    fn scroll_to_track(&mut self, (pos, height): (usize, usize)) {
+        let total = self.selected_rows().map_or(0, Vec::len);
+        if height == 0 {
+            return;
+        }
+        let row = pos.min(height).saturating_mul(total) / height;
+        self.v_scroll = row.saturating_sub(self.viewport_rows / 2);
+    }
+
+    /// Grows or shrinks the tree (or the `HEAD` pane, for `split`) by `times` resize steps.
+    fn resize(&mut self, split: bool, grow: bool, times: usize) {
+        let delta = RESIZE_STEP.saturating_mul(times.min(100) as u16);
+        let apply = |percent: u16| {
+            if grow {
+                percent.saturating_add(delta)
+            } else {
+                percent.saturating_sub(delta)
+            }
+        };
+        if split {
+            self.layout
+                .set_split_percent(apply(self.layout.split_percent));
+        } else {
+            self.layout
+                .set_tree_percent(apply(self.layout.tree_percent));
+        }
+    }
+
+    /// True while the selected file is being loaded and diffed in the background.
+    pub fn is_loading(&self) -> bool {
+        self.pending_load
+            .as_ref()
+            .is_some_and(|ticket| ticket.file_idx == self.selected_file_idx)
+    }
+
+    /// Applies watcher batches and finished background loads. Results for superseded
+    /// requests are dropped, and a failed load of the selected file is shown in its place.
+    pub fn poll_background(&mut self) {
+        if let Some(outcome) = self.export.as_ref().and_then(|done| done.try_recv().ok()) {
+            self.export = None;
+            match outcome {
+                Ok(text) => self.message = Some(Message::info(text)),
+                Err(err) => self.report(Err(err.context("export failed"))),
+            }
+        }
+
+        if let Some(changes) = self.watcher.as_ref().and_then(RepoWatcher::try_recv) {
+            if self.live_updates {
+                let result = self.refresh_from_watch(&changes);
+                self.report(result);
+            } else {
+                self.missed_updates = true;
+            }
+        }
+
+        while let Some(result) = self.loader.try_recv() {
+            let selected = self
+                .pending_load
+                .as_ref()
+                .is_some_and(|ticket| ticket.generation == result.generation);
+            let prefetched = self
+                .prefetches
+                .iter()
+                .position(|ticket| ticket.generation == result.generation);
+
+            let mut file = if selected {
+                let Some(ticket) = self.pending_load.take() else {
+                    continue;
+                };
+                match result.outcome {
+                    Ok(file) => file,
+                    Err(err) => {
+                        self.fail_load(&ticket, err);
+                        continue;
+                    }
+                }
+            } else if let Some(pos) = prefetched {
+                self.prefetches.swap_remove(pos);
+                // A failed prefetch is retried, and reported, once the file is selected.
+                match result.outcome {
+                    Ok(file) => file,
+                    Err(_) => continue,
+                }
+            } else {
+                continue;
+            };
+            file.load_error = None;
+
+            let Some(slot) = self
+                .files
+                .get_mut(result.file_idx)
+                .filter(|slot| slot.path == file.path)
+            else {
+                continue;
+            };
+            *slot = file;
+
+            if result.file_idx == self.selected_file_idx {
+                self.touch_resident(result.file_idx);
+                self.restore_anchor();
+                self.prewarm_selected();
+            } else {
+                self.resident
+                    .retain(|resident| *resident != result.file_idx);
+                self.resident.push_back(result.file_idx);
+            }
+            self.evict_over_budget();
+            self.clamp_scroll();
+        }
+    }
+
+    /// Keeps the error on the file, where the diff pane shows it; selecting the file again or
+    /// refreshing retries the load.
+    fn fail_load(&mut self, ticket: &LoadTicket, err: anyhow::Error) {
+        let err = err.context(format!("failed to load {}", ticket.path.display()));
+        if let Some(file) = self
+            .files
+            .get_mut(ticket.file_idx)
+            .filter(|file| file.path == ticket.path)
+        {
+            file.load_error = Some(format!("{:#}", err));
+        }
+        self.report(Err(err));
+    }
+
+    /// Turns a failed action into a status bar message and a log entry, so one bad file or
+    /// git call does not end the session.
+    pub fn report(&mut self, result: Result<()>) {
+        if let Err(err) = result {
+            if let Some(path) = &self.log_path {
+                logging::log_error(path, &err);
+            }
+            self.message = Some(Message::error(&err));
+        }
+    }
+
+    fn select_file_by(&mut self, steps: usize, forward: bool) {
+        if let Some(idx) = self.file_steps_away(steps, forward) {
+            self.select_file(idx);
+        }
+    }
+
+    /// Switches to file `idx`, expanding any collapsed directory that hides it.
+    fn select_file(&mut self, idx: usize) {
+        self.selected_file_idx = idx;
+        if let Some(path) = self.files.get(idx).map(|file| file.path.clone()) {
+            let before = self.collapsed_dirs.len();
+            self.collapsed_dirs.retain(|dir| !path.starts_with(dir));
+            if self.collapsed_dirs.len() != before {
+                self.rebuild_tree();
+            }
+        }
+        self.reset_scroll();
+        self.ensure_selected_loaded();
+    }
+
+    fn passes_filter(&self, file: &ChangedFile) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches(&file.path))
+    }
+
+    fn rebuild_tree(&mut self) {
+        self.tree_rows = visible_tree_rows(&self.files, self.filter.as_ref(), &self.collapsed_dirs);
+        self.clamp_tree_scroll();
+    }
+
+    /// File index after (or before) the selected one in tree order, wrapping around.
+    fn neighbor_file(&self, forward: bool) -> Option<usize> {
+        self.file_steps_away(1, forward)
+    }
+
+    /// File index `steps` files after (or before) the selected one in tree order, wrapping around.
+    fn file_steps_away(&self, steps: usize, forward: bool) -> Option<usize> {
+        let file_indices: Vec<usize> = self.tree_rows.iter().filter_map(|r| r.file_index).collect();
+        if self.files.is_empty() || file_indices.is_empty() {
+            return None;
+        }
+
+        let current_pos = file_indices
+            .iter()
+            .position(|&idx| idx == self.selected_file_idx);
+
+        let len = file_indices.len();
+        let steps = steps % len;
+        let new_pos = match (current_pos, forward) {
+            (Some(pos), true) => (pos + steps) % len,
+            (Some(pos), false) => (pos + len - steps) % len,
+            (None, true) => (steps + len - 1) % len,
+            (None, false) => (len - steps) % len,
+        };
+
+        Some(file_indices[new_pos])
+    }
+
+    /// Requests a background load of the selected file unless it is loaded or already queued,
+    /// then prefetches its neighbors.
+    fn ensure_selected_loaded(&mut self) {
+        let idx = self.selected_file_idx;
+        let Some(file) = self.files.get(idx) else {
+            return;
+        };
+
+        let already_queued = self
+            .pending_load
+            .as_ref()
+            .is_some_and(|ticket| ticket.file_idx == idx && ticket.path == file.path);
+        if !already_queued {
+            // The previous selection is likely a neighbor; its load is kept as a prefetch
+            // and cancelled by `prefetch_neighbors` otherwise.
+            if let Some(previous) = self.pending_load.take() {
+                self.prefetches.push(previous);
+            }
+
+            if file.is_loaded() {
+                self.touch_resident(idx);
+                self.prewarm_selected();
+            } else if let Some(pos) = self
+                .prefetches
+                .iter()
+                .position(|ticket| ticket.file_idx == idx && ticket.path == file.path)
+            {
+                self.pending_load = Some(self.prefetches.swap_remove(pos));
+            } else {
+                self.pending_load = Some(self.loader.request(idx, file));
+            }
+        }
+
+        self.prefetch_neighbors();
+        self.clamp_scroll();
+    }
+
+    /// Loads the files before and after the selection in the background so stepping
+    /// through them does not wait on git and diffing.
+    fn prefetch_neighbors(&mut self) {
+        let neighbors: Vec<usize> = [self.neighbor_file(false), self.neighbor_file(true)]
+            .into_iter()
+            .flatten()
+            .filter(|idx| *idx != self.selected_file_idx)
+            .collect();
+
+        self.prefetches.retain(|ticket| {
+            let keep = neighbors.contains(&ticket.file_idx);
+            if !keep {
+                ticket.cancel();
+            }
+            keep
+        });
+
+        for idx in neighbors {
+            let file = &self.files[idx];
+            let queued = self.prefetches.iter().any(|ticket| ticket.file_idx == idx);
+            if !file.is_loaded() && !queued {
+                let ticket = self.loader.prefetch(idx, file);
+                self.prefetches.push(ticket);
+            }
+        }
+    }
+
+    fn prewarm_selected(&self) {
+        if let Some(file) = self.selected_file().filter(|file| !file.degraded) {
+            if let Some(rows) = file.aligned_rows.as_ref() {
+                self.highlights.prewarm(
+                    &file.path,
+                    file.content_hash.unwrap_or_default(),
+                    rows,
+                    self.highlight_epoch,
+                );
+            }
+        }
+    }
+
+    fn touch_resident(&mut self, idx: usize) {
+        self.resident.retain(|resident| *resident != idx);
+        self.resident.push_front(idx);
+    }
+
+    /// Unloads the least recently viewed files until the cache fits its budget. The selected
+    /// file is never evicted.
+    fn evict_over_budget(&mut self) {
+        let budget = self.limits.max_cached_bytes;
+        let mut total: u64 = self
+            .resident
+            .iter()
+            .filter_map(|idx| self.files.get(*idx))
+            .map(ChangedFile::resident_bytes)
+            .sum();
+
+        let mut pos = self.resident.len();
+        while total > budget && pos > 0 {
+            pos -= 1;
+            let idx = self.resident[pos];
+            if idx == self.selected_file_idx {
+                continue;
+            }
+
+            self.resident.remove(pos);
+            if let Some(file) = self.files.get_mut(idx) {
+                total = total.saturating_sub(file.resident_bytes());
+                file.unload();
             }
-            Action::GoBottom => self.go_bottom(),
-            Action::NextChange => self.jump_next_change(),
-            Action::PrevChange => self.jump_prev_change(),
-            Action::Quit => self.should_quit = true,
-            Action::None => {}
-            Action::PrefixG => {}
         }
-        self.clamp_scroll();
-        Ok(())
     }
 
-    pub fn selected_file(&self) -> Option<&ChangedFile> {
-        self.files.get(self.selected_file_idx)
+    fn load_selected_anyway(&mut self) {
+        let Some(file) = self.files.get_mut(self.selected_file_idx) else {
+            return;
+        };
+        let too_large = matches!(file.new_content, Some(ContentData::TooLarge { .. }));
+        if !too_large {
+            return;
+        }
+
+        file.force_load = true;
+        file.old_content = None;
+        file.new_content = None;
+        file.aligned_rows = None;
+        self.ensure_selected_loaded();
     }
 
-    pub fn selected_rows(&self) -> Option<&Vec<AlignedRow>> {
-        self.selected_file().and_then(|f| f.aligned_rows.as_ref())
+    fn refresh(&mut self) -> Result<()> {
+        let files = self.git.collect_changed_files()?;
+        self.apply_refresh(files, |_| true);
+        Ok(())
     }
 
-    pub fn set_viewport_rows(&mut self, rows: usize) {
-        self.viewport_rows = rows.max(1);
-        self.clamp_scroll();
+    /// Refreshes after a watcher batch, keeping loaded contents of files it did not touch.
+    fn refresh_from_watch(&mut self, changes: &Changes) -> Result<()> {
+        let files = self.git.collect_changed_files()?;
+        self.apply_watch_changes(files, changes);
+        Ok(())
     }
 
-    fn select_prev_file(&mut self) -> Result<()> {
-        if self.files.is_empty() {
-            return Ok(());
+    fn apply_watch_changes(&mut self, files: Vec<ChangedFile>, changes: &Changes) {
+        // A moved `HEAD` can change the left side of every file; outside the worktree mode the
+        // index is one of the sides too.
+        let index_is_a_side = self.compare_mode != CompareMode::Worktree;
+        if changes.head_changed || (changes.index_changed && index_is_a_side) {
+            self.apply_refresh(files, |_| true);
+        } else if changes.paths.is_empty() && changes.index_changed {
+            self.apply_index_change(files);
+        } else {
+            self.apply_refresh(files, |path| {
+                changes
+                    .paths
+                    .iter()
+                    .any(|touched| path.starts_with(touched))
+            });
         }
+    }
 
-        let file_indices: Vec<usize> = self.tree_rows.iter().filter_map(|r| r.file_index).collect();
-
-        if file_indices.is_empty() {
-            return Ok(());
+    /// Staging or unstaging changes the status indicators but not either side's contents, so
+    /// when the same files are listed their statuses are updated in place and loads go on.
+    fn apply_index_change(&mut self, files: Vec<ChangedFile>) {
+        let same_files = files.len() == self.files.len()
+            && files
+                .iter()
+                .zip(&self.files)
+                .all(|(fresh, current)| fresh.path == current.path);
+        if !same_files {
+            self.apply_refresh(files, |_| false);
+            return;
         }
 
-        let current_pos = file_indices
-            .iter()
-            .position(|&idx| idx == self.selected_file_idx);
+        for (current, fresh) in self.files.iter_mut().zip(files) {
+            current.status = fresh.status;
+        }
+        self.tree_rows = visible_tree_rows(&self.files, self.filter.as_ref(), &self.collapsed_dirs);
+    }
 
-        let new_pos = match current_pos {
-            Some(0) => file_indices.len() - 1,
-            Some(pos) => pos - 1,
-            None => file_indices.len() - 1,
-        };
+    fn apply_refresh(&mut self, mut files: Vec<ChangedFile>, is_touched: impl Fn(&Path) -> bool) {
+        let anchor = self.scroll_anchor();
+        self.carry_over_loaded(&mut files, is_touched);
+        self.apply_refreshed_files(files);
 
-        self.selected_file_idx = file_indices[new_pos];
-        self.reset_scroll();
-        self.ensure_selected_loaded()
+        // Carried-over files keep their rows, so only a reload needs re-anchoring.
+        self.pending_anchor = anchor.filter(|anchor| {
+            self.selected_file()
+                .is_some_and(|file| file.path == anchor.path && !file.is_loaded())
+        });
+        self.ensure_selected_loaded();
     }
 
-    fn select_next_file(&mut self) -> Result<()> {
-        if self.files.is_empty() {
-            return Ok(());
-        }
-
-        let file_indices: Vec<usize> = self.tree_rows.iter().filter_map(|r| r.file_index).collect();
+    /// Moves loaded state from the current files into their refreshed counterparts: untouched
+    /// files keep it as is, touched ones keep it as `previous` for comparison on reload.
+    fn carry_over_loaded(&mut self, files: &mut [ChangedFile], is_touched: impl Fn(&Path) -> bool) {
+        let mut resident: HashMap<PathBuf, &mut ChangedFile> = self
+            .files
+            .iter_mut()
+            .filter(|file| file.is_resident())
+            .map(|file| (file.path.clone(), file))
+            .collect();
+
+        for file in files {
+            let Some(previous) = resident.remove(&file.path) else {
+                continue;
+            };
 
-        if file_indices.is_empty() {
-            return Ok(());
+            if previous.is_loaded() && !is_touched(&file.path) {
+                let status = file.status.clone();
+                // The placeholder keeps the path so LRU order can be carried over too.
+                let placeholder = ChangedFile::new(file.path.clone(), FileStatus::default());
+                *file = std::mem::replace(previous, placeholder);
+                file.status = status;
+            } else {
+                previous.stash_for_reload();
+                file.previous = previous.previous.take();
+                file.force_load = previous.force_load;
+            }
         }
+    }
 
-        let current_pos = file_indices
+    /// The worktree line at the top of the viewport, if the selected file has one.
+    fn scroll_anchor(&self) -> Option<ScrollAnchor> {
+        let file = self.selected_file()?;
+        let rows = file.aligned_rows.as_ref()?;
+        let line = rows
             .iter()
-            .position(|&idx| idx == self.selected_file_idx);
+            .skip(self.v_scroll)
+            .find_map(|row| row.right_line_no)?;
+        Some(ScrollAnchor {
+            path: file.path.clone(),
+            side: Side::Right,
+            line,
+            follow_edits: true,
+        })
+    }
 
-        let new_pos = match current_pos {
-            Some(pos) => (pos + 1) % file_indices.len(),
-            None => 0,
+    /// Scrolls the selected file to the pending anchor's line, if it is for this file.
+    fn restore_anchor(&mut self) {
+        let Some(anchor) = self.pending_anchor.take() else {
+            return;
+        };
+        let Some(file) = self.selected_file().filter(|file| file.path == anchor.path) else {
+            return;
+        };
+        let Some(rows) = file.aligned_rows.as_ref() else {
+            return;
         };
 
-        self.selected_file_idx = file_indices[new_pos];
-        self.reset_scroll();
-        self.ensure_selected_loaded()
+        let line = match file.line_remap.as_ref() {
+            Some(remap) if anchor.follow_edits && anchor.side == Side::Right => {
+                remap.map(anchor.line)
+            }
+            _ => anchor.line,
+        };
+        if let Some(row) = row_for_line(rows, anchor.side, line) {
+            self.v_scroll = row;
+        } else if !anchor.follow_edits {
+            // Past the end of that side: show the end rather than staying put.
+            self.v_scroll = rows.len();
+        }
     }
 
-    fn ensure_selected_loaded(&mut self) -> Result<()> {
-        if self.files.is_empty() {
-            return Ok(());
+    /// Rows depend on how lines are compared, so every load is dropped and the selected
+    /// file aligned again, keeping the worktree line at the top.
+    fn toggle_whitespace(&mut self) {
+        self.loader.options.ignore_whitespace = !self.loader.options.ignore_whitespace;
+        let anchor = self.scroll_anchor();
+        if let Some(stale) = self.pending_load.take() {
+            stale.cancel();
         }
-
-        let idx = self.selected_file_idx;
-        let file = &mut self.files[idx];
-
-        if file.old_content.is_none() || file.new_content.is_none() {
-            git::load_file_contents(&self.repo_root, file)?;
+        for ticket in self.prefetches.drain(..) {
+            ticket.cancel();
         }
+        for file in &mut self.files {
+            file.unload();
+        }
+        self.resident.clear();
+        // Same contents, other rows: cached highlights no longer line up.
+        self.highlight_epoch = self.highlight_epoch.wrapping_add(1);
+        self.pending_anchor = anchor;
+        self.ensure_selected_loaded();
+    }
 
-        if file.aligned_rows.is_none() {
-            let rows = match (&file.old_content, &file.new_content) {
-                (Some(ContentData::Text(old)), Some(ContentData::Text(new))) => {
-                    align_full_file(old, new)
-                }
-                _ => vec![AlignedRow {
-                    left_line_no: None,
-                    right_line_no: None,
-                    left_text: "[binary or non-utf8 file]".to_string(),
-                    right_text: "[binary or non-utf8 file]".to_string(),
-                    kind: crate::model::RowKind::Changed,
-                }],
-            };
-            file.aligned_rows = Some(rows);
+    fn toggle_live_updates(&mut self) -> Result<()> {
+        if self.watcher.is_none() {
+            return Ok(());
         }
 
-        self.clamp_scroll();
+        self.live_updates = !self.live_updates;
+        self.message = Some(Message::info(if self.live_updates {
+            "live updates on"
+        } else {
+            "live updates paused"
+        }));
+        if self.live_updates && self.missed_updates {
+            self.missed_updates = false;
+            self.refresh()?;
+        }
         Ok(())
     }
 
-    fn refresh(&mut self) -> Result<()> {
-        let files = git::collect_changed_files(&self.repo_root)?;
-        self.apply_refreshed_files(files);
-        self.ensure_selected_loaded()
-    }
-
     fn apply_refreshed_files(&mut self, files: Vec<ChangedFile>) {
         let previous_selected_path = self.selected_file().map(|file| file.path.clone());
         let previous_selected_idx = self.selected_file_idx;
+        let resident_paths: Vec<PathBuf> = self
+            .resident
+            .iter()
+            .filter_map(|idx| self.files.get(*idx))
+            .map(|file| file.path.clone())
+            .collect();
 
-        let tree = tree::build_tree(&files);
-        let tree_rows = tree::flatten_tree(&tree, &files);
+        let tree_rows = visible_tree_rows(&files, self.filter.as_ref(), &self.collapsed_dirs);
 
+        if let Some(stale) = self.pending_load.take() {
+            stale.cancel();
+        }
+        for ticket in self.prefetches.drain(..) {
+            ticket.cancel();
+        }
         self.files = files;
         self.tree_rows = tree_rows;
-        self.highlight_epoch = self.highlight_epoch.wrapping_add(1);
+        self.resident = resident_paths
+            .iter()
+            .filter_map(|path| {
+                self.files
+                    .iter()
+                    .position(|file| &file.path == path && file.is_resident())
+            })
+            .collect();
 
         if self.files.is_empty() {
             self.selected_file_idx = 0;
@@ -244,16 +1451,17 @@ impl App {
     fn reset_scroll(&mut self) {
         self.v_scroll = 0;
         self.h_scroll = 0;
+        self.pending_anchor = None;
     }
 
-    fn page_down(&mut self) {
+    fn page_down(&mut self, pages: usize) {
         let step = (self.viewport_rows / 2).max(1);
-        self.v_scroll = self.v_scroll.saturating_add(step);
+        self.v_scroll = self.v_scroll.saturating_add(step.saturating_mul(pages));
     }
 
-    fn page_up(&mut self) {
+    fn page_up(&mut self, pages: usize) {
         let step = (self.viewport_rows / 2).max(1);
-        self.v_scroll = self.v_scroll.saturating_sub(step);
+        self.v_scroll = self.v_scroll.saturating_sub(step.saturating_mul(pages));
     }
 
     fn go_top(&mut self) {
@@ -264,7 +1472,15 @@ impl App {
         self.v_scroll = self.max_v_scroll();
     }
 
-    fn jump_next_change(&mut self) {
+    /// Scrolls worktree line `line` to the top, or the nearest line after it.
+    fn go_to_line(&mut self, line: usize) {
+        let Some(rows) = self.selected_rows() else {
+            return;
+        };
+        self.v_scroll = row_for_line(rows, Side::Right, line).unwrap_or(rows.len());
+    }
+
+    fn jump_next_change(&mut self, times: usize) {
         let Some(rows) = self.selected_rows() else {
             return;
         };
@@ -273,14 +1489,17 @@ impl App {
             return;
         }
 
-        if let Some(next) = starts.iter().copied().find(|idx| *idx > self.v_scroll) {
-            self.v_scroll = next;
-        } else if let Some(first) = starts.first().copied() {
-            self.v_scroll = first;
+        // Going around more than once lands on the same blocks again.
+        for _ in 0..times.min(starts.len()) {
+            if let Some(next) = starts.iter().copied().find(|idx| *idx > self.v_scroll) {
+                self.v_scroll = next;
+            } else if let Some(first) = starts.first().copied() {
+                self.v_scroll = first;
+            }
         }
     }
 
-    fn jump_prev_change(&mut self) {
+    fn jump_prev_change(&mut self, times: usize) {
         let Some(rows) = self.selected_rows() else {
             return;
         };
@@ -289,27 +1508,87 @@ impl App {
             return;
         }
 
-        if let Some(prev) = starts
-            .iter()
-            .copied()
-            .rev()
-            .find(|idx| *idx < self.v_scroll)
-        {
-            self.v_scroll = prev;
-        } else if let Some(last) = starts.last().copied() {
-            self.v_scroll = last;
+        for _ in 0..times.min(starts.len()) {
+            if let Some(prev) = starts
+                .iter()
+                .copied()
+                .rev()
+                .find(|idx| *idx < self.v_scroll)
+            {
+                self.v_scroll = prev;
+            } else if let Some(last) = starts.last().copied() {
+                self.v_scroll = last;
+            }
         }
     }
 
     fn clamp_scroll(&mut self) {
+        // Keep the offset while content is loading so a reload does not jump to the top.
+        if self.selected_rows().is_none() {
+            return;
+        }
         let max_scroll = self.max_v_scroll();
         self.v_scroll = self.v_scroll.min(max_scroll);
     }
 
+    /// First row of the last screenful; wrapped rows may take several lines each.
     fn max_v_scroll(&self) -> usize {
-        let total_rows = self.selected_rows().map(|r| r.len()).unwrap_or(0);
-        total_rows.saturating_sub(self.viewport_rows)
+        let Some(rows) = self.selected_rows() else {
+            return 0;
+        };
+        if !self.wrap {
+            return rows.len().saturating_sub(self.viewport_rows);
+        }
+        let mut lines = 0;
+        for (idx, row) in rows.iter().enumerate().rev() {
+            lines += row.wrapped_height(self.text_width);
+            if lines > self.viewport_rows {
+                return (idx + 1).min(rows.len() - 1);
+            }
+        }
+        0
     }
+
+    /// Brings a newly selected file's row into view, then keeps the offset within the rows.
+    fn clamp_tree_scroll(&mut self) {
+        if self.revealed_file != Some(self.selected_file_idx) {
+            let row = self
+                .tree_rows
+                .iter()
+                .position(|row| row.file_index == Some(self.selected_file_idx));
+            if let Some(row) = row {
+                if row < self.tree_v_scroll {
+                    self.tree_v_scroll = row;
+                } else if row >= self.tree_v_scroll + self.tree_viewport_rows {
+                    self.tree_v_scroll = row + 1 - self.tree_viewport_rows;
+                }
+                self.revealed_file = Some(self.selected_file_idx);
+            }
+        }
+        let max_scroll = self.tree_rows.len().saturating_sub(self.tree_viewport_rows);
+        self.tree_v_scroll = self.tree_v_scroll.min(max_scroll);
+    }
+}
+
+fn visible_tree_rows(
+    files: &[ChangedFile],
+    filter: Option<&FileFilter>,
+    collapsed: &HashSet<PathBuf>,
+) -> Vec<TreeRow> {
+    let tree = tree::build_tree(files, |file| {
+        filter.is_none_or(|filter| filter.matches(&file.path))
+    });
+    tree::flatten_tree(&tree, files, collapsed)
+}
+
+/// How the placeholder of a file over the size limit says to load it anyway: the shortest
+/// bound key, or the command when nothing is bound.
+fn load_anyway_hint(keymap: &Keymap) -> String {
+    let how = match keymap.sequences_for(Action::LoadAnyway).first() {
+        Some(keys) => format!("press {}", keys),
+        None => "run :load_anyway".to_string(),
+    };
+    format!("{} to load anyway without syntax highlighting", how)
 }
 
 fn change_block_starts(rows: &[AlignedRow]) -> Vec<usize> {
@@ -317,7 +1596,7 @@ fn change_block_starts(rows: &[AlignedRow]) -> Vec<usize> {
     let mut in_change = false;
 
     for (idx, row) in rows.iter().enumerate() {
-        if row.kind != crate::model::RowKind::Equal {
+        if row.kind != RowKind::Equal {
             if !in_change {
                 starts.push(idx);
             }
@@ -332,25 +1611,76 @@ fn change_block_starts(rows: &[AlignedRow]) -> Vec<usize> {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::{HashSet, VecDeque};
     use std::path::PathBuf;
-
-    use super::App;
-    use crate::input::Action;
-    use crate::model::{ChangedFile, FileStatus};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use ratatui::style::Color;
+
+    use super::{load_anyway_hint, visible_tree_rows, App, PendingInput};
+    use crate::command::GotoTarget;
+    use crate::diff::{align_full_file, line_remap};
+    use crate::git::CliBackend;
+    use crate::highlight::HighlightCache;
+    use crate::input::{Action, Keymap, MouseInput, MouseKind, MouseTarget};
+    use crate::layout::{LayoutSettings, TreePosition};
+    use crate::mode::CompareMode;
+    use crate::model::{content_hash, ChangedFile, ContentData, FileStatus, LoadLimits, Side};
+    use crate::theme::{ColorDepth, Theme};
+    use crate::watch::Changes;
+    use crate::worker::Loader;
 
     fn app_for_test() -> App {
         App {
-            repo_root: PathBuf::new(),
+            repo: Arc::new(CliBackend::new(PathBuf::new())),
+            git: Arc::new(CliBackend::new(PathBuf::new())),
+            compare_mode: CompareMode::default(),
             files: Vec::new(),
             tree_rows: Vec::new(),
             show_tree: true,
             tree_h_scroll: 0,
+            tree_v_scroll: 0,
+            tree_viewport_rows: 1,
+            layout: LayoutSettings::default(),
+            collapsed_dirs: HashSet::new(),
+            revealed_file: None,
             selected_file_idx: 0,
             v_scroll: 0,
             h_scroll: 0,
             viewport_rows: 1,
+            text_width: 1,
+            wrap: false,
+            drag: None,
             highlight_epoch: 0,
-            g_prefix_pending: false,
+            limits: LoadLimits::default(),
+            highlights: HighlightCache::spawn(Arc::clone(&Theme::default().syntax)),
+            loader: Loader::spawn(
+                Arc::new(CliBackend::new(PathBuf::new())),
+                LoadLimits::default(),
+                None,
+            ),
+            pending_load: None,
+            pending_anchor: None,
+            prefetches: Vec::new(),
+            resident: VecDeque::new(),
+            watcher: None,
+            live_updates: false,
+            missed_updates: false,
+            keymap: Keymap::default(),
+            theme: Theme::default(),
+            color_depth: ColorDepth::TrueColor,
+            configured_theme: Theme::default(),
+            pending: PendingInput::default(),
+            prompt: None,
+            help: None,
+            filter: None,
+            open_request: None,
+            export: None,
+            suspend_requested: false,
+            message: None,
+            log_path: None,
             should_quit: false,
         }
     }
@@ -362,6 +1692,7 @@ mod tests {
                 staged: false,
                 unstaged: true,
                 untracked: false,
+                ..FileStatus::default()
             },
         )
     }
@@ -398,6 +1729,325 @@ mod tests {
         assert_eq!(app.tree_h_scroll, 0);
     }
 
+    #[test]
+    fn key_sequences_wait_for_completion_and_restart_on_a_stray_key() {
+        let mut app = app_for_test();
+        let text: String = (0..50).map(|n| format!("line {}\n", n)).collect();
+        app.files = vec![loaded_file("a.rs", &text)];
+        app.viewport_rows = 5;
+        app.v_scroll = 20;
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+
+        app.on_key(key('g')).expect("prefix key should succeed");
+        assert_eq!(app.v_scroll, 20);
+        assert_eq!(app.pending.keys.len(), 1);
+
+        app.on_key(key('k')).expect("stray key should succeed");
+        assert_eq!(app.v_scroll, 19);
+        assert!(app.pending.is_empty());
+
+        app.on_key(key('g')).expect("prefix key should succeed");
+        app.on_key(key('g')).expect("sequence should succeed");
+        assert_eq!(app.v_scroll, 0);
+    }
+
+    #[test]
+    fn count_prefix_repeats_motions_and_jumps_to_a_line() {
+        let mut app = app_for_test();
+        let text: String = (0..50).map(|n| format!("line {}\n", n)).collect();
+        app.files = vec![loaded_file("a.rs", &text)];
+        app.viewport_rows = 5;
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+
+        for c in ['1', '0', 'j'] {
+            app.on_key(key(c)).expect("counted motion should succeed");
+        }
+        assert_eq!(app.v_scroll, 10);
+        assert!(app.pending.is_empty());
+
+        app.on_key(key('4')).expect("digit should succeed");
+        app.on_key(key('2')).expect("digit should succeed");
+        assert_eq!(app.pending.describe(), "42");
+        app.on_key(key('G')).expect("line jump should succeed");
+        assert_eq!(app.v_scroll, 41);
+
+        app.on_key(key('0')).expect("leading zero is not a count");
+        assert!(app.pending.is_empty());
+        for c in ['3', 'g'] {
+            app.on_key(key(c)).expect("partial sequence should succeed");
+        }
+        assert_eq!(app.pending.describe(), "3g");
+        app.on_key(key('g')).expect("line jump should succeed");
+        assert_eq!(app.v_scroll, 2);
+    }
+
+    #[test]
+    fn count_prefix_selects_files_several_steps_away() {
+        let mut app = app_for_test();
+        app.files = ["a.rs", "b.rs", "c.rs", "d.rs"]
+            .into_iter()
+            .map(changed_file)
+            .collect();
+        tree_rows_for(&mut app);
+
+        app.pending.count = Some(3);
+        app.on_action(Action::SelectNextFile)
+            .expect("counted selection should succeed");
+        assert_eq!(app.selected_file().unwrap().path, PathBuf::from("d.rs"));
+
+        app.pending.count = Some(6);
+        app.on_action(Action::SelectPrevFile)
+            .expect("counted selection should wrap");
+        assert_eq!(app.selected_file().unwrap().path, PathBuf::from("b.rs"));
+    }
+
+    #[test]
+    fn command_line_jumps_to_a_line_on_either_side_of_another_file() {
+        let mut app = app_for_test();
+        let old: String = (1..=30).map(|n| format!("line {}\n", n)).collect();
+        let new: String = (1..=30)
+            .filter(|n| *n > 10)
+            .map(|n| format!("line {}\n", n))
+            .collect();
+        let mut edited = loaded_file("src/edited.rs", &old);
+        edited.new_content = Some(ContentData::Text(new.clone()));
+        edited.aligned_rows = Some(align_full_file(&old, &new));
+        app.files = vec![
+            loaded_file("src/a.rs", &old),
+            edited,
+            loaded_file("lib/edited.rs", &old),
+        ];
+        app.viewport_rows = 5;
+        let type_line = |app: &mut App, text: &str| {
+            for c in std::iter::once(':').chain(text.chars()) {
+                app.on_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+                    .expect("typing should succeed");
+            }
+            app.on_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+                .expect("submit should succeed");
+        };
+
+        type_line(&mut app, "src/edited.rs:5:2");
+        assert_eq!(app.selected_file_idx, 1);
+        let row = &app.files[1].aligned_rows.as_ref().unwrap()[app.v_scroll];
+        assert_eq!(row.right_line_no, Some(5));
+        assert!(app.prompt.is_none());
+
+        type_line(&mut app, "L3");
+        let row = &app.files[1].aligned_rows.as_ref().unwrap()[app.v_scroll];
+        assert_eq!(row.left_line_no, Some(3));
+
+        type_line(&mut app, "nope.rs:1");
+        assert_eq!(app.selected_file_idx, 1);
+        assert!(app.message.as_ref().unwrap().text.contains("nope.rs"));
+
+        type_line(&mut app, "edited.rs:1");
+        assert!(app.message.as_ref().unwrap().text.contains("several"));
+    }
+
+    #[test]
+    fn filter_command_hides_files_and_moves_the_selection() {
+        let mut app = app_for_test();
+        app.files = ["README.md", "src/main.rs", "src/ui.rs"]
+            .into_iter()
+            .map(changed_file)
+            .collect();
+        tree_rows_for(&mut app);
+        let run = |app: &mut App, line: &str| {
+            app.on_action(Action::CommandLine)
+                .expect("opening the prompt should succeed");
+            app.prompt.as_mut().unwrap().input = line.to_string();
+            app.on_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+                .expect("submit should succeed");
+        };
+
+        run(&mut app, "filter *.rs");
+        let shown: Vec<usize> = app.tree_rows.iter().filter_map(|r| r.file_index).collect();
+        assert_eq!(shown, vec![1, 2]);
+        assert_eq!(app.selected_file_idx, 1);
+
+        run(&mut app, "filter *.toml");
+        assert!(app.message.is_some());
+        assert_eq!(app.filter.as_ref().unwrap().pattern, "*.rs");
+
+        run(&mut app, "filter");
+        assert!(app.filter.is_none());
+        assert_eq!(app.tree_rows.iter().filter_map(|r| r.file_index).count(), 3);
+
+        run(&mut app, "toggle_tree");
+        assert!(!app.show_tree);
+    }
+
+    #[test]
+    fn theme_command_switches_themes_and_keeps_config_overrides() {
+        let mut app = app_for_test();
+        app.configured_theme.colors.gutter = Color::Magenta;
+        let epoch = app.highlight_epoch;
+
+        app.run_command_line("theme light")
+            .expect("switching to a built-in theme should succeed");
+        assert_eq!(app.theme.name, "light");
+        assert_eq!(app.highlight_epoch, epoch + 1);
+
+        app.run_command_line("theme dark")
+            .expect("switching back should succeed");
+        assert_eq!(app.theme.colors.gutter, Color::Magenta);
+        assert!(app.run_command_line("theme neon").is_err());
+        assert_eq!(app.theme.name, "dark");
+    }
+
+    #[test]
+    fn help_overlay_takes_motions_and_searches_until_closed() {
+        let mut app = app_for_test();
+        let text: String = (0..50).map(|n| format!("line {}\n", n)).collect();
+        app.files = vec![loaded_file("a.rs", &text)];
+        app.viewport_rows = 5;
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+
+        app.on_key(key('?')).expect("help should open");
+        app.on_key(key('j')).expect("help scroll should succeed");
+        app.on_key(key('j')).expect("help scroll should succeed");
+        assert_eq!(app.help.as_ref().unwrap().scroll, 2);
+        assert_eq!(app.v_scroll, 0);
+
+        for c in "/quit".chars() {
+            app.on_key(key(c)).expect("search typing should succeed");
+        }
+        app.on_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+            .expect("search should finish");
+        let help = app.help.as_ref().unwrap();
+        assert_eq!(
+            (help.query.as_str(), help.searching, help.scroll),
+            ("quit", false, 0)
+        );
+
+        app.on_key(key('q')).expect("q should close help");
+        assert!(app.help.is_none());
+        assert!(!app.should_quit);
+        app.on_key(key('j')).expect("scroll should succeed");
+        assert_eq!(app.v_scroll, 1);
+    }
+
+    fn mouse(kind: MouseKind, target: MouseTarget) -> MouseInput {
+        MouseInput {
+            kind,
+            target: Some(target),
+            track: (0, 10),
+            tree_percent: 50,
+            split_percent: 50,
+        }
+    }
+
+    #[test]
+    fn clicking_tree_rows_selects_files_and_collapses_directories() {
+        let mut app = app_for_test();
+        app.files = ["src/a.rs", "src/b.rs", "z.rs"]
+            .into_iter()
+            .map(changed_file)
+            .collect();
+        tree_rows_for(&mut app);
+        let press = |row| mouse(MouseKind::Press, MouseTarget::TreeRow(row));
+
+        // Rows: "src/", "a.rs", "b.rs", "z.rs".
+        app.on_mouse(press(2)).expect("click should succeed");
+        assert_eq!(app.selected_file_idx, 1);
+
+        app.on_mouse(press(0)).expect("click should succeed");
+        assert_eq!(app.tree_rows.len(), 2);
+        assert_eq!(app.tree_rows[0].label, "src/ …");
+
+        // Selecting a hidden file expands its directory again.
+        app.on_action(Action::SelectPrevFile)
+            .expect("select prev should succeed");
+        assert_eq!(app.selected_file_idx, 2);
+        app.on_action(Action::SelectNextFile)
+            .expect("select next should succeed");
+        assert_eq!(app.selected_file_idx, 2);
+        app.go_to(GotoTarget {
+            path: Some("src/a.rs".to_string()),
+            side: Side::Right,
+            line: 1,
+        })
+        .expect("goto should succeed");
+        assert_eq!(app.tree_rows.len(), 4);
+    }
+
+    #[test]
+    fn wheel_scrolls_the_pane_under_the_pointer_and_drags_move_the_view() {
+        let mut app = app_for_test();
+        let text: String = (0..100).map(|n| format!("line {}\n", n)).collect();
+        app.files = (0..20)
+            .map(|n| loaded_file(&format!("f{:02}.rs", n), &text))
+            .collect();
+        tree_rows_for(&mut app);
+        app.set_viewport(10, 40, 5);
+
+        app.on_mouse(mouse(MouseKind::WheelDown, MouseTarget::Diff))
+            .expect("wheel should succeed");
+        app.on_mouse(mouse(MouseKind::WheelRight, MouseTarget::Diff))
+            .expect("wheel should succeed");
+        assert_eq!((app.v_scroll, app.h_scroll), (3, 4));
+
+        app.on_mouse(mouse(MouseKind::WheelDown, MouseTarget::Tree))
+            .expect("wheel should succeed");
+        assert_eq!(app.tree_v_scroll, 3);
+        assert_eq!(app.v_scroll, 3);
+
+        let mut drag = mouse(MouseKind::Press, MouseTarget::Scrollbar);
+        drag.track = (5, 10);
+        app.on_mouse(drag).expect("scrollbar press should succeed");
+        assert_eq!(app.v_scroll, 45);
+        drag.kind = MouseKind::Drag;
+        drag.track = (20, 10);
+        drag.target = None;
+        app.on_mouse(drag).expect("scrollbar drag should succeed");
+        assert_eq!(app.v_scroll, 90);
+
+        app.on_mouse(mouse(MouseKind::Release, MouseTarget::Diff))
+            .expect("release should succeed");
+        app.on_mouse(mouse(MouseKind::Press, MouseTarget::TreeBorder))
+            .expect("border press should succeed");
+        let mut resize = mouse(MouseKind::Drag, MouseTarget::Diff);
+        resize.tree_percent = 95;
+        app.on_mouse(resize).expect("border drag should succeed");
+        assert_eq!(app.layout.tree_percent, 80);
+        assert_eq!(app.v_scroll, 90);
+
+        app.on_mouse(mouse(MouseKind::Release, MouseTarget::Diff))
+            .expect("release should succeed");
+        app.on_mouse(mouse(MouseKind::Press, MouseTarget::SplitBorder))
+            .expect("divider press should succeed");
+        resize.split_percent = 30;
+        app.on_mouse(resize).expect("divider drag should succeed");
+        assert_eq!(app.layout.split_percent, 30);
+    }
+
+    #[test]
+    fn layout_keys_resize_panes_and_move_the_tree() {
+        let mut app = app_for_test();
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+
+        app.on_key(key('+')).expect("grow should succeed");
+        assert_eq!(app.layout.tree_percent, 30);
+        for c in "50-".chars() {
+            app.on_key(key(c)).expect("counted shrink should succeed");
+        }
+        assert_eq!(app.layout.tree_percent, 10);
+        for c in "3}".chars() {
+            app.on_key(key(c)).expect("counted split should succeed");
+        }
+        assert_eq!(app.layout.split_percent, 56);
+
+        app.show_tree = false;
+        app.on_key(key('T')).expect("cycle should succeed");
+        assert_eq!(app.layout.tree_position, TreePosition::Right);
+        assert!(app.show_tree);
+
+        app.on_action(Action::ResetLayout)
+            .expect("reset should succeed");
+        assert_eq!(app.layout, LayoutSettings::default());
+    }
+
     #[test]
     fn apply_refreshed_files_preserves_selection_by_path() {
         let mut app = app_for_test();
@@ -428,13 +2078,363 @@ mod tests {
         assert_eq!(app.h_scroll, 0);
     }
 
+    fn loaded_file(path: &str, text: &str) -> ChangedFile {
+        let mut file = changed_file(path);
+        file.old_content = Some(ContentData::Text(text.to_string()));
+        file.new_content = Some(ContentData::Text(text.to_string()));
+        file.aligned_rows = Some(align_full_file(text, text));
+        file.content_hash = Some(content_hash(&file.old_content, &file.new_content));
+        file
+    }
+
+    fn tree_rows_for(app: &mut App) {
+        app.tree_rows = visible_tree_rows(&app.files, None, &app.collapsed_dirs);
+    }
+
+    #[test]
+    fn wrapping_lets_the_view_scroll_until_the_last_rows_fit() {
+        let mut app = app_for_test();
+        let long = "x".repeat(25);
+        let text = format!("a\nb\nc\nd\n{}\n", long);
+        app.files = vec![loaded_file("a.rs", &text)];
+        tree_rows_for(&mut app);
+        app.set_viewport(4, 10, 5);
+
+        app.on_action(Action::GoBottom).unwrap();
+        assert_eq!(app.v_scroll, 1);
+
+        // The last row takes three lines at width 10, so only one more fits above it.
+        app.run_command_line("wrap").unwrap();
+        assert!(app.wrap);
+        app.on_action(Action::GoBottom).unwrap();
+        assert_eq!(app.v_scroll, 3);
+    }
+
+    #[test]
+    fn mode_command_lists_the_files_of_each_comparison() {
+        let root = crate::git::tests::fixture_repo("app-modes");
+        let mut app = app_for_test();
+        app.repo = Arc::new(CliBackend::new(root));
+        app.run_command_line("mode worktree").unwrap();
+        let paths = |app: &App| -> Vec<String> {
+            app.files
+                .iter()
+                .map(|file| file.path.display().to_string())
+                .collect()
+        };
+        assert_eq!(paths(&app).len(), 4);
+
+        app.run_command_line("mode staged").unwrap();
+        assert_eq!(app.compare_mode, CompareMode::Staged);
+        assert_eq!(paths(&app), ["staged.txt"]);
+        assert_eq!(app.status().modes[0], "HEAD..index");
+
+        app.run_command_line("mode unstaged").unwrap();
+        assert_eq!(paths(&app), ["deleted.txt", "modified.txt"]);
+        assert_eq!(app.status().modes[0], "index..worktree");
+    }
+
+    #[test]
+    fn load_anyway_hint_names_the_bound_key_or_the_command() {
+        let mut keymap = Keymap::default();
+        assert_eq!(
+            load_anyway_hint(&keymap),
+            "press X to load anyway without syntax highlighting"
+        );
+        keymap.bind(crate::input::parse_sequence("X").unwrap(), Action::None);
+        assert!(load_anyway_hint(&keymap).starts_with("run :load_anyway "));
+    }
+
+    #[test]
+    fn export_runs_in_the_background_and_reports_when_done() {
+        let root = crate::git::tests::fixture_repo("app-export");
+        let mut app = app_for_test();
+        app.git = Arc::new(CliBackend::new(root));
+        app.loader = Loader::spawn(Arc::clone(&app.git), LoadLimits::default(), None);
+        app.files = app.git.collect_changed_files().unwrap();
+        app.set_filter(Some("modified.txt")).unwrap();
+
+        let out = std::env::temp_dir().join(format!("fdf-app-export-{}.patch", std::process::id()));
+        let command = format!("export patch {}", out.display());
+        app.run_command_line(&command).unwrap();
+        assert!(app.run_command_line(&command).is_err());
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while app.export.is_some() {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "export did not finish"
+            );
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            app.poll_background();
+        }
+        let message = app.message.as_ref().map(|message| message.text.clone());
+        assert!(
+            message.is_some_and(|text| text.starts_with("exported 1 file to ")),
+            "{:?}",
+            app.message
+        );
+        let patch = std::fs::read_to_string(&out).unwrap();
+        assert!(patch.starts_with("diff --git a/modified.txt b/modified.txt\n"));
+        let _ = std::fs::remove_file(&out);
+    }
+
+    #[test]
+    fn status_reports_file_position_hunks_lines_and_stats() {
+        let mut app = app_for_test();
+        let old = "a\nb\nc\nd\ne\nf\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\n";
+        let mut file = loaded_file("src/b.rs", old);
+        file.aligned_rows = Some(align_full_file(old, new));
+        app.files = vec![changed_file("a.rs"), file];
+        tree_rows_for(&mut app);
+        app.selected_file_idx = 1;
+
+        // Directories come first in the tree.
+        let status = app.status();
+        assert_eq!(status.file, Some((1, 2)));
+        assert_eq!(status.hunk, Some((None, 2)));
+        assert_eq!((status.left_line, status.right_line), (Some(1), Some(1)));
+        assert_eq!(status.stats, Some((3, 1)));
+        assert_eq!(status.modes, vec!["HEAD..worktree".to_string()]);
+
+        app.on_action(Action::NextChange).unwrap();
+        app.on_action(Action::NextChange).unwrap();
+        let status = app.status();
+        assert_eq!(status.hunk, Some((Some(2), 2)));
+        assert_eq!((status.left_line, status.right_line), (None, Some(7)));
+
+        app.selected_file_idx = 0;
+        let status = app.status();
+        assert_eq!(status.file, Some((2, 2)));
+        assert_eq!(status.hunk, None);
+    }
+
+    #[test]
+    fn status_lists_wrap_and_whitespace_modes() {
+        let mut app = app_for_test();
+        app.files = vec![loaded_file("a.rs", "a\n")];
+        tree_rows_for(&mut app);
+
+        app.run_command_line("wrap").unwrap();
+        app.run_command_line("whitespace").unwrap();
+        assert_eq!(
+            app.status().modes,
+            ["HEAD..worktree", "ignore whitespace", "wrap"]
+        );
+        // Rows aligned the other way are dropped and the file is loaded again.
+        assert!(app.loader.options.ignore_whitespace);
+        assert!(!app.files[0].is_loaded());
+        assert!(app.pending_load.is_some());
+        // Rows from a patch's hunks stay as they are.
+        app.files[0].hunks_only = true;
+        assert_eq!(app.status().modes[1], "ignore whitespace (not in hunks)");
+
+        app.on_action(Action::ToggleWhitespace).unwrap();
+        app.on_action(Action::ToggleWrap).unwrap();
+        assert_eq!(app.status().modes, ["HEAD..worktree"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_failed_load_is_shown_on_the_file_instead_of_ending_the_session() {
+        let root = std::env::temp_dir().join(format!("fdf-load-error-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        // A symlink to itself fails to read like an unreadable file would.
+        std::fs::create_dir_all(&root).unwrap();
+        std::os::unix::fs::symlink("loop.rs", root.join("loop.rs")).unwrap();
+
+        let mut app = app_for_test();
+        app.loader = Loader::spawn(
+            Arc::new(CliBackend::new(root.clone())),
+            LoadLimits::default(),
+            None,
+        );
+        let mut file = changed_file("loop.rs");
+        file.status.untracked = true;
+        app.files = vec![file];
+        app.ensure_selected_loaded();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while app.is_loading() && Instant::now() < deadline {
+            app.poll_background();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        let error = app.files[0]
+            .load_error
+            .as_deref()
+            .expect("load should fail");
+        assert!(error.contains("loop.rs"), "{}", error);
+        assert!(app.message.as_ref().is_some_and(|message| message.is_error));
+
+        app.on_action(Action::ScrollDown)
+            .expect("the session should go on");
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn a_finished_prefetch_of_a_resident_file_keeps_one_entry() {
+        let root = std::env::temp_dir().join(format!("fdf-prefetch-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("b.rs"), "b\n").unwrap();
+
+        let mut app = app_for_test();
+        app.loader = Loader::spawn(
+            Arc::new(CliBackend::new(root.clone())),
+            LoadLimits::default(),
+            None,
+        );
+        let mut file = changed_file("b.rs");
+        file.status.untracked = true;
+        app.files = vec![loaded_file("a.rs", "a\n"), file];
+        // Still listed from a load before a refresh.
+        app.resident = VecDeque::from([0, 1]);
+        let ticket = app.loader.prefetch(1, &app.files[1]);
+        app.prefetches.push(ticket);
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !app.prefetches.is_empty() && Instant::now() < deadline {
+            app.poll_background();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        assert!(app.files[1].is_loaded());
+        assert_eq!(app.resident, VecDeque::from([0, 1]));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn selecting_a_file_prefetches_its_neighbors_in_tree_order() {
+        let mut app = app_for_test();
+        app.files = vec![
+            loaded_file("a.rs", "a\n"),
+            changed_file("b.rs"),
+            changed_file("c.rs"),
+            changed_file("d.rs"),
+        ];
+        tree_rows_for(&mut app);
+
+        app.on_action(Action::SelectNextFile)
+            .expect("select next should succeed");
+
+        assert_eq!(app.selected_file_idx, 1);
+        assert_eq!(app.pending_load.as_ref().map(|t| t.file_idx), Some(1));
+        let mut prefetched: Vec<usize> = app.prefetches.iter().map(|t| t.file_idx).collect();
+        prefetched.sort_unstable();
+        // a.rs is already loaded, so only c.rs needs fetching.
+        assert_eq!(prefetched, vec![2]);
+
+        app.on_action(Action::SelectNextFile)
+            .expect("select next should succeed");
+
+        // The in-flight prefetch for c.rs becomes the selected load instead of a new request.
+        assert_eq!(app.selected_file_idx, 2);
+        assert_eq!(app.pending_load.as_ref().map(|t| t.file_idx), Some(2));
+        // b.rs keeps its in-flight load as a prefetch.
+        let mut prefetched: Vec<usize> = app.prefetches.iter().map(|t| t.file_idx).collect();
+        prefetched.sort_unstable();
+        assert_eq!(prefetched, vec![1, 3]);
+    }
+
+    #[test]
+    fn eviction_drops_least_recently_viewed_files_but_never_the_selection() {
+        let mut app = app_for_test();
+        let text = "x".repeat(100);
+        app.files = vec![
+            loaded_file("a.rs", &text),
+            loaded_file("b.rs", &text),
+            loaded_file("c.rs", &text),
+        ];
+        let per_file = app.files[0].resident_bytes();
+        app.limits.max_cached_bytes = per_file * 2;
+        app.resident = VecDeque::from([2, 1, 0]);
+        app.selected_file_idx = 0;
+
+        app.evict_over_budget();
+
+        assert!(app.files[0].is_loaded());
+        assert!(!app.files[1].is_loaded());
+        assert!(app.files[2].is_loaded());
+        assert_eq!(app.resident, VecDeque::from([2, 0]));
+    }
+
+    #[test]
+    fn carry_over_keeps_untouched_files_loaded() {
+        let mut app = app_for_test();
+        app.files = vec![loaded_file("a.rs", "a\n"), loaded_file("src/b.rs", "b\n")];
+        let mut refreshed = vec![changed_file("a.rs"), changed_file("src/b.rs")];
+        app.resident = VecDeque::from([1, 0]);
+
+        app.carry_over_loaded(&mut refreshed, |path| path.starts_with("src"));
+        app.apply_refreshed_files(refreshed);
+
+        assert!(app.files[0].is_loaded());
+        assert!(!app.files[1].is_loaded());
+        assert!(app.files[1].previous.is_some());
+        assert_eq!(app.resident, VecDeque::from([1, 0]));
+    }
+
+    #[test]
+    fn index_only_changes_update_statuses_without_reloading() {
+        let mut app = app_for_test();
+        app.files = vec![loaded_file("a.rs", "a\n"), changed_file("b.rs")];
+        tree_rows_for(&mut app);
+        app.ensure_selected_loaded();
+        app.selected_file_idx = 1;
+        app.ensure_selected_loaded();
+        assert!(app.pending_load.is_some());
+
+        let mut refreshed = vec![changed_file("a.rs"), changed_file("b.rs")];
+        refreshed[0].status.staged = true;
+        let changes = Changes {
+            index_changed: true,
+            ..Changes::default()
+        };
+        app.apply_watch_changes(refreshed, &changes);
+
+        assert!(app.files[0].is_loaded());
+        assert!(app.pending_load.is_some());
+        assert_eq!(app.files[0].status.indicator(), "[SU]");
+        assert!(app.tree_rows.iter().any(|row| row.label == "[SU] a.rs"));
+
+        // A file that left the list (`git rm --cached` of a new file) needs a full refresh.
+        app.apply_watch_changes(vec![changed_file("a.rs")], &changes);
+        assert_eq!(app.files.len(), 1);
+        assert!(app.files[0].is_loaded());
+    }
+
+    #[test]
+    fn refresh_reanchors_viewport_to_the_same_worktree_line() {
+        let mut app = app_for_test();
+        let before = "l1\nl2\nl3\nl4\nl5\n";
+        app.files = vec![loaded_file("a.rs", before)];
+        app.viewport_rows = 2;
+        app.v_scroll = 2;
+
+        app.apply_refresh(vec![changed_file("a.rs")], |_| true);
+        assert_eq!(app.pending_anchor.as_ref().map(|a| a.line), Some(3));
+
+        let after = "new\nnew\nl1\nl2\nl3\nl4\nl5\n";
+        let file = &mut app.files[0];
+        file.new_content = Some(ContentData::Text(after.to_string()));
+        file.aligned_rows = Some(align_full_file(before, after));
+        file.line_remap = Some(line_remap(before, after));
+        app.restore_anchor();
+
+        assert_eq!(app.v_scroll, 4);
+        assert!(app.pending_anchor.is_none());
+    }
+
     #[test]
-    fn apply_refreshed_files_bumps_highlight_epoch() {
+    fn apply_refreshed_files_keeps_highlights_of_other_files() {
         let mut app = app_for_test();
-        app.highlight_epoch = u64::MAX;
+        app.highlight_epoch = 7;
 
         app.apply_refreshed_files(vec![changed_file("a.rs")]);
 
-        assert_eq!(app.highlight_epoch, 0);
+        // Reloaded files get a new content hash instead, which keys their highlights.
+        assert_eq!(app.highlight_epoch, 7);
     }
 }
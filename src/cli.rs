@@ -0,0 +1,255 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::model::LoadLimits;
+use crate::print::MIN_WIDTH;
+use crate::theme::ColorDepth;
+
+pub const USAGE: &str = "\
+usage: fdf [options] [<left> <right>]
+       fdf [options] --patch <file>
+
+Without paths, fdf compares the worktree of the current repository with HEAD. With two files
+or two directories it compares those instead (the way `git difftool` calls it). When git runs
+fdf as its pager, the diff on stdin is shown.
+
+options:
+  --max-file-size <size>    skip loading files larger than <size> (e.g. 512K, 8M, 1G)
+  --max-line-length <n>     use degraded mode for files with lines longer than <n> chars
+  --cache-size <size>       memory kept for loaded files before evicting old ones (default 256M)
+  --config <path>           read settings from <path> instead of $XDG_CONFIG_HOME/fdf/config.toml
+  --color <depth>           auto, truecolor, 256, 16 or mono (default auto: from COLORTERM/TERM)
+  --patch <file>            show a unified diff or `git format-patch` mbox (- reads stdin)
+  -h, --help                print this help
+
+non-interactive output (also used when stdout is not a terminal):
+  --print, --no-tui         write every changed file side by side to stdout and exit
+  --width <n>               total line width (default: terminal width, $COLUMNS or 160)
+  --context <n>             fold unchanged lines more than <n> lines away from a change
+  --filter <glob>           only files matching <glob>; repeat to match any of several
+                            (plain text unless --color is given or stdout is a terminal)
+";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Options {
+    pub limits: LoadLimits,
+    pub config_path: Option<PathBuf>,
+    /// `None` detects the depth from the environment.
+    pub color: Option<ColorDepth>,
+    pub show_help: bool,
+    /// `--print`: write to stdout instead of starting the TUI.
+    pub print: bool,
+    /// `None` uses the terminal width.
+    pub width: Option<u16>,
+    /// `None` prints whole files.
+    pub context: Option<usize>,
+    pub filters: Vec<String>,
+    /// Files or directories to compare instead of the repository.
+    pub paths: Vec<PathBuf>,
+    /// `--patch`: a patch file to show, `-` for stdin.
+    pub patch: Option<PathBuf>,
+}
+
+impl Options {
+    /// Whether any option that only applies to printed output was given.
+    pub fn has_print_options(&self) -> bool {
+        self.width.is_some() || self.context.is_some() || !self.filters.is_empty()
+    }
+}
+
+pub fn parse<I>(args: I) -> Result<Options>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+
+        let mut value = |name: &str| -> Result<String> {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| anyhow!("missing value for {}", name))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => options.show_help = true,
+            "--max-file-size" => {
+                let raw = value("--max-file-size")?;
+                options.limits.max_file_bytes = parse_size(&raw)
+                    .with_context(|| format!("invalid --max-file-size value {:?}", raw))?;
+            }
+            "--max-line-length" => {
+                let raw = value("--max-line-length")?;
+                options.limits.max_line_chars = raw
+                    .parse()
+                    .with_context(|| format!("invalid --max-line-length value {:?}", raw))?;
+            }
+            "--cache-size" => {
+                let raw = value("--cache-size")?;
+                options.limits.max_cached_bytes = parse_size(&raw)
+                    .with_context(|| format!("invalid --cache-size value {:?}", raw))?;
+            }
+            "--config" => options.config_path = Some(PathBuf::from(value("--config")?)),
+            "--color" => {
+                let raw = value("--color")?;
+                options.color = ColorDepth::from_name(&raw).context("invalid --color value")?;
+            }
+            "--print" | "--no-tui" => options.print = true,
+            "--width" => {
+                let raw = value("--width")?;
+                let width: u16 = raw
+                    .parse()
+                    .with_context(|| format!("invalid --width value {:?}", raw))?;
+                if width < MIN_WIDTH {
+                    bail!("--width must be at least {}", MIN_WIDTH);
+                }
+                options.width = Some(width);
+            }
+            "--context" => {
+                let raw = value("--context")?;
+                options.context = Some(
+                    raw.parse()
+                        .with_context(|| format!("invalid --context value {:?}", raw))?,
+                );
+            }
+            "--filter" => options.filters.push(value("--filter")?),
+            "--patch" => options.patch = Some(PathBuf::from(value("--patch")?)),
+            "--" => options.paths.extend(args.by_ref().map(PathBuf::from)),
+            path if !path.starts_with('-') => options.paths.push(PathBuf::from(path)),
+            other => return Err(anyhow!("unknown argument {:?}\n\n{}", other, USAGE)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn parse_size(raw: &str) -> Result<u64> {
+    let raw = raw.trim();
+    let (digits, multiplier) = match raw.char_indices().last() {
+        Some((idx, 'k' | 'K')) => (&raw[..idx], 1024),
+        Some((idx, 'm' | 'M')) => (&raw[..idx], 1024 * 1024),
+        Some((idx, 'g' | 'G')) => (&raw[..idx], 1024 * 1024 * 1024),
+        _ => (raw, 1),
+    };
+
+    let value: u64 = digits.parse()?;
+    value
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow!("size is too large"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{parse, parse_size};
+    use crate::model::LoadLimits;
+    use crate::theme::ColorDepth;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn defaults_to_default_limits() {
+        let options = parse(args(&[])).expect("empty args should parse");
+        assert_eq!(options.limits, LoadLimits::default());
+        assert!(!options.show_help);
+    }
+
+    #[test]
+    fn parses_limit_flags_with_separate_and_inline_values() {
+        let options = parse(args(&[
+            "--max-file-size",
+            "2M",
+            "--max-line-length=300",
+            "--cache-size=64M",
+        ]))
+        .expect("limit flags should parse");
+
+        assert_eq!(options.limits.max_file_bytes, 2 * 1024 * 1024);
+        assert_eq!(options.limits.max_line_chars, 300);
+        assert_eq!(options.limits.max_cached_bytes, 64 * 1024 * 1024);
+    }
+
+    #[test]
+    fn parses_config_path() {
+        let options = parse(args(&["--config", "keys.toml"])).expect("config flag should parse");
+        assert_eq!(options.config_path, Some(PathBuf::from("keys.toml")));
+    }
+
+    #[test]
+    fn parses_color_depth() {
+        assert_eq!(parse(args(&[])).unwrap().color, None);
+        assert_eq!(parse(args(&["--color=auto"])).unwrap().color, None);
+        assert_eq!(
+            parse(args(&["--color", "256"])).unwrap().color,
+            Some(ColorDepth::Ansi256)
+        );
+        assert!(parse(args(&["--color", "88"])).is_err());
+    }
+
+    #[test]
+    fn parses_print_options() {
+        let options = parse(args(&[
+            "--no-tui",
+            "--width=100",
+            "--context",
+            "3",
+            "--filter",
+            "*.rs",
+            "--filter=docs/**",
+        ]))
+        .expect("print flags should parse");
+
+        assert!(options.print);
+        assert_eq!(options.width, Some(100));
+        assert_eq!(options.context, Some(3));
+        assert_eq!(options.filters, vec!["*.rs", "docs/**"]);
+        assert!(options.has_print_options());
+        assert!(!parse(args(&["--print"])).unwrap().has_print_options());
+        assert!(parse(args(&["--width", "12"])).is_err());
+    }
+
+    #[test]
+    fn collects_paths_to_compare() {
+        let options = parse(args(&["--width=80", "old", "new"])).expect("paths should parse");
+        assert_eq!(
+            options.paths,
+            vec![PathBuf::from("old"), PathBuf::from("new")]
+        );
+
+        let options = parse(args(&["a", "--", "--b"])).expect("paths after -- should parse");
+        assert_eq!(
+            options.paths,
+            vec![PathBuf::from("a"), PathBuf::from("--b")]
+        );
+
+        let options = parse(args(&["--patch", "-"])).expect("--patch - should parse");
+        assert_eq!(options.patch, Some(PathBuf::from("-")));
+        assert!(options.paths.is_empty());
+    }
+
+    #[test]
+    fn parses_size_suffixes() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("4k").unwrap(), 4096);
+        assert_eq!(parse_size("1G").unwrap(), 1024 * 1024 * 1024);
+        assert!(parse_size("lots").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_arguments_and_missing_values() {
+        assert!(parse(args(&["--frobnicate"])).is_err());
+        assert!(parse(args(&["--max-line-length"])).is_err());
+    }
+}
@@ -0,0 +1,413 @@
+use anyhow::{anyhow, bail, Result};
+
+use crate::input::Action;
+use crate::mode::{CompareMode, MODES};
+use crate::model::Side;
+use crate::theme::THEMES;
+
+/// A parsed `:` command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Any keymap action by its config name, e.g. `:refresh` or `:toggle_tree`.
+    Action(Action),
+    Goto(GotoTarget),
+    /// Show only files whose path matches the glob; `None` clears the filter.
+    Filter(Option<String>),
+    /// Open the selected file in `$VISUAL`/`$EDITOR` at the top visible line.
+    Open,
+    /// Switch to a built-in theme by name.
+    Theme(String),
+    /// Compare `HEAD`, the index and the worktree another way.
+    Mode(CompareMode),
+    /// Write the listed files' changes as a unified diff, to `fdf.patch` by default.
+    ExportPatch(Option<String>),
+}
+
+/// Commands beyond the keymap actions, with usage shown in completions and help.
+pub const COMMANDS: &[(&str, &str, &str)] = &[
+    (
+        "export",
+        "export patch [<file>]",
+        "write the listed changes as a patch (default fdf.patch)",
+    ),
+    (
+        "filter",
+        "filter [<glob>]",
+        "show only files matching <glob>; no glob shows all",
+    ),
+    (
+        "mode",
+        "mode <name>",
+        "compare worktree (HEAD..worktree), staged (HEAD..index) or unstaged (index..worktree)",
+    ),
+    (
+        "open",
+        "open",
+        "edit the selected file at the top visible line",
+    ),
+    (
+        "theme",
+        "theme <name>",
+        "switch colors: dark, light, high-contrast, colorblind",
+    ),
+    (
+        "<line>",
+        "[<path>:][L]<line>",
+        "jump to a worktree (or L: HEAD) line",
+    ),
+];
+
+/// Short forms accepted in addition to the action names.
+const ALIASES: &[(&str, Action)] = &[
+    ("q", Action::Quit),
+    ("wrap", Action::ToggleWrap),
+    ("whitespace", Action::ToggleWhitespace),
+];
+
+pub fn parse(input: &str) -> Result<Command> {
+    let input = input.trim();
+    let (name, argument) = match input.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, Some(argument.trim()).filter(|arg| !arg.is_empty())),
+        None => (input, None),
+    };
+
+    let action = Action::from_name(name)
+        .or_else(|| {
+            ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == name)
+                .map(|(_, action)| *action)
+        })
+        .filter(|action| !matches!(action, Action::None | Action::CommandLine));
+    let command = match (name, action) {
+        (_, Some(action)) => Command::Action(action),
+        ("filter", _) => return Ok(Command::Filter(argument.map(str::to_string))),
+        ("export", _) => {
+            let (format, file) = match argument.and_then(|arg| arg.split_once(char::is_whitespace))
+            {
+                Some((format, file)) => (format, Some(file.trim().to_string())),
+                None => (argument.unwrap_or_default(), None),
+            };
+            if format != "patch" {
+                bail!("export needs a format: patch");
+            }
+            return Ok(Command::ExportPatch(file));
+        }
+        ("mode", _) => {
+            let name =
+                argument.ok_or_else(|| anyhow!("mode needs a name: {}", MODES.join(", ")))?;
+            return CompareMode::from_name(name).map(Command::Mode);
+        }
+        ("open", _) => Command::Open,
+        ("theme", _) => {
+            return argument
+                .map(|name| Command::Theme(name.to_string()))
+                .ok_or_else(|| anyhow!("theme needs a name: {}", THEMES.join(", ")))
+        }
+        _ => {
+            return parse_goto(input)
+                .map_err(|err| {
+                    if name.contains(':') || name.starts_with(|c: char| c.is_ascii_digit()) {
+                        err
+                    } else {
+                        anyhow!("unknown command {:?}", name)
+                    }
+                })
+                .map(Command::Goto)
+        }
+    };
+
+    match argument {
+        Some(argument) => bail!("{} takes no argument (got {:?})", name, argument),
+        None => Ok(command),
+    }
+}
+
+/// Candidates for completing `input`: command names, then changed paths (with a trailing
+/// `:` ready for a line number). Of the arguments, only theme and mode names and export formats
+/// are completed.
+pub fn complete<'a>(input: &str, paths: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    for (command, names) in [("theme", THEMES), ("mode", MODES), ("export", &["patch"])] {
+        if let Some(name) = input
+            .strip_prefix(command)
+            .and_then(|rest| rest.strip_prefix(' '))
+        {
+            return names
+                .iter()
+                .filter(|candidate| candidate.starts_with(name.trim_start()))
+                .map(|candidate| format!("{} {}", command, candidate))
+                .collect();
+        }
+    }
+    if input.contains(char::is_whitespace) {
+        return Vec::new();
+    }
+
+    let mut commands: Vec<String> = Action::all()
+        .map(|(name, _)| name)
+        .filter(|name| *name != "command_line")
+        .chain(COMMANDS.iter().map(|(name, _, _)| *name))
+        .filter(|name| name.starts_with(input) && !name.starts_with('<'))
+        .map(|name| match name {
+            "export" | "filter" | "mode" | "theme" => format!("{} ", name),
+            name => name.to_string(),
+        })
+        .collect();
+    commands.sort();
+
+    let mut paths: Vec<String> = paths
+        .into_iter()
+        .filter(|path| {
+            path.starts_with(input)
+                || (!input.contains('/')
+                    && path
+                        .rsplit('/')
+                        .next()
+                        .is_some_and(|file| file.starts_with(input)))
+        })
+        .map(|path| format!("{}:", path))
+        .collect();
+    paths.sort();
+
+    commands.extend(paths);
+    commands
+}
+
+/// Text typed after `:` while the command line is open.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Prompt {
+    pub input: String,
+    pub completion: Option<Completion>,
+}
+
+/// Candidates offered by Tab for the text typed before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub candidates: Vec<String>,
+    pub selected: usize,
+}
+
+impl Prompt {
+    /// Fills in the next (or previous) candidate, computing them on the first Tab.
+    pub fn cycle_completion(
+        &mut self,
+        forward: bool,
+        candidates: impl FnOnce(&str) -> Vec<String>,
+    ) {
+        match self.completion.as_mut() {
+            Some(completion) => {
+                let len = completion.candidates.len();
+                completion.selected = if forward {
+                    (completion.selected + 1) % len
+                } else {
+                    (completion.selected + len - 1) % len
+                };
+            }
+            None => {
+                let candidates = candidates(&self.input);
+                if candidates.is_empty() {
+                    return;
+                }
+                let selected = if forward { 0 } else { candidates.len() - 1 };
+                self.completion = Some(Completion {
+                    candidates,
+                    selected,
+                });
+            }
+        }
+        if let Some(completion) = &self.completion {
+            self.input = completion.candidates[completion.selected].clone();
+        }
+    }
+
+    /// Any edit other than Tab starts a new completion.
+    pub fn edit(&mut self) -> &mut String {
+        self.completion = None;
+        &mut self.input
+    }
+}
+
+/// A `:` jump: `812` (worktree line), `L812` (`HEAD` line), or `path:line[:column]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GotoTarget {
+    pub path: Option<String>,
+    pub side: Side,
+    pub line: usize,
+}
+
+pub fn parse_goto(input: &str) -> Result<GotoTarget> {
+    let input = input.trim();
+    if let Ok((side, line)) = parse_line(input) {
+        return Ok(GotoTarget {
+            path: None,
+            side,
+            line,
+        });
+    }
+
+    let (head, tail) = input
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("expected <line>, L<line> or <path>:<line>, got {:?}", input))?;
+    // Compiler output adds a column: `src/foo.rs:812:5`.
+    let (path, line_spec) = match head.rsplit_once(':') {
+        Some((path, line)) if parse_line(line).is_ok() && tail.parse::<usize>().is_ok() => {
+            (path, line)
+        }
+        _ => (head, tail),
+    };
+    if path.is_empty() {
+        bail!("missing path before {:?}", line_spec);
+    }
+    let (side, line) = parse_line(line_spec)?;
+
+    Ok(GotoTarget {
+        path: Some(path.to_string()),
+        side,
+        line,
+    })
+}
+
+fn parse_line(spec: &str) -> Result<(Side, usize)> {
+    let (side, digits) = match spec.as_bytes().first() {
+        Some(b'L' | b'l') => (Side::Left, &spec[1..]),
+        Some(b'R' | b'r') => (Side::Right, &spec[1..]),
+        _ => (Side::Right, spec),
+    };
+    let line: usize = digits
+        .parse()
+        .map_err(|_| anyhow!("invalid line number {:?}", spec))?;
+    if line == 0 {
+        bail!("line numbers start at 1");
+    }
+    Ok((side, line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{complete, parse, parse_goto, Command, GotoTarget, Prompt};
+    use crate::input::Action;
+    use crate::mode::CompareMode;
+    use crate::model::Side;
+
+    fn target(path: Option<&str>, side: Side, line: usize) -> GotoTarget {
+        GotoTarget {
+            path: path.map(str::to_string),
+            side,
+            line,
+        }
+    }
+
+    #[test]
+    fn parses_line_numbers_for_either_side() {
+        assert_eq!(parse_goto("812").unwrap(), target(None, Side::Right, 812));
+        assert_eq!(parse_goto("L812").unwrap(), target(None, Side::Left, 812));
+        assert_eq!(parse_goto(" r7 ").unwrap(), target(None, Side::Right, 7));
+    }
+
+    #[test]
+    fn parses_path_and_line_with_optional_column() {
+        assert_eq!(
+            parse_goto("src/foo.rs:812").unwrap(),
+            target(Some("src/foo.rs"), Side::Right, 812)
+        );
+        assert_eq!(
+            parse_goto("src/foo.rs:812:5").unwrap(),
+            target(Some("src/foo.rs"), Side::Right, 812)
+        );
+        assert_eq!(
+            parse_goto("foo.rs:L3").unwrap(),
+            target(Some("foo.rs"), Side::Left, 3)
+        );
+    }
+
+    #[test]
+    fn parses_commands_actions_and_jumps() {
+        assert_eq!(parse("refresh").unwrap(), Command::Action(Action::Refresh));
+        assert_eq!(parse(" q ").unwrap(), Command::Action(Action::Quit));
+        assert_eq!(parse("wrap").unwrap(), Command::Action(Action::ToggleWrap));
+        assert_eq!(
+            parse("whitespace").unwrap(),
+            Command::Action(Action::ToggleWhitespace)
+        );
+        assert_eq!(
+            parse("filter  *.rs ").unwrap(),
+            Command::Filter(Some("*.rs".to_string()))
+        );
+        assert_eq!(parse("filter").unwrap(), Command::Filter(None));
+        assert_eq!(parse("open").unwrap(), Command::Open);
+        assert_eq!(
+            parse("mode staged").unwrap(),
+            Command::Mode(CompareMode::Staged)
+        );
+        assert!(parse("mode").is_err());
+        assert!(parse("mode index").is_err());
+        assert_eq!(parse("export patch").unwrap(), Command::ExportPatch(None));
+        assert_eq!(
+            parse("export patch  out/a.diff").unwrap(),
+            Command::ExportPatch(Some("out/a.diff".to_string()))
+        );
+        assert!(parse("export").is_err());
+        assert!(parse("export html").is_err());
+        assert_eq!(
+            parse("theme light").unwrap(),
+            Command::Theme("light".to_string())
+        );
+        assert!(parse("theme").is_err());
+        assert_eq!(
+            parse("12").unwrap(),
+            Command::Goto(target(None, Side::Right, 12))
+        );
+
+        let unknown = parse("frobnicate").unwrap_err().to_string();
+        assert!(unknown.contains("unknown command"), "{}", unknown);
+        assert!(parse("refresh now").is_err());
+        assert!(parse("command_line").is_err());
+    }
+
+    #[test]
+    fn completes_command_names_then_paths() {
+        let paths = ["src/main.rs", "src/model.rs", "README.md"];
+
+        assert_eq!(complete("ref", paths), vec!["refresh"]);
+        assert_eq!(complete("R", paths), vec!["README.md:"]);
+        assert_eq!(complete("fil", paths), vec!["filter "]);
+        assert_eq!(
+            complete("m", paths),
+            vec![
+                "mode ".to_string(),
+                "src/main.rs:".to_string(),
+                "src/model.rs:".to_string()
+            ]
+        );
+        assert!(complete("filter x", paths).is_empty());
+        assert_eq!(complete("mode s", paths), vec!["mode staged"]);
+        assert_eq!(complete("ex", paths), vec!["export "]);
+        assert_eq!(complete("export ", paths), vec!["export patch"]);
+        assert_eq!(complete("theme l", paths), vec!["theme light"]);
+    }
+
+    #[test]
+    fn tab_cycles_through_candidates_until_the_next_edit() {
+        let mut prompt = Prompt::default();
+        prompt.edit().push_str("page");
+        let candidates = |input: &str| complete(input, std::iter::empty());
+
+        prompt.cycle_completion(true, candidates);
+        assert_eq!(prompt.input, "page_down");
+        prompt.cycle_completion(true, candidates);
+        assert_eq!(prompt.input, "page_up");
+        prompt.cycle_completion(true, candidates);
+        assert_eq!(prompt.input, "page_down");
+
+        prompt.edit().pop();
+        assert!(prompt.completion.is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_targets() {
+        assert!(parse_goto("0").is_err());
+        assert!(parse_goto("abc").is_err());
+        assert!(parse_goto(":12").is_err());
+        assert!(parse_goto("foo.rs:x").is_err());
+    }
+}
@@ -0,0 +1,469 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::git::GitBackend;
+use crate::model::{ChangedFile, FileStatus};
+
+/// Version control metadata, which differs between any two checkouts and is never of interest.
+const SKIPPED_DIRS: &[&str] = &[".git", ".hg", ".svn"];
+
+/// Most threads used to compare the contents of files present on both sides.
+const MAX_COMPARE_THREADS: usize = 8;
+
+/// Bytes compared at a time, so large files are never held in memory whole.
+const COMPARE_CHUNK: usize = 64 * 1024;
+
+/// Compares two files or two directories named on the command line, the way `git difftool`
+/// passes `$LOCAL` and `$REMOTE` (directories with `--dir-diff`). Neither needs to be in a
+/// repository, and git is not run.
+///
+/// The left path stands in for `HEAD` and the right one for the worktree, so `:open` edits the
+/// right side. There is no repository to watch; `R` walks both sides again.
+#[derive(Debug)]
+pub struct PathsBackend {
+    left: PathBuf,
+    right: PathBuf,
+    /// What file paths are relative to: the right directory, or the right file's parent.
+    root: PathBuf,
+    dirs: bool,
+}
+
+impl PathsBackend {
+    /// A file and a directory compare the file with the one of the same name in the directory,
+    /// as diff(1) does.
+    pub fn new(left: &Path, right: &Path) -> Result<Self> {
+        let is_dir = |path: &Path| -> Result<bool> {
+            let meta =
+                fs::metadata(path).with_context(|| format!("cannot compare {}", path.display()))?;
+            Ok(meta.is_dir())
+        };
+        let file_name = |path: &Path| -> Result<PathBuf> {
+            path.file_name()
+                .map(PathBuf::from)
+                .ok_or_else(|| anyhow!("{} has no file name", path.display()))
+        };
+
+        let (left, right) = match (is_dir(left)?, is_dir(right)?) {
+            (false, true) => (left.to_path_buf(), right.join(file_name(left)?)),
+            (true, false) => (left.join(file_name(right)?), right.to_path_buf()),
+            _ => (left.to_path_buf(), right.to_path_buf()),
+        };
+        let (left, right) = (left.as_path(), right.as_path());
+
+        let (dirs, root) = match (is_dir(left)?, is_dir(right)?) {
+            (true, true) => (true, right.to_path_buf()),
+            (false, false) => {
+                let parent = right.parent().filter(|dir| !dir.as_os_str().is_empty());
+                (false, parent.unwrap_or(Path::new(".")).to_path_buf())
+            }
+            _ => bail!(
+                "{} and {} must both be files or both be directories",
+                left.display(),
+                right.display()
+            ),
+        };
+
+        Ok(Self {
+            left: left.to_path_buf(),
+            right: right.to_path_buf(),
+            root,
+            dirs,
+        })
+    }
+
+    /// Whether each of `paths` has the same contents on both sides. Release trees hold
+    /// thousands of files, so they are compared on several threads.
+    fn compare_all(&self, paths: &[&PathBuf]) -> Vec<bool> {
+        let threads = thread::available_parallelism()
+            .map_or(1, usize::from)
+            .min(MAX_COMPARE_THREADS);
+        let chunk = paths.len().div_ceil(threads).max(1);
+        thread::scope(|scope| {
+            let workers: Vec<_> = paths
+                .chunks(chunk)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|path| {
+                                same_contents(&self.left.join(path), &self.right.join(path))
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("comparison threads do not panic"))
+                .collect()
+        })
+    }
+
+    fn left_path(&self, path: &Path) -> PathBuf {
+        if self.dirs {
+            self.left.join(path)
+        } else {
+            self.left.clone()
+        }
+    }
+}
+
+impl GitBackend for PathsBackend {
+    fn repo_root(&self) -> &Path {
+        &self.root
+    }
+
+    fn git_dir(&self) -> Result<PathBuf> {
+        bail!("comparing two paths, not a repository")
+    }
+
+    /// Files that differ in content or exist on one side only, sorted by path.
+    fn collect_changed_files(&self) -> Result<Vec<ChangedFile>> {
+        let modified = FileStatus {
+            unstaged: true,
+            ..FileStatus::default()
+        };
+        if !self.dirs {
+            let name = self.right.file_name().unwrap_or(self.right.as_os_str());
+            return Ok(vec![ChangedFile::new(PathBuf::from(name), modified)]);
+        }
+
+        let left = walk(&self.left)?;
+        let right = walk(&self.right)?;
+        let both: Vec<&PathBuf> = left.intersection(&right).collect();
+        let unchanged: BTreeSet<&PathBuf> = both
+            .iter()
+            .zip(self.compare_all(&both))
+            .filter(|(_, same)| *same)
+            .map(|(path, _)| *path)
+            .collect();
+
+        let mut files = Vec::new();
+        for path in left.union(&right) {
+            let status = match (left.contains(path), right.contains(path)) {
+                (true, true) if unchanged.contains(path) => continue,
+                // Only on the right: new, like an untracked file.
+                (false, true) => FileStatus {
+                    untracked: true,
+                    ..FileStatus::default()
+                },
+                (true, false) => FileStatus {
+                    deleted: true,
+                    ..modified.clone()
+                },
+                _ => modified.clone(),
+            };
+            files.push(ChangedFile::new(path.clone(), status));
+        }
+        Ok(files)
+    }
+
+    fn head_blob_size(&self, path: &Path) -> Result<u64> {
+        Ok(fs::metadata(self.left_path(path)).map_or(0, |meta| meta.len()))
+    }
+
+    fn read_head_blob(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        let left = self.left_path(path);
+        match fs::read(&left) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).with_context(|| format!("failed to read {:?}", left)),
+        }
+    }
+
+    fn side_names(&self) -> [String; 2] {
+        [
+            self.left.display().to_string(),
+            self.right.display().to_string(),
+        ]
+    }
+}
+
+/// Paths of everything below `root` that is not a directory, relative to `root`, leaving out
+/// [`SKIPPED_DIRS`]. Symlinks to directories are followed, as diff(1) does, unless they lead
+/// back to a directory being walked; other symlinks count as files, since
+/// `git difftool --dir-diff` links worktree files into its temporary directory.
+fn walk(root: &Path) -> Result<BTreeSet<PathBuf>> {
+    let mut paths = BTreeSet::new();
+    // Directories to walk, each with the resolved paths of the directories it is in.
+    let mut pending = vec![(PathBuf::new(), Vec::new())];
+    while let Some((dir, mut ancestors)) = pending.pop() {
+        let full = root.join(&dir);
+        // A link to an enclosing directory would otherwise be walked forever.
+        let real = fs::canonicalize(&full)
+            .with_context(|| format!("failed to resolve {}", full.display()))?;
+        if ancestors.contains(&real) {
+            continue;
+        }
+        ancestors.push(real);
+        let entries =
+            fs::read_dir(&full).with_context(|| format!("failed to list {}", full.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let path = dir.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            let is_dir = file_type.is_dir()
+                || (file_type.is_symlink()
+                    && fs::metadata(entry.path()).is_ok_and(|meta| meta.is_dir()));
+            if is_dir {
+                let skipped = SKIPPED_DIRS.iter().any(|name| entry.file_name() == **name);
+                if !skipped {
+                    pending.push((path, ancestors.clone()));
+                }
+            } else {
+                paths.insert(path);
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// Two symlinks to regular files are the same if they point to the same place; anything else
+/// is compared by contents. Unreadable files, dangling links among them, count as different,
+/// so the error shows up when the file is opened.
+fn same_contents(left: &Path, right: &Path) -> bool {
+    let file_link = |path: &Path| {
+        fs::read_link(path)
+            .ok()
+            .filter(|_| fs::metadata(path).is_ok_and(|meta| meta.is_file()))
+    };
+    if let (Some(left), Some(right)) = (file_link(left), file_link(right)) {
+        return left == right;
+    }
+    let size = |path: &Path| fs::metadata(path).map(|meta| meta.len()).ok();
+    if size(left).is_none() || size(left) != size(right) {
+        return false;
+    }
+    match (fs::File::open(left), fs::File::open(right)) {
+        (Ok(left), Ok(right)) => {
+            same_stream(BufReader::new(left), BufReader::new(right)).unwrap_or(false)
+        }
+        _ => false,
+    }
+}
+
+/// Reads both sides in [`COMPARE_CHUNK`] pieces until they differ or both end.
+fn same_stream(mut left: impl Read, mut right: impl Read) -> io::Result<bool> {
+    let mut left_chunk = vec![0; COMPARE_CHUNK];
+    let mut right_chunk = vec![0; COMPARE_CHUNK];
+    loop {
+        let read = read_chunk(&mut left, &mut left_chunk)?;
+        if read != read_chunk(&mut right, &mut right_chunk)? {
+            return Ok(false);
+        }
+        if read == 0 {
+            return Ok(true);
+        }
+        if left_chunk[..read] != right_chunk[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Fills `chunk` unless the input ends first; the number of bytes read.
+fn read_chunk(input: &mut impl Read, chunk: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < chunk.len() {
+        match input.read(&mut chunk[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use std::io::Read;
+
+    use super::{same_stream, PathsBackend, COMPARE_CHUNK};
+    use crate::git::{load_file_contents, GitBackend};
+    use crate::model::{ChangedFile, ContentData, LoadLimits};
+
+    fn scratch(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("fdf-compare-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        root
+    }
+
+    fn write(path: &Path, text: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    fn text(content: &Option<ContentData>) -> &str {
+        match content {
+            Some(ContentData::Text(text)) => text,
+            other => panic!("expected text, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lists_directory_differences_and_loads_both_sides() {
+        let root = scratch("dirs");
+        let (left, right) = (root.join("left"), root.join("right"));
+        write(&left.join("same.txt"), "same\n");
+        write(&right.join("same.txt"), "same\n");
+        write(&left.join("src/lib.rs"), "old\n");
+        write(&right.join("src/lib.rs"), "new\n");
+        write(&left.join("removed.txt"), "gone\n");
+        write(&right.join("docs/added.md"), "hello\n");
+
+        let backend = PathsBackend::new(&left, &right).unwrap();
+        let files = backend.collect_changed_files().unwrap();
+        let listed: Vec<(String, &str)> = files
+            .iter()
+            .map(|file| (file.path.display().to_string(), file.status.indicator()))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                ("docs/added.md".to_string(), "[N]"),
+                ("removed.txt".to_string(), "[D]"),
+                ("src/lib.rs".to_string(), "[U]"),
+            ]
+        );
+
+        let mut removed = files[1].clone();
+        load_file_contents(&backend, &mut removed, &LoadLimits::default()).unwrap();
+        assert_eq!(text(&removed.old_content), "gone\n");
+        assert_eq!(text(&removed.new_content), "");
+
+        let mut changed = files[2].clone();
+        load_file_contents(&backend, &mut changed, &LoadLimits::default()).unwrap();
+        assert_eq!(text(&changed.old_content), "old\n");
+        assert_eq!(text(&changed.new_content), "new\n");
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn compares_two_files_under_the_right_name() {
+        let root = scratch("files");
+        write(&root.join("blob/main.rs"), "fn old() {}\n");
+        write(&root.join("work/main.rs"), "fn new() {}\n");
+
+        let backend =
+            PathsBackend::new(&root.join("blob/main.rs"), &root.join("work/main.rs")).unwrap();
+        let mut file: ChangedFile = backend.collect_changed_files().unwrap().remove(0);
+        assert_eq!(file.path, PathBuf::from("main.rs"));
+        assert_eq!(backend.repo_root(), root.join("work"));
+
+        load_file_contents(&backend, &mut file, &LoadLimits::default()).unwrap();
+        assert_eq!(text(&file.old_content), "fn old() {}\n");
+        assert_eq!(text(&file.new_content), "fn new() {}\n");
+        assert!(PathsBackend::new(&root.join("blob/main.rs"), &root.join("missing")).is_err());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn compares_a_file_with_its_namesake_in_a_directory() {
+        let root = scratch("file-dir");
+        write(&root.join("main.rs"), "fn old() {}\n");
+        write(&root.join("release/main.rs"), "fn new() {}\n");
+
+        let backend = PathsBackend::new(&root.join("main.rs"), &root.join("release")).unwrap();
+        let mut file = backend.collect_changed_files().unwrap().remove(0);
+        load_file_contents(&backend, &mut file, &LoadLimits::default()).unwrap();
+        assert_eq!(text(&file.old_content), "fn old() {}\n");
+        assert_eq!(text(&file.new_content), "fn new() {}\n");
+
+        let backend = PathsBackend::new(&root.join("release"), &root.join("main.rs")).unwrap();
+        assert_eq!(
+            backend.side_names()[0],
+            root.join("release/main.rs").display().to_string()
+        );
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn skips_vcs_metadata_and_compares_symlink_targets() {
+        let root = scratch("vcs");
+        let (left, right) = (root.join("v1"), root.join("v2"));
+        write(&left.join(".git/HEAD"), "ref: refs/heads/main\n");
+        write(&right.join(".git/HEAD"), "ref: refs/heads/release\n");
+        write(&left.join("lib.so.1"), "one\n");
+        write(&right.join("lib.so.1"), "one\n");
+        write(&right.join("lib.so.2"), "two\n");
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("lib.so.1", left.join("lib.so")).unwrap();
+            std::os::unix::fs::symlink("lib.so.2", right.join("lib.so")).unwrap();
+            std::os::unix::fs::symlink("lib.so.1", left.join("same.so")).unwrap();
+            std::os::unix::fs::symlink("lib.so.1", right.join("same.so")).unwrap();
+        }
+
+        let backend = PathsBackend::new(&left, &right).unwrap();
+        let paths: Vec<PathBuf> = backend
+            .collect_changed_files()
+            .unwrap()
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
+        let mut expected = vec!["lib.so.2"];
+        if cfg!(unix) {
+            expected.insert(0, "lib.so");
+        }
+        assert_eq!(
+            paths,
+            expected.iter().map(PathBuf::from).collect::<Vec<_>>()
+        );
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_directory_symlinks_but_not_loops() {
+        let root = scratch("dir-links");
+        let (left, right) = (root.join("v1"), root.join("v2"));
+        write(&left.join("real/a.txt"), "one\n");
+        write(&right.join("real/a.txt"), "two\n");
+        for side in [&left, &right] {
+            std::os::unix::fs::symlink("real", side.join("docs")).unwrap();
+            std::os::unix::fs::symlink("..", side.join("real/up")).unwrap();
+        }
+
+        let backend = PathsBackend::new(&left, &right).unwrap();
+        let mut files = backend.collect_changed_files().unwrap();
+        let paths: Vec<PathBuf> = files.iter().map(|file| file.path.clone()).collect();
+        assert_eq!(
+            paths,
+            [PathBuf::from("docs/a.txt"), PathBuf::from("real/a.txt")]
+        );
+
+        load_file_contents(&backend, &mut files[0], &LoadLimits::default()).unwrap();
+        assert_eq!(text(&files[0].old_content), "one\n");
+        assert_eq!(text(&files[0].new_content), "two\n");
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn streams_compare_across_chunks_and_short_reads() {
+        let data: Vec<u8> = (0..COMPARE_CHUNK * 2 + 100).map(|i| i as u8).collect();
+        let mut changed = data.clone();
+        *changed.last_mut().unwrap() ^= 1;
+        // A reader that stops short at 1000 bytes, as pipes and some filesystems do.
+        fn split(bytes: &[u8]) -> impl Read + '_ {
+            (&bytes[..1000]).chain(&bytes[1000..])
+        }
+
+        assert!(same_stream(split(&data), &data[..]).unwrap());
+        assert!(!same_stream(split(&data), &changed[..]).unwrap());
+        assert!(!same_stream(&data[..], &data[..data.len() - 1]).unwrap());
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::input::{parse_sequence, Action, Keymap};
+use crate::theme::{self, Theme};
+
+/// Settings read from `config.toml`; everything is optional and defaults to built-in behavior.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub keymap: Keymap,
+    /// The chosen built-in theme with the config's syntax theme and color overrides applied.
+    pub theme: Theme,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    keys: KeysSection,
+    #[serde(default)]
+    theme: ThemeSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeysSection {
+    preset: Option<String>,
+    /// Key sequence (`"g g"`, `"ctrl-d"`) to action name.
+    #[serde(default)]
+    bindings: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeSection {
+    name: Option<String>,
+    /// One of syntect's bundled syntax themes.
+    syntax: Option<String>,
+    /// A `.tmTheme` file, relative to the config file's directory.
+    syntax_file: Option<PathBuf>,
+    /// Palette slot (`insert_bg`, `gutter`, ...) to color.
+    #[serde(default)]
+    colors: BTreeMap<String, String>,
+}
+
+/// `$XDG_CONFIG_HOME/fdf/config.toml`, falling back to `~/.config/fdf/config.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("fdf").join("config.toml"))
+}
+
+/// Loads `explicit` (which must exist) or the default path (which may be absent).
+pub fn load(explicit: Option<&Path>) -> Result<Config> {
+    let (path, required) = match explicit {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok(Config::default()),
+        },
+    };
+
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound && !required => return Ok(Config::default()),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", path.display())),
+    };
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    parse(&text, dir).with_context(|| format!("invalid config file {}", path.display()))
+}
+
+/// Parses a config file's text; relative paths in it are resolved against `dir`.
+pub fn parse(text: &str, dir: &Path) -> Result<Config> {
+    let file: ConfigFile = toml::from_str(text)?;
+    let keys = file.keys;
+
+    let mut keymap = Keymap::preset(keys.preset.as_deref().unwrap_or("default"))?;
+    let mut errors = Vec::new();
+    for (sequence, action_name) in &keys.bindings {
+        let parsed = parse_sequence(sequence).and_then(|keys| {
+            let action = Action::from_name(action_name)
+                .ok_or_else(|| anyhow!("unknown action {:?}", action_name))?;
+            Ok((keys, action))
+        });
+        match parsed {
+            Ok((keys, action)) => keymap.bind(keys, action),
+            Err(err) => errors.push(format!("[keys.bindings] {:?}: {}", sequence, err)),
+        }
+    }
+    if let Err(conflicts) = keymap.validate() {
+        errors.extend(
+            conflicts
+                .to_string()
+                .lines()
+                .map(|line| format!("[keys.bindings] {}", line)),
+        );
+    }
+
+    let theme = parse_theme(file.theme, dir, &mut errors);
+
+    if errors.is_empty() {
+        Ok(Config { keymap, theme })
+    } else {
+        Err(anyhow!(errors.join("\n")))
+    }
+}
+
+fn parse_theme(section: ThemeSection, dir: &Path, errors: &mut Vec<String>) -> Theme {
+    let mut theme = match Theme::builtin(section.name.as_deref().unwrap_or("dark")) {
+        Ok(theme) => theme,
+        Err(err) => {
+            errors.push(format!("[theme] name: {}", err));
+            Theme::default()
+        }
+    };
+
+    let syntax = match (section.syntax, section.syntax_file) {
+        (Some(_), Some(_)) => Err(anyhow!("syntax and syntax_file cannot both be set")),
+        (Some(name), None) => theme::builtin_syntax(&name).map(Some),
+        (None, Some(file)) => theme::load_syntax_file(&expand_home(&file, dir)).map(Some),
+        (None, None) => Ok(None),
+    };
+    match syntax {
+        Ok(Some(syntax)) => theme.syntax = syntax,
+        Ok(None) => {}
+        Err(err) => errors.push(format!("[theme] {:#}", err)),
+    }
+
+    for (slot, color) in &section.colors {
+        let applied = theme::parse_color(color).and_then(|color| theme.colors.set(slot, color));
+        if let Err(err) = applied {
+            errors.push(format!("[theme.colors] {:?}: {}", slot, err));
+        }
+    }
+    theme
+}
+
+/// Resolves `~/...` against `$HOME` and other relative paths against `dir`.
+fn expand_home(path: &Path, dir: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => dir.join(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use ratatui::style::Color;
+
+    use crate::input::{parse_sequence, Action, Keymap, Resolution};
+
+    fn parse(text: &str) -> anyhow::Result<super::Config> {
+        super::parse(text, Path::new("."))
+    }
+
+    #[test]
+    fn empty_config_uses_default_keymap() {
+        assert_eq!(parse("").unwrap().keymap, Keymap::default());
+    }
+
+    #[test]
+    fn bindings_override_and_unbind_preset_keys() {
+        let config = parse(
+            r#"
+            [keys]
+            preset = "less"
+
+            [keys.bindings]
+            "ctrl-n" = "select_next_file"
+            "q" = "none"
+            "Z Z" = "quit"
+            "#,
+        )
+        .unwrap();
+        let resolve = |raw: &str| config.keymap.resolve(&parse_sequence(raw).unwrap());
+
+        assert_eq!(
+            resolve("ctrl-n"),
+            Resolution::Action(Action::SelectNextFile)
+        );
+        assert_eq!(resolve("down"), Resolution::Action(Action::ScrollDown));
+        assert_eq!(resolve("q"), Resolution::Unbound);
+        assert_eq!(resolve("Z Z"), Resolution::Action(Action::Quit));
+    }
+
+    #[test]
+    fn reports_every_invalid_binding_at_once() {
+        let err = parse(
+            r#"
+            [keys.bindings]
+            "hyper-x" = "quit"
+            "x" = "explode"
+            "g" = "go_bottom"
+            "#,
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("unknown key \"hyper-x\""), "{}", err);
+        assert!(err.contains("unknown action \"explode\""), "{}", err);
+        assert!(err.contains("\"g g\" can never be typed"), "{}", err);
+    }
+
+    #[test]
+    fn theme_section_picks_a_builtin_and_overrides_colors() {
+        let config = parse(
+            r##"
+            [theme]
+            name = "light"
+            syntax = "Solarized (light)"
+
+            [theme.colors]
+            insert_bg = "#001122"
+            gutter = "darkgray"
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(config.theme.name, "light");
+        assert_eq!(config.theme.colors.insert_bg, Color::Rgb(0, 0x11, 0x22));
+        assert_eq!(config.theme.colors.gutter, Color::DarkGray);
+        assert_eq!(
+            config.theme.syntax.name.as_deref(),
+            Some("Solarized (light)")
+        );
+
+        let err = parse(
+            r##"
+            [theme]
+            name = "neon"
+            syntax_file = "missing.tmTheme"
+
+            [theme.colors]
+            border = "red"
+            error = "#xyz"
+            "##,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("unknown theme \"neon\""), "{}", err);
+        assert!(err.contains("missing.tmTheme"), "{}", err);
+        assert!(err.contains("unknown color \"border\""), "{}", err);
+        assert!(err.contains("invalid color \"#xyz\""), "{}", err);
+    }
+
+    #[test]
+    fn rejects_unknown_presets_and_sections() {
+        assert!(parse("[keys]\npreset = \"emacs\"").is_err());
+        assert!(parse("[colors]\nbg = 1").is_err());
+    }
+}
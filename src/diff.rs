@@ -1,12 +1,123 @@
 use similar::{ChangeTag, DiffTag, TextDiff};
 
-use crate::model::{AlignedRow, RowKind};
+use crate::model::{AlignedRow, LineRemap, RemapBlock, RowKind};
+
+/// Number of lines per side diffed at once by [`align_chunked`].
+pub const DEGRADED_CHUNK_LINES: usize = 2_000;
+
+/// How lines are compared when aligning the two sides.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffOptions {
+    /// Lines that differ only in whitespace count as equal, as with `git diff -w`.
+    pub ignore_whitespace: bool,
+}
+
+/// Runs `align` on `old` and `new`, with whitespace removed from every line if `options`
+/// ignore it; rows then show the original lines.
+pub fn align_with(
+    options: DiffOptions,
+    old: &str,
+    new: &str,
+    align: impl Fn(&str, &str) -> Vec<AlignedRow>,
+) -> Vec<AlignedRow> {
+    if !options.ignore_whitespace {
+        return align(old, new);
+    }
+
+    let mut rows = align(&strip_whitespace(old), &strip_whitespace(new));
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let original = |lines: &[&str], no: Option<usize>| {
+        no.and_then(|no| lines.get(no - 1))
+            .map(|line| line.to_string())
+    };
+    for row in &mut rows {
+        if let Some(line) = original(&old_lines, row.left_line_no) {
+            row.left_text = line;
+        }
+        if let Some(line) = original(&new_lines, row.right_line_no) {
+            row.right_text = line;
+        }
+    }
+    rows
+}
+
+/// `text` with every whitespace character removed, keeping its line breaks.
+fn strip_whitespace(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        stripped.extend(line.chars().filter(|c| !c.is_whitespace()));
+        if line.ends_with('\n') {
+            stripped.push('\n');
+        }
+    }
+    stripped
+}
 
 pub fn align_full_file(old: &str, new: &str) -> Vec<AlignedRow> {
-    let diff = TextDiff::from_lines(old, new);
     let mut rows = Vec::new();
-    let mut left_no = 1usize;
-    let mut right_no = 1usize;
+    align_into(old, new, 1, 1, &mut rows);
+    finish_rows(rows)
+}
+
+/// Aligns oversized inputs by diffing fixed-size line windows independently.
+///
+/// Alignment is only exact within a window, but the cost stays bounded for
+/// generated files where a whole-file diff would stall the UI.
+pub fn align_chunked(old: &str, new: &str, chunk_lines: usize) -> Vec<AlignedRow> {
+    let chunk_lines = chunk_lines.max(1);
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let chunk_count = old_lines.len().max(new_lines.len()).div_ceil(chunk_lines);
+
+    let mut rows = Vec::new();
+    for chunk in 0..chunk_count {
+        let start = chunk * chunk_lines;
+        let old_chunk = window(&old_lines, start, chunk_lines).concat();
+        let new_chunk = window(&new_lines, start, chunk_lines).concat();
+        let left_start = start.min(old_lines.len()) + 1;
+        let right_start = start.min(new_lines.len()) + 1;
+        align_into(&old_chunk, &new_chunk, left_start, right_start, &mut rows);
+    }
+
+    finish_rows(rows)
+}
+
+/// Diffs two versions of the same side of a file to relate their line numbers.
+pub fn line_remap(before: &str, after: &str) -> LineRemap {
+    let diff = TextDiff::from_lines(before, after);
+    let blocks = diff
+        .ops()
+        .iter()
+        .map(|op| {
+            let (tag, old_range, new_range) = op.as_tag_tuple();
+            RemapBlock {
+                old_start: old_range.start + 1,
+                old_len: old_range.len(),
+                new_start: new_range.start + 1,
+                new_len: new_range.len(),
+                equal: tag == DiffTag::Equal,
+            }
+        })
+        .collect();
+
+    LineRemap { blocks }
+}
+
+fn window<'a>(lines: &[&'a str], start: usize, len: usize) -> Vec<&'a str> {
+    lines.iter().skip(start).take(len).copied().collect()
+}
+
+fn align_into(
+    old: &str,
+    new: &str,
+    left_start: usize,
+    right_start: usize,
+    rows: &mut Vec<AlignedRow>,
+) {
+    let diff = TextDiff::from_lines(old, new);
+    let mut left_no = left_start;
+    let mut right_no = right_start;
 
     for op in diff.ops() {
         let old_chunk: Vec<String> = diff
@@ -62,7 +173,9 @@ pub fn align_full_file(old: &str, new: &str) -> Vec<AlignedRow> {
             }
         }
     }
+}
 
+fn finish_rows(mut rows: Vec<AlignedRow>) -> Vec<AlignedRow> {
     if rows.is_empty() {
         rows.push(AlignedRow {
             left_line_no: None,
@@ -85,3 +198,80 @@ fn clean_line(mut line: String) -> String {
     }
     line
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{align_chunked, align_full_file, align_with, line_remap, DiffOptions};
+    use crate::model::RowKind;
+
+    #[test]
+    fn ignoring_whitespace_aligns_reindented_lines_as_equal() {
+        let old = "fn a() {\n  x();\n}\n";
+        let new = "fn a() {\n    x( );\n\ty();\n}\n";
+        let options = DiffOptions {
+            ignore_whitespace: true,
+        };
+
+        let rows = align_with(options, old, new, align_full_file);
+        let kinds: Vec<RowKind> = rows.iter().map(|row| row.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                RowKind::Equal,
+                RowKind::Equal,
+                RowKind::Insert,
+                RowKind::Equal
+            ]
+        );
+        assert_eq!(rows[1].left_text, "  x();");
+        assert_eq!(rows[1].right_text, "    x( );");
+        assert_eq!(rows[2].right_text, "\ty();");
+
+        let rows = align_with(DiffOptions::default(), old, new, align_full_file);
+        assert_eq!(rows[1].kind, RowKind::Changed);
+    }
+
+    #[test]
+    fn chunked_alignment_matches_full_alignment_for_in_place_edits() {
+        let old = "a\nb\nc\nd\ne\n";
+        let new = "a\nB\nc\nd\nE\n";
+
+        let full = align_full_file(old, new);
+        let chunked = align_chunked(old, new, 2);
+
+        let kinds = |rows: &[crate::model::AlignedRow]| -> Vec<RowKind> {
+            rows.iter().map(|row| row.kind).collect()
+        };
+        assert_eq!(kinds(&chunked), kinds(&full));
+    }
+
+    #[test]
+    fn chunked_alignment_continues_line_numbers_across_chunks() {
+        let old = "1\n2\n3\n4\n5\n";
+        let new = "1\n2\n3\n4\n5\n6\n";
+
+        let rows = align_chunked(old, new, 2);
+        let last = rows.last().expect("rows should not be empty");
+
+        assert_eq!(rows.len(), 6);
+        assert_eq!(rows[4].left_line_no, Some(5));
+        assert_eq!(rows[4].right_line_no, Some(5));
+        assert_eq!(last.left_line_no, None);
+        assert_eq!(last.right_line_no, Some(6));
+        assert_eq!(last.kind, RowKind::Insert);
+    }
+
+    #[test]
+    fn line_remap_follows_lines_shifted_by_insertions_and_edits() {
+        let before = "a\nb\nc\nd\n";
+        let after = "new\nnew\na\nB\nc\nd\n";
+
+        let remap = line_remap(before, after);
+
+        assert_eq!(remap.map(1), 3);
+        assert_eq!(remap.map(2), 4);
+        assert_eq!(remap.map(4), 6);
+        assert_eq!(remap.map(9), 6);
+        assert_eq!(remap.changed_lines().collect::<Vec<_>>(), vec![1, 2, 4]);
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use similar::TextDiff;
+
+use crate::git::GitBackend;
+use crate::model::{ChangedFile, ContentData, LoadLimits};
+
+/// Lines of context around each hunk, as `git diff` uses.
+const CONTEXT_LINES: usize = 3;
+
+/// What `:export patch` wrote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportSummary {
+    pub files: usize,
+    /// Files left out: binary ones, and those from a patch whose full sides are unknown.
+    pub skipped: usize,
+}
+
+/// Loads `files` through `git`, ignoring the size limit, and writes their changes to `path`
+/// as a unified diff that `git apply` accepts.
+pub fn write_patch(
+    git: &dyn GitBackend,
+    files: Vec<ChangedFile>,
+    path: &Path,
+) -> Result<ExportSummary> {
+    let mut patch = String::new();
+    let mut summary = ExportSummary {
+        files: 0,
+        skipped: 0,
+    };
+    for mut file in files {
+        file.force_load = true;
+        git.load_contents(&mut file, &LoadLimits::default())
+            .with_context(|| format!("failed to load {}", file.path.display()))?;
+        // Sources that set rows themselves only know the hunks, and `git apply` rejects
+        // "Binary files differ" without the blob ids of an `index` line.
+        let (None, Some(ContentData::Text(old)), Some(ContentData::Text(new))) =
+            (&file.aligned_rows, &file.old_content, &file.new_content)
+        else {
+            summary.skipped += 1;
+            continue;
+        };
+        if write_file_diff(&mut patch, &file, old, new) {
+            summary.files += 1;
+        }
+    }
+
+    fs::write(path, patch).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(summary)
+}
+
+/// Appends the `diff --git` section for `file`, whose sides are `old` and `new`; false if
+/// they are equal.
+fn write_file_diff(patch: &mut String, file: &ChangedFile, old: &str, new: &str) -> bool {
+    if old == new && file.renamed_from.is_none() {
+        return false;
+    }
+
+    // The sides that lack the file, not the status, tell additions from deletions: a file
+    // added to the index is not untracked, and one deleted only in the worktree still is in
+    // the index `:mode staged` compares.
+    let [old_exists, new_exists] = file.sides_exist;
+    let new_name = file.path.to_string_lossy();
+    let old_name = file
+        .renamed_from
+        .as_deref()
+        .map_or_else(|| new_name.clone(), Path::to_string_lossy);
+    let old_label = if old_exists {
+        format!("a/{}", old_name)
+    } else {
+        "/dev/null".to_string()
+    };
+    let new_label = if new_exists {
+        format!("b/{}", new_name)
+    } else {
+        "/dev/null".to_string()
+    };
+
+    let _ = writeln!(patch, "diff --git a/{} b/{}", old_name, new_name);
+    // `git apply` needs the mode line to create or delete a file; modes are not tracked here.
+    if !old_exists {
+        let _ = writeln!(patch, "new file mode 100644");
+    } else if !new_exists {
+        let _ = writeln!(patch, "deleted file mode 100644");
+    }
+    if let Some(from) = &file.renamed_from {
+        let _ = writeln!(patch, "rename from {}", from.display());
+        let _ = writeln!(patch, "rename to {}", file.path.display());
+    }
+    if old != new {
+        let diff = TextDiff::from_lines(old, new);
+        let _ = write!(
+            patch,
+            "{}",
+            diff.unified_diff()
+                .context_radius(CONTEXT_LINES)
+                .header(&old_label, &new_label)
+        );
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    use super::write_patch;
+    use crate::git::tests::fixture_repo;
+    use crate::git::{CliBackend, GitBackend};
+    use crate::mode::{CompareMode, ModeBackend};
+
+    fn git(dir: &PathBuf, args: &[&str]) {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .expect("git runs");
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn exported_patch_applies_to_head() {
+        let root = fixture_repo("export");
+        fs::write(root.join("added.txt"), "added\n").unwrap();
+        git(&root, &["add", "added.txt"]);
+        fs::write(root.join("image.bin"), [0u8, 0xff, 0xfe]).unwrap();
+        let backend = CliBackend::new(root.clone());
+        let files = backend.collect_changed_files().unwrap();
+        let out = std::env::temp_dir().join(format!("fdf-export-{}.patch", std::process::id()));
+
+        let summary = write_patch(&backend, files, &out).unwrap();
+        assert_eq!((summary.files, summary.skipped), (5, 1));
+        let patch = fs::read_to_string(&out).unwrap();
+        assert!(
+            patch.contains("--- /dev/null\n+++ b/dir/untracked.txt\n"),
+            "{}",
+            patch
+        );
+        assert!(
+            patch.contains("new file mode 100644\n--- /dev/null\n+++ b/added.txt\n"),
+            "{}",
+            patch
+        );
+        assert!(!patch.contains("image.bin"), "{}", patch);
+        assert!(patch.contains("-one\n+one!\n"), "{}", patch);
+        assert!(
+            patch.contains("deleted file mode 100644\n--- a/deleted.txt\n+++ /dev/null\n"),
+            "{}",
+            patch
+        );
+
+        // The worktree's changes, applied to a clean checkout of HEAD, reproduce it.
+        let clean = root.with_file_name(format!("fdf-git-export-clean-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&clean);
+        git(
+            &root,
+            &["worktree", "add", "-q", "--detach", clean.to_str().unwrap()],
+        );
+        git(&clean, &["apply", out.to_str().unwrap()]);
+        for path in [
+            "modified.txt",
+            "staged.txt",
+            "dir/untracked.txt",
+            "added.txt",
+        ] {
+            assert_eq!(
+                fs::read(clean.join(path)).unwrap(),
+                fs::read(root.join(path)).unwrap(),
+                "{}",
+                path
+            );
+        }
+        assert!(!clean.join("deleted.txt").exists());
+        assert!(!clean.join("image.bin").exists());
+        let _ = fs::remove_file(&out);
+        let _ = fs::remove_dir_all(&clean);
+    }
+
+    #[test]
+    fn staged_export_keeps_files_deleted_only_in_the_worktree() {
+        let root = fixture_repo("export-staged");
+        fs::write(root.join("same.txt"), "four!\n").unwrap();
+        git(&root, &["add", "same.txt"]);
+        fs::remove_file(root.join("same.txt")).unwrap();
+        let backend = ModeBackend::wrap(
+            std::sync::Arc::new(CliBackend::new(root.clone())),
+            CompareMode::Staged,
+        );
+        let files = backend.collect_changed_files().unwrap();
+        let out =
+            std::env::temp_dir().join(format!("fdf-export-staged-{}.patch", std::process::id()));
+
+        write_patch(backend.as_ref(), files, &out).unwrap();
+        let patch = fs::read_to_string(&out).unwrap();
+        assert!(
+            patch.contains("--- a/same.txt\n+++ b/same.txt\n@@ -1 +1 @@\n-four\n+four!\n"),
+            "{}",
+            patch
+        );
+        assert!(!patch.contains("deleted file mode"), "{}", patch);
+        let _ = fs::remove_file(&out);
+    }
+}
@@ -1,175 +1,868 @@
+use std::ffi::OsString;
+use std::io::{self, BufRead, BufReader, Read, Write};
+#[cfg(unix)]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 
-use crate::model::{ChangedFile, ContentData, FileStatus};
+use crate::model::{ChangedFile, ContentData, FileStatus, LoadLimits};
 
-pub fn repo_root() -> Result<PathBuf> {
-    let out = Command::new("git")
-        .args(["rev-parse", "--show-toplevel"])
-        .output()
-        .context("failed to run git rev-parse")?;
+/// Repository access needed by the app: discovery, status and reading blobs at `HEAD`.
+///
+/// [`CliBackend`] shells out to `git`; with the `libgit2` cargo feature the in-process
+/// `Libgit2Backend` is preferred and the CLI is kept as fallback. Sources outside a repository
+/// (two paths given on the command line, a patch piped in by git) implement it too: the "`HEAD`"
+/// side is then whatever they compare against.
+pub trait GitBackend: Send + Sync {
+    /// Top-level directory of the worktree.
+    fn repo_root(&self) -> &Path;
 
-    if !out.status.success() {
-        return Err(anyhow!("not inside a git repository"));
+    /// Absolute path of the repository's git directory (`.git`, or the worktree's admin dir).
+    fn git_dir(&self) -> Result<PathBuf>;
+
+    /// Changed, staged and untracked files, sorted by path.
+    fn collect_changed_files(&self) -> Result<Vec<ChangedFile>>;
+
+    /// Size of `path` at `HEAD` in bytes, or 0 if it does not exist there.
+    fn head_blob_size(&self, path: &Path) -> Result<u64>;
+
+    /// Contents of `path` at `HEAD`, or `None` if it does not exist there.
+    fn read_head_blob(&self, path: &Path) -> Result<Option<Vec<u8>>>;
+
+    /// Size of `path` in the index in bytes, or 0 if it is not staged there.
+    fn index_blob_size(&self, _path: &Path) -> Result<u64> {
+        Ok(0)
+    }
+
+    /// Contents of `path` in the index, or `None` if it is not there or the source has no
+    /// index.
+    fn read_index_blob(&self, _path: &Path) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Contents of the blob with the (possibly abbreviated) object id `id`, or `None` if the
+    /// object database lacks it.
+    fn read_blob(&self, _id: &str) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Labels for the left and right side, shown in pane titles and the status bar.
+    fn side_names(&self) -> [String; 2] {
+        ["HEAD".to_string(), "worktree".to_string()]
+    }
+
+    /// Fills in `old_content` and `new_content`. Sources that only know parts of each side
+    /// also set `aligned_rows`, which is then used as is, and `hunks_only`.
+    fn load_contents(&self, file: &mut ChangedFile, limits: &LoadLimits) -> Result<()> {
+        load_file_contents(self, file, limits)
+    }
+}
+
+/// Opens the repository containing `start` with the best available backend.
+pub fn discover(start: &Path) -> Result<Arc<dyn GitBackend>> {
+    #[cfg(feature = "libgit2")]
+    if let Ok(backend) = crate::libgit2::Libgit2Backend::discover(start) {
+        return Ok(Arc::new(backend));
     }
 
-    let root = String::from_utf8(out.stdout).context("git output was not valid utf-8")?;
-    Ok(PathBuf::from(root.trim()))
+    Ok(Arc::new(CliBackend::discover(start)?))
 }
 
-pub fn collect_changed_files(repo_root: &Path) -> Result<Vec<ChangedFile>> {
-    let out = Command::new("git")
-        .arg("status")
-        .arg("--porcelain=v2")
-        .arg("--untracked-files=all")
-        .arg("-z")
-        .current_dir(repo_root)
-        .output()
-        .context("failed to run git status")?;
+/// Backend that runs the `git` executable.
+///
+/// Blob reads and size probes go through long-lived `git cat-file --batch` and
+/// `--batch-check` processes instead of one fork per file.
+#[derive(Debug)]
+pub struct CliBackend {
+    repo_root: PathBuf,
+    batch: Mutex<BatchState>,
+}
 
-    if !out.status.success() {
-        return Err(anyhow!("git status failed"));
+#[derive(Debug, Default)]
+struct BatchState {
+    /// `HEAD` commit as of the last status; the outer `None` means not resolved yet and the
+    /// inner `None` an unborn branch. Specs use the commit id because a long-lived
+    /// `cat-file` may keep resolving `HEAD` to where it pointed when it started.
+    head: Option<Option<String>>,
+    contents: Option<CatFile>,
+    info: Option<CatFile>,
+}
+
+impl CliBackend {
+    pub fn new(repo_root: PathBuf) -> Self {
+        Self {
+            repo_root,
+            batch: Mutex::default(),
+        }
     }
 
-    let fields: Vec<&[u8]> = out
-        .stdout
-        .split(|b| *b == 0)
-        .filter(|entry| !entry.is_empty())
-        .collect();
+    pub fn discover(start: &Path) -> Result<Self> {
+        let out = match Command::new("git")
+            .args(["rev-parse", "--show-toplevel"])
+            .current_dir(start)
+            .output()
+        {
+            Ok(out) => out,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                bail!("git was not found on PATH; fdf runs it to read the repository, so install git or add it to PATH")
+            }
+            Err(err) => return Err(err).context("failed to run git rev-parse"),
+        };
 
-    let mut files = Vec::new();
-    let mut i = 0usize;
-    while i < fields.len() {
-        let entry = fields[i];
-        if entry.starts_with(b"#") {
-            i += 1;
-            continue;
+        if !out.status.success() {
+            let dir = start.canonicalize().unwrap_or_else(|_| start.to_path_buf());
+            return Err(discover_error(&dir, &String::from_utf8_lossy(&out.stderr)));
         }
 
-        let text = String::from_utf8_lossy(entry);
+        let root = String::from_utf8(out.stdout).context("git output was not valid utf-8")?;
+        Ok(Self::new(PathBuf::from(root.trim())))
+    }
 
-        if text.starts_with("? ") {
-            let path = text[2..].to_string();
-            if path.ends_with('/') {
-                i += 1;
-                continue;
+    fn git(&self) -> Command {
+        let mut command = Command::new("git");
+        command.current_dir(&self.repo_root);
+        command
+    }
+
+    fn batch(&self) -> MutexGuard<'_, BatchState> {
+        self.batch.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn resolve_head(&self) -> Result<Option<String>> {
+        let out = self
+            .git()
+            .args(["rev-parse", "--verify", "--quiet", "HEAD^{commit}"])
+            .output()
+            .context("failed to run git rev-parse")?;
+
+        if !out.status.success() {
+            return Ok(None);
+        }
+        Ok(Some(
+            String::from_utf8_lossy(&out.stdout).trim().to_string(),
+        ))
+    }
+
+    /// Object spec for `path` at `HEAD`, from the path's raw bytes, or `None` if `HEAD` is
+    /// unborn.
+    fn head_spec(&self, batch: &mut BatchState, path: &Path) -> Result<Option<Vec<u8>>> {
+        if batch.head.is_none() {
+            batch.head = Some(self.resolve_head()?);
+        }
+        Ok(batch.head.clone().flatten().map(|head| {
+            let mut spec = format!("{}:", head).into_bytes();
+            spec.extend(path_bytes(path));
+            spec
+        }))
+    }
+
+    /// Object spec for the stage-0 index entry of `path`.
+    fn index_spec(path: &Path) -> Vec<u8> {
+        let mut spec = b":".to_vec();
+        spec.extend(path_bytes(path));
+        spec
+    }
+
+    fn blob_size(&self, mut batch: MutexGuard<'_, BatchState>, spec: &[u8]) -> Result<u64> {
+        if !CatFile::accepts(spec) {
+            return Ok(0);
+        }
+        let size = self.query(&mut batch.info, "--batch-check", spec, |_, size| Ok(size))?;
+        Ok(size.unwrap_or(0))
+    }
+
+    fn read_spec(
+        &self,
+        mut batch: MutexGuard<'_, BatchState>,
+        spec: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>> {
+        if !CatFile::accepts(&spec) {
+            drop(batch);
+            let out = self
+                .git()
+                .arg("show")
+                .arg(os_string(spec))
+                .output()
+                .context("failed to run git show")?;
+            // The path may not exist there (e.g. a file added to the index only).
+            return Ok(out.status.success().then_some(out.stdout));
+        }
+
+        self.query(&mut batch.contents, "--batch", &spec, CatFile::read_body)
+    }
+
+    /// Asks the batch process in `slot` about `spec`, restarting it once if it died.
+    fn query<T>(
+        &self,
+        slot: &mut Option<CatFile>,
+        mode: &str,
+        spec: &[u8],
+        read: impl Fn(&mut CatFile, u64) -> Result<T>,
+    ) -> Result<Option<T>> {
+        for _ in 0..2 {
+            if slot.is_none() {
+                *slot = Some(CatFile::spawn(&self.repo_root, mode)?);
+            }
+            let process = slot.as_mut().expect("batch process spawned above");
+            let answer = process
+                .request(spec)
+                .and_then(|size| size.map(|size| read(process, size)).transpose());
+            match answer {
+                Ok(answer) => return Ok(answer),
+                Err(_) => *slot = None,
             }
-            files.push(ChangedFile::new(
-                PathBuf::from(path),
-                FileStatus {
-                    staged: false,
-                    unstaged: true,
-                    untracked: true,
-                },
-            ));
-            i += 1;
-            continue;
         }
+        Err(anyhow!(
+            "git cat-file {} failed for {}",
+            mode,
+            String::from_utf8_lossy(spec)
+        ))
+    }
+}
 
-        if text.starts_with("1 ") || text.starts_with("2 ") || text.starts_with("u ") {
-            let parts: Vec<&str> = text.split_whitespace().collect();
-            if parts.len() < 2 {
-                i += 1;
-                continue;
+impl GitBackend for CliBackend {
+    fn repo_root(&self) -> &Path {
+        &self.repo_root
+    }
+
+    fn git_dir(&self) -> Result<PathBuf> {
+        let out = self
+            .git()
+            .args(["rev-parse", "--absolute-git-dir"])
+            .output()
+            .context("failed to run git rev-parse")?;
+
+        if !out.status.success() {
+            return Err(anyhow!("git rev-parse --absolute-git-dir failed"));
+        }
+
+        let dir = String::from_utf8(out.stdout).context("git output was not valid utf-8")?;
+        Ok(PathBuf::from(dir.trim()))
+    }
+
+    fn collect_changed_files(&self) -> Result<Vec<ChangedFile>> {
+        // Without optional locks `git status` never rewrites the index, which would otherwise
+        // wake the file watcher and trigger another refresh.
+        let out = self
+            .git()
+            .arg("--no-optional-locks")
+            .arg("status")
+            .arg("--porcelain=v2")
+            .arg("--untracked-files=all")
+            .arg("-z")
+            .output()
+            .context("failed to run git status")?;
+
+        if !out.status.success() {
+            return Err(anyhow!("git status failed"));
+        }
+
+        // `HEAD` may have moved since the last status; resolve it again on the next read.
+        self.batch().head = None;
+        Ok(parse_porcelain_v2(&out.stdout))
+    }
+
+    fn head_blob_size(&self, path: &Path) -> Result<u64> {
+        let mut batch = self.batch();
+        let Some(spec) = self.head_spec(&mut batch, path)? else {
+            return Ok(0);
+        };
+        self.blob_size(batch, &spec)
+    }
+
+    fn read_head_blob(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        let mut batch = self.batch();
+        let Some(spec) = self.head_spec(&mut batch, path)? else {
+            return Ok(None);
+        };
+        self.read_spec(batch, spec)
+    }
+
+    fn index_blob_size(&self, path: &Path) -> Result<u64> {
+        self.blob_size(self.batch(), &Self::index_spec(path))
+    }
+
+    fn read_index_blob(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        self.read_spec(self.batch(), Self::index_spec(path))
+    }
+
+    fn read_blob(&self, id: &str) -> Result<Option<Vec<u8>>> {
+        if !CatFile::accepts(id.as_bytes()) {
+            return Ok(None);
+        }
+        let mut batch = self.batch();
+        self.query(
+            &mut batch.contents,
+            "--batch",
+            id.as_bytes(),
+            CatFile::read_body,
+        )
+    }
+}
+
+/// A `git cat-file --batch` or `--batch-check` child process.
+#[derive(Debug)]
+struct CatFile {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    /// `--batch` follows each header with the object body; `--batch-check` does not.
+    sends_bodies: bool,
+}
+
+impl CatFile {
+    fn spawn(repo_root: &Path, mode: &str) -> Result<Self> {
+        let mut child = Command::new("git")
+            .args(["cat-file", mode])
+            .current_dir(repo_root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("failed to start git cat-file {}", mode))?;
+
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+            sends_bodies: mode == "--batch",
+        })
+    }
+
+    /// The batch protocol is line based, so specs containing newlines need a one-off command.
+    fn accepts(spec: &[u8]) -> bool {
+        !spec.contains(&b'\n')
+    }
+
+    /// Sends `spec` and parses the header; returns the object size or `None` if missing.
+    fn request(&mut self, spec: &[u8]) -> Result<Option<u64>> {
+        self.stdin.write_all(spec)?;
+        self.stdin.write_all(b"\n")?;
+        self.stdin.flush()?;
+
+        let mut header = String::new();
+        if self.stdout.read_line(&mut header)? == 0 {
+            return Err(anyhow!("git cat-file exited"));
+        }
+
+        let header = header.trim_end();
+        if header.ends_with(" missing") || header.ends_with(" ambiguous") {
+            return Ok(None);
+        }
+
+        // "<oid> <type> <size>"
+        let mut fields = header.rsplitn(3, ' ');
+        let size = fields
+            .next()
+            .and_then(|size| size.parse().ok())
+            .ok_or_else(|| anyhow!("unexpected git cat-file header {:?}", header))?;
+        match fields.next() {
+            Some("blob") => Ok(Some(size)),
+            // Trees and submodule commits have no file contents; skip their body.
+            Some(_) => {
+                if self.sends_bodies {
+                    self.read_body(size)?;
+                }
+                Ok(None)
             }
+            None => Err(anyhow!("unexpected git cat-file header {:?}", header)),
+        }
+    }
 
-            let xy = parts[1];
-            let (staged, unstaged) = parse_xy(xy);
+    fn read_body(&mut self, size: u64) -> Result<Vec<u8>> {
+        let mut body = vec![0; size as usize + 1];
+        self.stdout.read_exact(&mut body)?;
+        body.pop();
+        Ok(body)
+    }
+}
 
-            let path = if text.starts_with("2 ") {
-                // For rename/copy entries in -z mode, current path is in this entry and
-                // original path is the next NUL field.
-                let p = parts.last().copied().unwrap_or("");
-                i += 1; // consume current entry
-                if i < fields.len() {
-                    i += 1; // consume orig path entry
+impl Drop for CatFile {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn parse_porcelain_v2(stdout: &[u8]) -> Vec<ChangedFile> {
+    let mut fields = stdout.split(|b| *b == 0).filter(|entry| !entry.is_empty());
+
+    let mut files = Vec::new();
+    while let Some(entry) = fields.next() {
+        let rest = entry.get(2..).unwrap_or_default();
+        // Ordinary, rename/copy and unmerged entries have 6, 7 and 8 fields between `XY` and
+        // the path, which may itself contain spaces.
+        let fields_before_path = match entry[0] {
+            b'?' => {
+                if !rest.ends_with(b"/") {
+                    let status = FileStatus {
+                        unstaged: true,
+                        untracked: true,
+                        ..FileStatus::default()
+                    };
+                    files.push(ChangedFile::new(path_from_bytes(rest), status));
                 }
-                p.to_string()
-            } else {
-                let p = parts.last().copied().unwrap_or("").to_string();
-                i += 1;
-                p
-            };
-
-            if !path.is_empty() {
-                files.push(ChangedFile::new(
-                    PathBuf::from(path),
-                    FileStatus {
-                        staged,
-                        unstaged,
-                        untracked: false,
-                    },
-                ));
+                continue;
             }
-            continue;
+            b'1' => 6,
+            b'2' => 7,
+            b'u' => 8,
+            _ => continue,
+        };
+        if entry[0] == b'2' {
+            // In -z mode the original path of a rename or copy is the next NUL field.
+            fields.next();
         }
 
-        i += 1;
+        let mut parts = rest.splitn(fields_before_path + 2, |b| *b == b' ');
+        let xy = parts.next().unwrap_or_default();
+        let Some(path) = parts
+            .nth(fields_before_path)
+            .filter(|path| !path.is_empty())
+        else {
+            continue;
+        };
+        let (staged, unstaged) = parse_xy(xy);
+        // An unmerged path with a `D` side still has the conflicted file in the worktree.
+        let merged = entry[0] != b'u';
+        let deleted_in_index = merged && xy.first() == Some(&b'D');
+        let deleted_in_worktree = merged && xy.get(1) == Some(&b'D');
+        let status = FileStatus {
+            staged,
+            unstaged,
+            deleted: deleted_in_index || deleted_in_worktree,
+            deleted_in_index,
+            deleted_in_worktree,
+            ..FileStatus::default()
+        };
+        files.push(ChangedFile::new(path_from_bytes(path), status));
     }
 
     files.sort_by(|a, b| a.path.cmp(&b.path));
-    Ok(files)
+    files
 }
 
-pub fn load_file_contents(repo_root: &Path, file: &mut ChangedFile) -> Result<()> {
+pub fn load_file_contents<G: GitBackend + ?Sized>(
+    git: &G,
+    file: &mut ChangedFile,
+    limits: &LoadLimits,
+) -> Result<()> {
+    let repo_root = git.repo_root();
+    if !file.force_load {
+        let old_bytes = if file.status.untracked {
+            0
+        } else {
+            git.head_blob_size(&file.path)?
+        };
+        let new_bytes = worktree_size(repo_root, &file.path);
+
+        if old_bytes.max(new_bytes) > limits.max_file_bytes {
+            file.old_content = Some(ContentData::TooLarge { bytes: old_bytes });
+            file.new_content = Some(ContentData::TooLarge { bytes: new_bytes });
+            return Ok(());
+        }
+    }
+
     let old = if file.status.untracked {
-        ContentData::Text(String::new())
+        None
     } else {
-        read_head_content(repo_root, &file.path)?
+        git.read_head_blob(&file.path)?.map(bytes_to_content)
     };
     let new = read_worktree_content(repo_root, &file.path)?;
 
-    file.old_content = Some(old);
-    file.new_content = Some(new);
+    file.sides_exist = [old.is_some(), new.is_some()];
+    file.old_content = Some(old.unwrap_or_else(|| ContentData::Text(String::new())));
+    file.new_content = Some(new.unwrap_or_else(|| ContentData::Text(String::new())));
     Ok(())
 }
 
-fn parse_xy(xy: &str) -> (bool, bool) {
-    let mut chars = xy.chars();
-    let x = chars.next().unwrap_or('.');
-    let y = chars.next().unwrap_or('.');
+fn parse_xy(xy: &[u8]) -> (bool, bool) {
+    let x = xy.first().copied().unwrap_or(b'.');
+    let y = xy.get(1).copied().unwrap_or(b'.');
 
-    let staged = x != '.' && x != ' ';
-    let unstaged = y != '.' && y != ' ';
+    let staged = x != b'.' && x != b' ';
+    let unstaged = y != b'.' && y != b' ';
     (staged, unstaged)
 }
 
-fn read_head_content(repo_root: &Path, path: &Path) -> Result<ContentData> {
-    let spec = format!("HEAD:{}", path.to_string_lossy());
-    let out = Command::new("git")
-        .arg("show")
-        .arg(spec)
-        .current_dir(repo_root)
-        .output()
-        .context("failed to run git show")?;
+/// Git prints and takes paths as raw bytes, which need not be UTF-8 on unix.
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(OsString::from_vec(bytes.to_vec()))
+}
 
-    if !out.status.success() {
-        // File may not exist at HEAD (e.g. newly added file in index only).
-        return Ok(ContentData::Text(String::new()));
-    }
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
 
-    Ok(bytes_to_content(out.stdout))
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.as_os_str().as_bytes().to_vec()
 }
 
-fn read_worktree_content(repo_root: &Path, path: &Path) -> Result<ContentData> {
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn os_string(bytes: Vec<u8>) -> OsString {
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+fn os_string(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn worktree_size(repo_root: &Path, path: &Path) -> u64 {
+    std::fs::metadata(repo_root.join(path))
+        .ok()
+        .filter(|meta| meta.is_file())
+        .map(|meta| meta.len())
+        .unwrap_or(0)
+}
+
+/// Advice for the usual reasons `git rev-parse --show-toplevel` fails in `dir`.
+fn discover_error(dir: &Path, stderr: &str) -> anyhow::Error {
+    let stderr = stderr.trim();
+    let dir = dir.display();
+
+    if stderr.contains("dubious ownership") {
+        // git names the repository it refused as `... repository at '<path>'`.
+        let repo = stderr
+            .split_once("repository at '")
+            .and_then(|(_, rest)| rest.split_once('\''))
+            .map_or_else(|| dir.to_string(), |(path, _)| path.to_string());
+        return anyhow!(
+            "git refuses to read {} because another user owns it\n\
+             if you trust it, run: git config --global --add safe.directory {}",
+            repo,
+            repo
+        );
+    }
+    if stderr.contains("must be run in a work tree") {
+        return anyhow!(
+            "{} is in a bare repository or a .git directory, which has no worktree to compare\n\
+             run fdf from a checkout; `git worktree add <path>` creates one for a bare repository",
+            dir
+        );
+    }
+    if let Some(admin_dir) = stderr
+        .split_once("not a git repository: ")
+        .map(|(_, path)| path.trim())
+        .filter(|path| path.contains("/worktrees/"))
+    {
+        return anyhow!(
+            "{} is a linked worktree whose git directory {} no longer exists\n\
+             run `git worktree repair {}` from the main worktree, or `git worktree prune` and add it again",
+            dir,
+            admin_dir,
+            dir
+        );
+    }
+    if stderr.contains("not a git repository") {
+        return anyhow!(
+            "{} is not inside a git repository\n\
+             cd into a repository to compare its worktree with HEAD",
+            dir
+        );
+    }
+    anyhow!("git rev-parse failed in {}: {}", dir, stderr)
+}
+
+/// Contents of `path` in the worktree, or `None` if it does not exist there.
+fn read_worktree_content(repo_root: &Path, path: &Path) -> Result<Option<ContentData>> {
     let full = repo_root.join(path);
     if full.is_dir() {
-        return Ok(ContentData::Text("[directory]".to_string()));
+        return Ok(Some(ContentData::Text("[directory]".to_string())));
     }
     match std::fs::read(&full) {
-        Ok(bytes) => Ok(bytes_to_content(bytes)),
-        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-            Ok(ContentData::Text(String::new()))
-        }
+        Ok(bytes) => Ok(Some(bytes_to_content(bytes))),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
         Err(err) => Err(err).with_context(|| format!("failed to read {:?}", full)),
     }
 }
 
-fn bytes_to_content(bytes: Vec<u8>) -> ContentData {
+pub fn bytes_to_content(bytes: Vec<u8>) -> ContentData {
     match String::from_utf8(bytes) {
         Ok(text) => ContentData::Text(text),
         Err(_) => ContentData::Binary,
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    use super::{discover_error, load_file_contents, CliBackend, GitBackend};
+    use crate::model::{ContentData, FileStatus, LoadLimits};
+
+    pub(crate) fn git(repo: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args([
+                "-c",
+                "user.name=fdf",
+                "-c",
+                "user.email=fdf@example.com",
+                "-c",
+                "commit.gpgsign=false",
+            ])
+            .args(args)
+            .current_dir(repo)
+            .status()
+            .expect("git should run");
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    /// Builds a repository with one file in each interesting state.
+    pub(crate) fn fixture_repo(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("fdf-git-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).expect("create fixture dir");
+        git(&root, &["init", "-q"]);
+
+        fs::write(root.join("modified.txt"), "one\n").expect("write fixture");
+        fs::write(root.join("staged.txt"), "two\n").expect("write fixture");
+        fs::write(root.join("deleted.txt"), "three\n").expect("write fixture");
+        fs::write(root.join("same.txt"), "four\n").expect("write fixture");
+        fs::write(root.join(".gitignore"), "*.log\n").expect("write fixture");
+        git(&root, &["add", "."]);
+        git(&root, &["commit", "-q", "-m", "initial"]);
+
+        fs::write(root.join("modified.txt"), "one!\n").expect("write fixture");
+        fs::write(root.join("staged.txt"), "two!\n").expect("write fixture");
+        git(&root, &["add", "staged.txt"]);
+        fs::remove_file(root.join("deleted.txt")).expect("delete fixture");
+        fs::create_dir_all(root.join("dir")).expect("create fixture dir");
+        fs::write(root.join("dir/untracked.txt"), "new\n").expect("write fixture");
+        fs::write(root.join("ignored.log"), "noise\n").expect("write fixture");
+
+        root.canonicalize().expect("fixture path")
+    }
+
+    /// Shared expectations every backend must meet on [`fixture_repo`].
+    pub(crate) fn assert_backend_reads_fixture(backend: &dyn GitBackend, root: &Path) {
+        assert_eq!(backend.repo_root(), root);
+        assert_eq!(
+            backend.git_dir().expect("git dir").canonicalize().ok(),
+            root.join(".git").canonicalize().ok()
+        );
+
+        let files = backend.collect_changed_files().expect("status");
+        let summary: Vec<(String, &str)> = files
+            .iter()
+            .map(|file| (file.path.display().to_string(), file.status.indicator()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("deleted.txt".to_string(), "[D]"),
+                ("dir/untracked.txt".to_string(), "[N]"),
+                ("modified.txt".to_string(), "[U]"),
+                ("staged.txt".to_string(), "[S]"),
+            ]
+        );
+
+        assert_eq!(
+            backend.head_blob_size(Path::new("modified.txt")).unwrap(),
+            4
+        );
+        assert_eq!(
+            backend
+                .head_blob_size(Path::new("dir/untracked.txt"))
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            backend.read_head_blob(Path::new("deleted.txt")).unwrap(),
+            Some(b"three\n".to_vec())
+        );
+        assert_eq!(
+            backend.read_head_blob(Path::new("missing.txt")).unwrap(),
+            None
+        );
+        assert_eq!(backend.index_blob_size(Path::new("staged.txt")).unwrap(), 5);
+        assert_eq!(
+            backend.read_index_blob(Path::new("staged.txt")).unwrap(),
+            Some(b"two!\n".to_vec())
+        );
+        assert_eq!(
+            backend
+                .read_index_blob(Path::new("dir/untracked.txt"))
+                .unwrap(),
+            None
+        );
+
+        let mut modified = files
+            .into_iter()
+            .find(|file| file.path == Path::new("modified.txt"))
+            .expect("modified file listed");
+        load_file_contents(backend, &mut modified, &LoadLimits::default()).expect("load");
+        assert!(
+            matches!(modified.old_content, Some(ContentData::Text(ref text)) if text == "one\n")
+        );
+        assert!(
+            matches!(modified.new_content, Some(ContentData::Text(ref text)) if text == "one!\n")
+        );
+    }
+
+    #[test]
+    fn cli_backend_reads_fixture_repo() {
+        let root = fixture_repo("cli");
+
+        let backend = CliBackend::discover(&root.join("dir")).expect("discover");
+        assert_backend_reads_fixture(&backend, &root);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[cfg(feature = "libgit2")]
+    #[test]
+    fn libgit2_backend_reads_fixture_repo() {
+        let root = fixture_repo("libgit2");
+
+        let backend =
+            crate::libgit2::Libgit2Backend::discover(&root.join("dir")).expect("discover");
+        assert_backend_reads_fixture(&backend, &root);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn cli_batch_reads_follow_head_across_refreshes() {
+        let root = fixture_repo("batch");
+        let backend = CliBackend::new(root.clone());
+        let modified = Path::new("modified.txt");
+
+        assert_eq!(
+            backend.read_head_blob(modified).unwrap(),
+            Some(b"one\n".to_vec())
+        );
+        assert_eq!(backend.read_head_blob(Path::new("dir")).unwrap(), None);
+        assert_eq!(backend.head_blob_size(Path::new("dir")).unwrap(), 0);
+
+        git(&root, &["commit", "-q", "-am", "second"]);
+        backend.collect_changed_files().expect("status");
+
+        assert_eq!(
+            backend.read_head_blob(modified).unwrap(),
+            Some(b"one!\n".to_vec())
+        );
+        assert_eq!(backend.head_blob_size(modified).unwrap(), 5);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cli_backend_keeps_non_utf8_and_spaced_paths_exact() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let root = fixture_repo("bytes");
+        // Latin-1 "café menu.txt": not UTF-8, and lossy decoding would also lose the space.
+        let name = Path::new(OsStr::from_bytes(b"caf\xe9 menu.txt"));
+        fs::write(root.join(name), "old\n").expect("write fixture");
+        git(&root, &["add", "."]);
+        git(&root, &["commit", "-q", "-m", "bytes"]);
+        fs::write(root.join(name), "new\n").expect("write fixture");
+
+        let backend = CliBackend::new(root.clone());
+        let files = backend.collect_changed_files().expect("status");
+        assert!(files.iter().any(|file| file.path == name));
+        assert_eq!(
+            backend.read_head_blob(name).unwrap(),
+            Some(b"old\n".to_vec())
+        );
+        assert_eq!(backend.head_blob_size(name).unwrap(), 4);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn discovery_explains_bare_repositories_and_broken_worktrees() {
+        let root = fixture_repo("discover");
+        let message = |dir: &Path| format!("{:#}", CliBackend::discover(dir).unwrap_err());
+
+        assert!(message(&root.join(".git")).contains("bare repository"));
+
+        let bare = root.with_extension("bare");
+        let _ = fs::remove_dir_all(&bare);
+        git(
+            &root,
+            &["clone", "-q", "--bare", ".", bare.to_str().unwrap()],
+        );
+        assert!(message(&bare).contains("git worktree add"));
+
+        let linked = root.with_extension("linked");
+        let _ = fs::remove_dir_all(&linked);
+        git(&root, &["worktree", "add", "-q", linked.to_str().unwrap()]);
+        assert!(CliBackend::discover(&linked).is_ok());
+        fs::remove_dir_all(root.join(".git/worktrees")).unwrap();
+        assert!(message(&linked).contains("git worktree repair"));
+
+        for dir in [root, bare, linked] {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+
+    #[test]
+    fn discovery_suggests_safe_directory_for_foreign_repositories() {
+        let stderr = "fatal: detected dubious ownership in repository at '/srv/repo'\n\
+                      To add an exception for this directory, call:\n\n\
+                      \tgit config --global --add safe.directory /srv/repo\n";
+        let message = discover_error(Path::new("/srv/repo/src"), stderr).to_string();
+
+        assert!(message.contains("another user"), "{}", message);
+        assert!(message.contains("safe.directory /srv/repo"), "{}", message);
+
+        let outside = discover_error(
+            Path::new("/tmp"),
+            "fatal: not a git repository (or any of the parent directories): .git",
+        );
+        assert!(outside
+            .to_string()
+            .starts_with("/tmp is not inside a git repository"));
+    }
+
+    #[test]
+    fn too_large_files_are_not_read() {
+        let root = fixture_repo("limits");
+        let backend = CliBackend::new(root.clone());
+        let mut file = crate::model::ChangedFile::new(
+            PathBuf::from("modified.txt"),
+            FileStatus {
+                staged: false,
+                unstaged: true,
+                untracked: false,
+                ..FileStatus::default()
+            },
+        );
+        let limits = LoadLimits {
+            max_file_bytes: 2,
+            ..LoadLimits::default()
+        };
+
+        load_file_contents(&backend, &mut file, &limits).expect("load");
+
+        assert!(matches!(
+            file.old_content,
+            Some(ContentData::TooLarge { bytes: 4 })
+        ));
+        assert!(matches!(
+            file.new_content,
+            Some(ContentData::TooLarge { bytes: 5 })
+        ));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::command::COMMANDS;
+use crate::input::{Action, Keymap, CATEGORIES};
+
+/// State of the `?` overlay.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HelpView {
+    pub scroll: usize,
+    /// Case-insensitive filter over keys, action names and descriptions.
+    pub query: String,
+    /// Typing goes to `query` until Enter or Esc.
+    pub searching: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HelpLine {
+    Heading(&'static str),
+    Entry { keys: String, summary: String },
+    Blank,
+}
+
+/// Help text for the active keymap, grouped by category, then the `:` commands.
+///
+/// Actions without a binding are listed as `:name`, since the command line reaches them.
+pub fn help_lines(keymap: &Keymap, query: &str) -> Vec<HelpLine> {
+    let query = query.to_lowercase();
+    let matches = |texts: &[&str]| {
+        query.is_empty()
+            || texts
+                .iter()
+                .any(|text| text.to_lowercase().contains(&query))
+    };
+
+    let mut sections: Vec<(&'static str, Vec<HelpLine>)> = CATEGORIES
+        .iter()
+        .map(|category| (*category, Vec::new()))
+        .collect();
+    for (name, action) in Action::all() {
+        let (category, summary) = action.describe();
+        let sequences = keymap.sequences_for(action);
+        let keys = if sequences.is_empty() {
+            format!(":{}", name)
+        } else {
+            sequences.join(", ")
+        };
+        if !matches(&[&keys, name, summary]) {
+            continue;
+        }
+        if let Some((_, entries)) = sections.iter_mut().find(|(c, _)| *c == category) {
+            entries.push(HelpLine::Entry {
+                keys,
+                summary: summary.to_string(),
+            });
+        }
+    }
+
+    let commands: Vec<HelpLine> = COMMANDS
+        .iter()
+        .filter(|(name, usage, summary)| matches(&[name, usage, summary]))
+        .map(|(_, usage, summary)| HelpLine::Entry {
+            keys: format!(":{}", usage),
+            summary: summary.to_string(),
+        })
+        .collect();
+    sections.push((": commands", commands));
+
+    let mut lines = Vec::new();
+    for (heading, entries) in sections {
+        if entries.is_empty() {
+            continue;
+        }
+        if !lines.is_empty() {
+            lines.push(HelpLine::Blank);
+        }
+        lines.push(HelpLine::Heading(heading));
+        lines.extend(entries);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{help_lines, HelpLine};
+    use crate::input::{parse_sequence, Action, Keymap};
+
+    fn entry_for<'a>(lines: &'a [HelpLine], summary_part: &str) -> Option<&'a str> {
+        lines.iter().find_map(|line| match line {
+            HelpLine::Entry { keys, summary } if summary.contains(summary_part) => {
+                Some(keys.as_str())
+            }
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn lists_active_bindings_grouped_by_category() {
+        let mut keymap = Keymap::default();
+        keymap.bind(parse_sequence("down").unwrap(), Action::ScrollDown);
+        keymap.bind(parse_sequence("q").unwrap(), Action::None);
+
+        let lines = help_lines(&keymap, "");
+
+        assert_eq!(lines[0], HelpLine::Heading("Files"));
+        assert_eq!(entry_for(&lines, "scroll down one"), Some("j, down"));
+        assert_eq!(entry_for(&lines, "go to the top"), Some("g g"));
+        assert_eq!(entry_for(&lines, "quit"), Some(":quit"));
+        assert!(lines.contains(&HelpLine::Heading(": commands")));
+    }
+
+    #[test]
+    fn search_keeps_matching_entries_and_their_headings() {
+        let lines = help_lines(&Keymap::default(), "CHANGE");
+
+        assert!(lines.contains(&HelpLine::Heading("Changes")));
+        assert!(!lines.contains(&HelpLine::Heading("Scrolling")));
+        assert_eq!(entry_for(&lines, "next change"), Some("n"));
+        assert!(help_lines(&Keymap::default(), "zzz").is_empty());
+    }
+}
@@ -0,0 +1,938 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Span;
+use syntect::highlighting::{
+    FontStyle, HighlightIterator, HighlightState, Highlighter, Style as SyntectStyle, Theme,
+};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+
+use crate::model::AlignedRow;
+
+/// Rows between saved parser checkpoints, bounding the re-parse needed to reach any row.
+const CHECKPOINT_INTERVAL: usize = 256;
+
+/// Number of recently viewed files whose highlight state is kept.
+const CACHED_FILES: usize = 8;
+
+/// Longest stretch of work the highlighter thread does before checking for newer requests.
+const WORK_SLICE: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone)]
+pub struct HighlightedRow {
+    pub left: Vec<Span<'static>>,
+    pub right: Vec<Span<'static>>,
+}
+
+impl HighlightedRow {
+    pub fn plain(row: &AlignedRow) -> Self {
+        Self {
+            left: vec![Span::raw(row.left_text.clone())],
+            right: vec![Span::raw(row.right_text.clone())],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SideState {
+    parse: ParseState,
+    highlight: HighlightState,
+}
+
+impl SideState {
+    fn new(syntax: &SyntaxReference, highlighter: &Highlighter<'_>) -> Self {
+        Self {
+            parse: ParseState::new(syntax),
+            highlight: HighlightState::new(highlighter, ScopeStack::new()),
+        }
+    }
+
+    fn highlight_line(&mut self, line: &str, highlighter: &Highlighter<'_>) -> Vec<Span<'static>> {
+        let line_for_highlight = with_newline(line);
+        let Ok(ops) = self.parse.parse_line(&line_for_highlight, syntax_set()) else {
+            return vec![Span::raw(line.to_string())];
+        };
+
+        let mut spans: Vec<Span<'static>> =
+            HighlightIterator::new(&mut self.highlight, &ops, &line_for_highlight, highlighter)
+                .map(|(style, text)| {
+                    Span::styled(
+                        text.to_string(),
+                        syntect_to_ratatui_style(style, Style::default()),
+                    )
+                })
+                .collect();
+        trim_trailing_newline(&mut spans);
+
+        if spans.is_empty() {
+            vec![Span::raw(String::new())]
+        } else {
+            spans
+        }
+    }
+
+    /// Advances parser and highlighter state past `line` without materializing spans.
+    fn skip_line(&mut self, line: &str, highlighter: &Highlighter<'_>) {
+        let line_for_highlight = with_newline(line);
+        if let Ok(ops) = self.parse.parse_line(&line_for_highlight, syntax_set()) {
+            HighlightIterator::new(&mut self.highlight, &ops, &line_for_highlight, highlighter)
+                .for_each(drop);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RowState {
+    left: SideState,
+    right: SideState,
+}
+
+/// Identifies one version of one file's aligned rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileKey {
+    path: PathBuf,
+    content_hash: u64,
+    epoch: u64,
+    rows_len: usize,
+}
+
+impl FileKey {
+    fn new(path: &Path, content_hash: u64, rows: &[AlignedRow], epoch: u64) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            content_hash,
+            epoch,
+            rows_len: rows.len(),
+        }
+    }
+}
+
+/// Parser state for one file, owned by the highlighter thread (`ParseState` is not `Send`).
+#[derive(Debug)]
+struct FileHighlightCache {
+    key: FileKey,
+    source: Vec<AlignedRow>,
+    /// `checkpoints[i]` is the state before row `i * CHECKPOINT_INTERVAL`.
+    checkpoints: Vec<RowState>,
+    /// State right after the most recently highlighted row, so scrolling down resumes there.
+    cursor: Option<(usize, RowState)>,
+    /// Progress of the background checkpoint pass.
+    warm_cursor: Option<(usize, RowState)>,
+    rows: Vec<Option<HighlightedRow>>,
+    theme: Arc<Theme>,
+}
+
+impl FileHighlightCache {
+    fn new(key: FileKey, source: Vec<AlignedRow>, theme: Arc<Theme>) -> Self {
+        let syntax = syntax_for_path(&key.path, syntax_set());
+        let highlighter = Highlighter::new(&theme);
+        let initial = SideState::new(syntax, &highlighter);
+
+        Self {
+            rows: (0..source.len()).map(|_| None).collect(),
+            key,
+            source,
+            theme,
+            checkpoints: vec![RowState {
+                left: initial.clone(),
+                right: initial,
+            }],
+            cursor: None,
+            warm_cursor: None,
+        }
+    }
+
+    /// Highlights every row in `start..end` that is not cached yet.
+    ///
+    /// Returns `false` if `deadline` passed before the whole window was highlighted; the
+    /// progress made so far is kept and resumed by the next call.
+    fn ensure_range(&mut self, start: usize, end: usize, deadline: Option<Instant>) -> bool {
+        let end = end.min(self.source.len());
+        let Some(first_missing) = (start..end).find(|idx| self.rows[*idx].is_none()) else {
+            return true;
+        };
+
+        let theme = Arc::clone(&self.theme);
+        let highlighter = Highlighter::new(&theme);
+        let checkpoint_idx = (first_missing / CHECKPOINT_INTERVAL).min(self.checkpoints.len() - 1);
+        let checkpoint_row = checkpoint_idx * CHECKPOINT_INTERVAL;
+        let (mut row_idx, mut state) = self
+            .cursor
+            .take()
+            .filter(|(cursor_row, _)| *cursor_row <= first_missing && *cursor_row > checkpoint_row)
+            .unwrap_or_else(|| (checkpoint_row, self.checkpoints[checkpoint_idx].clone()));
+
+        while row_idx < end {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                self.cursor = Some((row_idx, state));
+                return false;
+            }
+
+            self.record_checkpoint(row_idx, &state);
+            let row = &self.source[row_idx];
+            if row_idx >= start && self.rows[row_idx].is_none() {
+                self.rows[row_idx] = Some(HighlightedRow {
+                    left: state.left.highlight_line(&row.left_text, &highlighter),
+                    right: state.right.highlight_line(&row.right_text, &highlighter),
+                });
+            } else {
+                state.left.skip_line(&row.left_text, &highlighter);
+                state.right.skip_line(&row.right_text, &highlighter);
+            }
+            row_idx += 1;
+        }
+
+        self.cursor = Some((row_idx, state));
+        true
+    }
+
+    /// Continues parsing the whole file to record every checkpoint.
+    ///
+    /// Returns `true` once the end of the file is reached.
+    fn warm_checkpoints(&mut self, deadline: Option<Instant>) -> bool {
+        let theme = Arc::clone(&self.theme);
+        let highlighter = Highlighter::new(&theme);
+        let (mut row_idx, mut state) = self.warm_cursor.take().unwrap_or_else(|| {
+            let last_idx = self.checkpoints.len() - 1;
+            (
+                last_idx * CHECKPOINT_INTERVAL,
+                self.checkpoints[last_idx].clone(),
+            )
+        });
+
+        while row_idx < self.source.len() {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                self.warm_cursor = Some((row_idx, state));
+                return false;
+            }
+
+            self.record_checkpoint(row_idx, &state);
+            let row = &self.source[row_idx];
+            state.left.skip_line(&row.left_text, &highlighter);
+            state.right.skip_line(&row.right_text, &highlighter);
+            row_idx += 1;
+        }
+
+        true
+    }
+
+    fn record_checkpoint(&mut self, row_idx: usize, state: &RowState) {
+        if row_idx.is_multiple_of(CHECKPOINT_INTERVAL)
+            && row_idx / CHECKPOINT_INTERVAL == self.checkpoints.len()
+        {
+            self.checkpoints.push(state.clone());
+        }
+    }
+}
+
+enum Request {
+    Open {
+        key: FileKey,
+        rows: Vec<AlignedRow>,
+    },
+    Window {
+        key: FileKey,
+        start: usize,
+        end: usize,
+    },
+    Prewarm {
+        key: FileKey,
+    },
+    /// Drops all parser state, which depends on the theme, and highlights with `theme` from
+    /// now on.
+    SetTheme(Arc<Theme>),
+}
+
+#[derive(Debug)]
+struct PublishedFile {
+    key: FileKey,
+    rows: Vec<Option<HighlightedRow>>,
+}
+
+type Published = Arc<Mutex<VecDeque<PublishedFile>>>;
+
+/// Syntax highlighting for recently viewed files, computed on a dedicated thread.
+///
+/// The render loop asks for windows of rows and gets whatever is finished, with plain text
+/// for the rest; the highlighter thread fills in missing rows and pre-computes parser
+/// checkpoints for the selected file in the background. The thread decides which files stay
+/// cached and removes the published rows of the ones it drops, so a file is opened again the
+/// next time it is shown.
+pub struct HighlightCache {
+    published: Published,
+    requests: Sender<Request>,
+}
+
+impl HighlightCache {
+    pub fn spawn(theme: Arc<Theme>) -> Self {
+        let published: Published = Arc::default();
+        let (requests, request_rx) = mpsc::channel();
+
+        let worker_published = Arc::clone(&published);
+        thread::spawn(move || run_highlighter(&request_rx, &worker_published, theme));
+
+        Self {
+            published,
+            requests,
+        }
+    }
+
+    /// Returns rows `start..end`, highlighted where available and plain text otherwise.
+    ///
+    /// Missing rows are requested from the highlighter thread. Entries are keyed by path,
+    /// the file's content hash, `epoch` and row count, so a reloaded file with new contents
+    /// gets new rows and bumping `epoch` invalidates every file.
+    pub fn window(
+        &self,
+        file_path: &Path,
+        content_hash: u64,
+        rows: &[AlignedRow],
+        epoch: u64,
+        start: usize,
+        end: usize,
+    ) -> Vec<HighlightedRow> {
+        let key = FileKey::new(file_path, content_hash, rows, epoch);
+        let end = end.min(rows.len());
+        let start = start.min(end);
+
+        let mut published = lock(&self.published);
+        let idx = self.open(&mut published, &key, rows);
+        let entry = &published[idx];
+
+        let window: Vec<HighlightedRow> = (start..end)
+            .map(|idx| {
+                entry.rows[idx]
+                    .clone()
+                    .unwrap_or_else(|| HighlightedRow::plain(&rows[idx]))
+            })
+            .collect();
+        if entry.rows[start..end].iter().any(Option::is_none) {
+            self.send(Request::Window { key, start, end });
+        }
+        window
+    }
+
+    /// Asks the highlighter thread to checkpoint the whole file so later jumps are quick.
+    pub fn prewarm(&self, file_path: &Path, content_hash: u64, rows: &[AlignedRow], epoch: u64) {
+        let key = FileKey::new(file_path, content_hash, rows, epoch);
+        self.open(&mut lock(&self.published), &key, rows);
+        self.send(Request::Prewarm { key });
+    }
+
+    /// Index of the published entry for `key`, adding one and asking the highlighter thread
+    /// to open the file if there is none.
+    fn open(
+        &self,
+        published: &mut VecDeque<PublishedFile>,
+        key: &FileKey,
+        rows: &[AlignedRow],
+    ) -> usize {
+        if let Some(idx) = published.iter().position(|entry| &entry.key == key) {
+            return idx;
+        }
+        self.send(Request::Open {
+            key: key.clone(),
+            rows: rows.to_vec(),
+        });
+        published.push_back(PublishedFile {
+            key: key.clone(),
+            rows: (0..rows.len()).map(|_| None).collect(),
+        });
+        published.len() - 1
+    }
+
+    /// Switches the syntax theme. Callers also bump their epoch so rows requested before
+    /// the switch are not mistaken for current ones.
+    pub fn set_theme(&self, theme: Arc<Theme>) {
+        lock(&self.published).clear();
+        self.send(Request::SetTheme(theme));
+    }
+
+    fn send(&self, request: Request) {
+        // The highlighter thread only exits once this sender is dropped.
+        let _ = self.requests.send(request);
+    }
+}
+
+/// Highlights every row of a file in one pass, for output that is written once rather than
+/// scrolled through.
+pub fn highlight_rows(file_path: &Path, rows: &[AlignedRow], theme: &Theme) -> Vec<HighlightedRow> {
+    let highlighter = Highlighter::new(theme);
+    let mut left = SideState::new(syntax_for_path(file_path, syntax_set()), &highlighter);
+    let mut right = left.clone();
+    rows.iter()
+        .map(|row| HighlightedRow {
+            left: left.highlight_line(&row.left_text, &highlighter),
+            right: right.highlight_line(&row.right_text, &highlighter),
+        })
+        .collect()
+}
+
+fn lock(published: &Published) -> MutexGuard<'_, VecDeque<PublishedFile>> {
+    published.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn run_highlighter(requests: &Receiver<Request>, published: &Published, mut theme: Arc<Theme>) {
+    let mut files: VecDeque<FileHighlightCache> = VecDeque::new();
+    let mut window: Option<(FileKey, usize, usize)> = None;
+    let mut prewarm: Option<FileKey> = None;
+
+    loop {
+        let idle = window.is_none() && prewarm.is_none();
+        let first = if idle {
+            match requests.recv() {
+                Ok(request) => Some(request),
+                Err(_) => return,
+            }
+        } else {
+            match requests.try_recv() {
+                Ok(request) => Some(request),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        };
+
+        for request in first.into_iter().chain(requests.try_iter()) {
+            match request {
+                Request::Open { key, rows } => {
+                    // Older versions of the file and the least recently used files go.
+                    let mut dropped: Vec<FileKey> = Vec::new();
+                    files.retain(|file| {
+                        let keep = file.key.path != key.path;
+                        if !keep {
+                            dropped.push(file.key.clone());
+                        }
+                        keep
+                    });
+                    files.push_front(FileHighlightCache::new(key, rows, Arc::clone(&theme)));
+                    dropped.extend(
+                        files
+                            .drain(CACHED_FILES.min(files.len())..)
+                            .map(|file| file.key),
+                    );
+                    unpublish(published, &dropped);
+                }
+                Request::Window { key, start, end } => {
+                    // Only the latest window matters; checkpointing a file the user moved
+                    // away from is wasted work.
+                    if prewarm.as_ref().is_some_and(|warm| warm.path != key.path) {
+                        prewarm = None;
+                    }
+                    if promote(&mut files, &key, published) {
+                        window = Some((key, start, end));
+                    }
+                }
+                Request::Prewarm { key } => {
+                    if promote(&mut files, &key, published) {
+                        prewarm = Some(key);
+                    }
+                }
+                Request::SetTheme(new_theme) => {
+                    theme = new_theme;
+                    files.clear();
+                    window = None;
+                    prewarm = None;
+                }
+            }
+        }
+
+        let deadline = Instant::now() + WORK_SLICE;
+        if let Some((key, start, end)) = window.take() {
+            if let Some(file) = files.iter_mut().find(|file| file.key == key) {
+                let done = file.ensure_range(start, end, Some(deadline));
+                publish(published, file, start, end);
+                if !done {
+                    window = Some((key, start, end));
+                }
+            }
+            continue;
+        }
+
+        if let Some(key) = prewarm.take() {
+            if let Some(file) = files.iter_mut().find(|file| file.key == key) {
+                if !file.warm_checkpoints(Some(deadline)) {
+                    prewarm = Some(key);
+                }
+            }
+        }
+    }
+}
+
+/// Moves the file for `key` to the front of the cache. A file that was dropped in the meantime
+/// loses its published entry, so the next window opens it again.
+fn promote(files: &mut VecDeque<FileHighlightCache>, key: &FileKey, published: &Published) -> bool {
+    match files.iter().position(|file| &file.key == key) {
+        Some(idx) => {
+            let file = files.remove(idx).expect("index from position");
+            files.push_front(file);
+            true
+        }
+        None => {
+            unpublish(published, std::slice::from_ref(key));
+            false
+        }
+    }
+}
+
+fn unpublish(published: &Published, keys: &[FileKey]) {
+    if !keys.is_empty() {
+        lock(published).retain(|entry| !keys.contains(&entry.key));
+    }
+}
+
+fn publish(published: &Published, file: &FileHighlightCache, start: usize, end: usize) {
+    let mut published = lock(published);
+    let Some(entry) = published.iter_mut().find(|entry| entry.key == file.key) else {
+        return;
+    };
+
+    let end = end.min(file.rows.len()).min(entry.rows.len());
+    for idx in start.min(end)..end {
+        if entry.rows[idx].is_none() {
+            entry.rows[idx] = file.rows[idx].clone();
+        }
+    }
+}
+
+#[cfg(test)]
+impl HighlightCache {
+    /// Like [`HighlightCache::window`], but waits until every row is highlighted.
+    pub fn window_blocking(
+        &self,
+        file_path: &Path,
+        content_hash: u64,
+        rows: &[AlignedRow],
+        epoch: u64,
+        start: usize,
+        end: usize,
+    ) -> Vec<HighlightedRow> {
+        let key = FileKey::new(file_path, content_hash, rows, epoch);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            assert!(Instant::now() < deadline, "rows were never highlighted");
+            let window = self.window(file_path, content_hash, rows, epoch, start, end);
+            let done = lock(&self.published).iter().any(|entry| {
+                entry.key == key
+                    && entry.rows[start..end.min(rows.len())]
+                        .iter()
+                        .all(Option::is_some)
+            });
+            if done {
+                return window;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+fn with_newline(line: &str) -> String {
+    let mut line_for_highlight = String::with_capacity(line.len() + 1);
+    line_for_highlight.push_str(line);
+    line_for_highlight.push('\n');
+    line_for_highlight
+}
+
+fn trim_trailing_newline(spans: &mut Vec<Span<'static>>) {
+    let Some(last_idx) = spans.iter().rposition(|span| !span.content.is_empty()) else {
+        return;
+    };
+
+    if !spans[last_idx].content.ends_with('\n') {
+        return;
+    }
+
+    let mut content = spans[last_idx].content.to_string();
+    content.pop();
+
+    if content.is_empty() {
+        spans.remove(last_idx);
+    } else {
+        let style = spans[last_idx].style;
+        spans[last_idx] = Span::styled(content, style);
+    }
+}
+
+pub fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+pub fn syntax_for_path<'a>(path: &Path, syntax_set: &'a SyntaxSet) -> &'a SyntaxReference {
+    if let Some(file_name) = path.file_name().and_then(|name| name.to_str()) {
+        if let Some(syntax) = syntax_set.find_syntax_by_token(file_name) {
+            return syntax;
+        }
+
+        let lower_file_name = file_name.to_ascii_lowercase();
+        if lower_file_name != file_name {
+            if let Some(syntax) = syntax_set.find_syntax_by_token(&lower_file_name) {
+                return syntax;
+            }
+        }
+    }
+
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        if let Some(syntax) = syntax_set.find_syntax_by_extension(extension) {
+            return syntax;
+        }
+
+        let lower_extension = extension.to_ascii_lowercase();
+        if lower_extension != extension {
+            if let Some(syntax) = syntax_set.find_syntax_by_extension(&lower_extension) {
+                return syntax;
+            }
+        }
+    }
+
+    syntax_set.find_syntax_plain_text()
+}
+
+fn syntect_to_ratatui_style(style: SyntectStyle, base: Style) -> Style {
+    // Preserve diff semantic background while letting syntect own token foreground color.
+    let mut mapped = base.fg(Color::Rgb(
+        style.foreground.r,
+        style.foreground.g,
+        style.foreground.b,
+    ));
+
+    if style.font_style.contains(FontStyle::BOLD) {
+        mapped = mapped.add_modifier(Modifier::BOLD);
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        mapped = mapped.add_modifier(Modifier::ITALIC);
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        mapped = mapped.add_modifier(Modifier::UNDERLINED);
+    }
+
+    mapped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use ratatui::style::{Color, Modifier, Style};
+    use syntect::highlighting::{
+        Color as SyntectColor, FontStyle, Highlighter, Style as SyntectStyle, Theme as SyntaxTheme,
+    };
+
+    use crate::model::{AlignedRow, RowKind};
+
+    use super::{
+        syntax_for_path, syntax_set, syntect_to_ratatui_style, FileHighlightCache, FileKey,
+        HighlightCache, SideState, CACHED_FILES,
+    };
+    use crate::theme::Theme;
+
+    fn syntax_theme() -> Arc<SyntaxTheme> {
+        Theme::default().syntax
+    }
+
+    fn rendered_text(spans: &[ratatui::text::Span<'static>]) -> String {
+        spans.iter().map(|span| span.content.as_ref()).collect()
+    }
+
+    fn equal_rows(lines: &[&str]) -> Vec<AlignedRow> {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(idx, line)| AlignedRow {
+                left_line_no: Some(idx + 1),
+                right_line_no: Some(idx + 1),
+                left_text: line.to_string(),
+                right_text: line.to_string(),
+                kind: RowKind::Equal,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resolves_rust_syntax_by_extension() {
+        let syntax = syntax_for_path(Path::new("src/main.rs"), syntax_set());
+        assert_eq!(syntax.name, "Rust");
+    }
+
+    #[test]
+    fn falls_back_to_plain_text_syntax_for_unknown_extension() {
+        let syntax = syntax_for_path(Path::new("assets/file.unknown"), syntax_set());
+        assert_eq!(syntax.name, syntax_set().find_syntax_plain_text().name);
+    }
+
+    #[test]
+    fn maps_syntect_styles_to_ratatui_styles() {
+        let syntect_style = SyntectStyle {
+            foreground: SyntectColor {
+                r: 1,
+                g: 2,
+                b: 3,
+                a: 255,
+            },
+            background: SyntectColor {
+                r: 10,
+                g: 20,
+                b: 30,
+                a: 255,
+            },
+            font_style: FontStyle::BOLD | FontStyle::ITALIC,
+        };
+
+        let mapped = syntect_to_ratatui_style(syntect_style, Style::default());
+
+        assert_eq!(mapped.fg, Some(Color::Rgb(1, 2, 3)));
+        assert!(mapped.add_modifier.contains(Modifier::BOLD));
+        assert!(mapped.add_modifier.contains(Modifier::ITALIC));
+    }
+
+    #[test]
+    fn preserves_existing_diff_background_color() {
+        let syntect_style = SyntectStyle {
+            foreground: SyntectColor {
+                r: 200,
+                g: 100,
+                b: 50,
+                a: 255,
+            },
+            background: SyntectColor {
+                r: 10,
+                g: 20,
+                b: 30,
+                a: 255,
+            },
+            font_style: FontStyle::BOLD,
+        };
+
+        let mapped =
+            syntect_to_ratatui_style(syntect_style, Style::default().bg(Color::Rgb(50, 51, 52)));
+
+        assert_eq!(mapped.fg, Some(Color::Rgb(200, 100, 50)));
+        assert_eq!(mapped.bg, Some(Color::Rgb(50, 51, 52)));
+        assert!(mapped.add_modifier.contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn highlight_line_produces_spans_without_trailing_newline() {
+        let syntax = syntax_for_path(Path::new("src/main.rs"), syntax_set());
+        let theme = syntax_theme();
+        let highlighter = Highlighter::new(&theme);
+        let mut state = SideState::new(syntax, &highlighter);
+        let text = "fn main() { let x = 1; }";
+
+        let spans = state.highlight_line(text, &highlighter);
+
+        assert!(!spans.is_empty());
+        assert!(spans.iter().all(|span| !span.content.is_empty()));
+        assert_eq!(rendered_text(&spans), text);
+    }
+
+    #[test]
+    fn highlight_state_advances_across_empty_line() {
+        let syntax = syntax_for_path(Path::new("src/main.rs"), syntax_set());
+        let theme = syntax_theme();
+        let highlighter = Highlighter::new(&theme);
+        let mut state = SideState::new(syntax, &highlighter);
+
+        let comment_spans = state.highlight_line("// comment", &highlighter);
+        let comment_style = comment_spans
+            .iter()
+            .find(|span| span.content.contains("comment"))
+            .expect("expected comment token")
+            .style;
+
+        let _ = state.highlight_line("", &highlighter);
+        let code_spans = state.highlight_line("let value = 1;", &highlighter);
+        let let_style = code_spans
+            .iter()
+            .find(|span| span.content.contains("let"))
+            .expect("expected let token")
+            .style;
+
+        assert_ne!(let_style, comment_style);
+    }
+
+    fn file_cache(name: &str, rows: &[AlignedRow]) -> FileHighlightCache {
+        FileHighlightCache::new(
+            FileKey::new(Path::new(name), 0, rows, 0),
+            rows.to_vec(),
+            syntax_theme(),
+        )
+    }
+
+    #[test]
+    fn highlights_only_requested_window_and_checkpoints_on_the_way() {
+        let lines: Vec<String> = (0..1000).map(|i| format!("let v{} = {};", i, i)).collect();
+        let line_refs: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let rows = equal_rows(&line_refs);
+        let mut cache = file_cache("window.rs", &rows);
+
+        assert!(cache.ensure_range(900, 910, None));
+
+        assert_eq!(cache.rows.iter().filter(|row| row.is_some()).count(), 10);
+        assert_eq!(cache.checkpoints.len(), 4);
+        assert_eq!(cache.cursor.as_ref().map(|(row, _)| *row), Some(910));
+    }
+
+    #[test]
+    fn resuming_from_checkpoint_matches_sequential_highlighting() {
+        let mut lines = vec!["/* start of a long comment"];
+        lines.extend(std::iter::repeat_n("still inside the comment", 600));
+        lines.push("end */ let value = 1;");
+        let rows = equal_rows(&lines);
+
+        let mut sequential = file_cache("resume.rs", &rows);
+        sequential.ensure_range(0, rows.len(), None);
+
+        let mut jumping = file_cache("resume.rs", &rows);
+        jumping.ensure_range(rows.len() - 5, rows.len(), None);
+        jumping.cursor = None;
+        jumping.ensure_range(300, 302, None);
+
+        for idx in [300, 301, rows.len() - 1] {
+            let expected = sequential.rows[idx].as_ref().expect("row highlighted");
+            let actual = jumping.rows[idx].as_ref().expect("row highlighted");
+            assert_eq!(expected.left, actual.left);
+        }
+    }
+
+    #[test]
+    fn expired_deadline_keeps_progress_for_next_slice() {
+        let rows = equal_rows(&["let a = 1;", "let b = 2;"]);
+        let mut cache = file_cache("deadline.rs", &rows);
+
+        assert!(!cache.ensure_range(0, 2, Some(Instant::now())));
+        assert!(cache.rows.iter().all(Option::is_none));
+
+        assert!(cache.ensure_range(0, 2, None));
+        assert!(cache.rows.iter().all(Option::is_some));
+    }
+
+    #[test]
+    fn warm_checkpoints_resumes_after_deadline() {
+        let lines: Vec<String> = (0..600).map(|i| format!("let v{} = {};", i, i)).collect();
+        let line_refs: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let rows = equal_rows(&line_refs);
+        let mut cache = file_cache("warm.rs", &rows);
+
+        assert!(!cache.warm_checkpoints(Some(Instant::now())));
+        assert_eq!(cache.checkpoints.len(), 1);
+
+        assert!(cache.warm_checkpoints(None));
+        assert_eq!(cache.checkpoints.len(), 3);
+    }
+
+    #[test]
+    fn window_returns_plain_rows_until_highlighted() {
+        let cache = HighlightCache::spawn(syntax_theme());
+        let rows = equal_rows(&["let value = 1;"]);
+
+        let first = cache.window(Path::new("plain_first.rs"), 0, &rows, 0, 0, 1);
+        assert_eq!(rendered_text(&first[0].left), "let value = 1;");
+
+        let highlighted = cache.window_blocking(Path::new("plain_first.rs"), 0, &rows, 0, 0, 1);
+        assert!(highlighted[0].left.len() > 1);
+    }
+
+    #[test]
+    fn window_invalidates_when_epoch_changes() {
+        let cache = HighlightCache::spawn(syntax_theme());
+        let file_path = Path::new("cache_epoch_test.rs");
+        let mut rows = equal_rows(&["let value = 1;"]);
+
+        let first = cache.window_blocking(file_path, 0, &rows, 10, 0, 1);
+
+        rows[0].left_text = "let changed = 2;".to_string();
+
+        let stale = cache.window(file_path, 0, &rows, 10, 0, 1);
+        let refreshed = cache.window_blocking(file_path, 0, &rows, 11, 0, 1);
+
+        assert_eq!(rendered_text(&stale[0].left), rendered_text(&first[0].left));
+        assert_eq!(rendered_text(&refreshed[0].left), "let changed = 2;");
+
+        // New contents of the same file are told apart by their hash alone.
+        rows[0].left_text = "let reloaded = 3;".to_string();
+        let reloaded = cache.window_blocking(file_path, 1, &rows, 11, 0, 1);
+        assert_eq!(rendered_text(&reloaded[0].left), "let reloaded = 3;");
+    }
+
+    #[test]
+    fn keeps_caches_for_several_recent_files() {
+        let cache = HighlightCache::spawn(syntax_theme());
+        let mut rows_a = equal_rows(&["let a = 1;"]);
+        let rows_b = equal_rows(&["let b = 2;"]);
+
+        cache.window_blocking(Path::new("multi_a.rs"), 0, &rows_a, 0, 0, 1);
+        cache.window_blocking(Path::new("multi_b.rs"), 0, &rows_b, 0, 0, 1);
+
+        // Same epoch and length: the entry for file A must still be cached after viewing B.
+        rows_a[0].left_text = "let edited = 3;".to_string();
+        let cached = cache.window(Path::new("multi_a.rs"), 0, &rows_a, 0, 0, 1);
+
+        assert_eq!(rendered_text(&cached[0].left), "let a = 1;");
+    }
+
+    #[test]
+    fn reopens_a_file_dropped_while_it_was_still_published() {
+        let cache = HighlightCache::spawn(syntax_theme());
+        let first = Path::new("cycled_first.rs");
+        let rows = equal_rows(&["let a = 1;", "let b = 2;", "let c = 3;"]);
+        cache.window_blocking(first, 0, &rows, 0, 0, 1);
+
+        // Rendering the finished first row sends no request, so only the published side
+        // sees the first file as recently used.
+        for idx in 0..=CACHED_FILES {
+            let path = PathBuf::from(format!("cycled_{}.rs", idx));
+            cache.window_blocking(&path, 0, &equal_rows(&["let x = 0;"]), 0, 0, 1);
+            cache.window(first, 0, &rows, 0, 0, 1);
+        }
+
+        let scrolled = cache.window_blocking(first, 0, &rows, 0, 1, 3);
+        assert!(scrolled.iter().all(|row| row.left.len() > 1));
+    }
+
+    /// Compares first-frame latency of eager whole-file highlighting with the viewport-driven
+    /// cache. Run with `cargo test --release bench_ -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_viewport_highlighting_against_eager() {
+        let lines: Vec<String> = (0..50_000)
+            .map(|i| format!("    let value_{} = compute({}, \"text\"); // note", i, i))
+            .collect();
+        let line_refs: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let rows = equal_rows(&line_refs);
+        let viewport = 60;
+
+        let started = Instant::now();
+        let mut eager = file_cache("bench.rs", &rows);
+        eager.ensure_range(0, rows.len(), None);
+        let eager_elapsed = started.elapsed();
+
+        let started = Instant::now();
+        let mut lazy = file_cache("bench.rs", &rows);
+        lazy.ensure_range(0, viewport, None);
+        let first_frame = started.elapsed();
+
+        let started = Instant::now();
+        lazy.ensure_range(rows.len() - viewport, rows.len(), None);
+        let jump_to_bottom = started.elapsed();
+
+        let started = Instant::now();
+        lazy.cursor = None;
+        lazy.ensure_range(rows.len() / 2, rows.len() / 2 + viewport, None);
+        let jump_to_middle = started.elapsed();
+
+        println!("eager whole file: {:?}", eager_elapsed);
+        println!("lazy first frame: {:?}", first_frame);
+        println!("lazy jump to bottom: {:?}", jump_to_bottom);
+        println!("lazy jump back to middle: {:?}", jump_to_middle);
+
+        assert!(first_frame < eager_elapsed);
+        assert!(jump_to_middle < jump_to_bottom);
+    }
+}
@@ -1,61 +1,671 @@
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+use std::fmt;
 
-#[derive(Debug, Clone, Copy)]
+use anyhow::{anyhow, bail, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Action {
     SelectPrevFile,
     SelectNextFile,
     ToggleTree,
+    ToggleWrap,
+    ToggleWhitespace,
     Refresh,
+    ToggleLiveUpdates,
+    LoadAnyway,
     TreeScrollLeft,
     TreeScrollRight,
+    GrowTree,
+    ShrinkTree,
+    SplitLeft,
+    SplitRight,
+    CycleTreePosition,
+    ResetLayout,
     ScrollDown,
     ScrollUp,
     PageDown,
     PageUp,
     ScrollLeft,
     ScrollRight,
-    PrefixG,
+    GoTop,
     GoBottom,
     NextChange,
     PrevChange,
+    CommandLine,
+    Help,
+    Suspend,
     Quit,
     None,
 }
 
-pub fn map_key(key: KeyEvent) -> Action {
+/// Help grouping for actions, in display order.
+pub const CATEGORIES: &[&str] = &["Files", "Scrolling", "Changes", "View", "Other"];
+
+/// Config name, help category and help text for every action; `none` unbinds a key.
+const ACTIONS: &[(&str, Action, &str, &str)] = &[
+    (
+        "select_prev_file",
+        Action::SelectPrevFile,
+        "Files",
+        "select the previous file",
+    ),
+    (
+        "select_next_file",
+        Action::SelectNextFile,
+        "Files",
+        "select the next file",
+    ),
+    (
+        "refresh",
+        Action::Refresh,
+        "Files",
+        "reload the list of changed files",
+    ),
+    (
+        "toggle_live_updates",
+        Action::ToggleLiveUpdates,
+        "Files",
+        "pause or resume live updates",
+    ),
+    (
+        "load_anyway",
+        Action::LoadAnyway,
+        "Files",
+        "load a file over the size limit (degraded)",
+    ),
+    (
+        "scroll_down",
+        Action::ScrollDown,
+        "Scrolling",
+        "scroll down one line",
+    ),
+    (
+        "scroll_up",
+        Action::ScrollUp,
+        "Scrolling",
+        "scroll up one line",
+    ),
+    (
+        "page_down",
+        Action::PageDown,
+        "Scrolling",
+        "scroll down half a page",
+    ),
+    (
+        "page_up",
+        Action::PageUp,
+        "Scrolling",
+        "scroll up half a page",
+    ),
+    (
+        "scroll_left",
+        Action::ScrollLeft,
+        "Scrolling",
+        "scroll the diff left",
+    ),
+    (
+        "scroll_right",
+        Action::ScrollRight,
+        "Scrolling",
+        "scroll the diff right",
+    ),
+    (
+        "go_top",
+        Action::GoTop,
+        "Scrolling",
+        "go to the top, or to line <count>",
+    ),
+    (
+        "go_bottom",
+        Action::GoBottom,
+        "Scrolling",
+        "go to the bottom, or to line <count>",
+    ),
+    (
+        "next_change",
+        Action::NextChange,
+        "Changes",
+        "jump to the next change block",
+    ),
+    (
+        "prev_change",
+        Action::PrevChange,
+        "Changes",
+        "jump to the previous change block",
+    ),
+    (
+        "toggle_whitespace",
+        Action::ToggleWhitespace,
+        "Changes",
+        "ignore or show changes in whitespace",
+    ),
+    (
+        "toggle_tree",
+        Action::ToggleTree,
+        "View",
+        "show or hide the file tree",
+    ),
+    (
+        "toggle_wrap",
+        Action::ToggleWrap,
+        "View",
+        "wrap long lines instead of scrolling sideways",
+    ),
+    (
+        "tree_scroll_left",
+        Action::TreeScrollLeft,
+        "View",
+        "scroll the file tree left",
+    ),
+    (
+        "tree_scroll_right",
+        Action::TreeScrollRight,
+        "View",
+        "scroll the file tree right",
+    ),
+    (
+        "grow_tree",
+        Action::GrowTree,
+        "View",
+        "make the file tree larger",
+    ),
+    (
+        "shrink_tree",
+        Action::ShrinkTree,
+        "View",
+        "make the file tree smaller",
+    ),
+    (
+        "split_left",
+        Action::SplitLeft,
+        "View",
+        "move the HEAD/worktree divider left",
+    ),
+    (
+        "split_right",
+        Action::SplitRight,
+        "View",
+        "move the HEAD/worktree divider right",
+    ),
+    (
+        "cycle_tree_position",
+        Action::CycleTreePosition,
+        "View",
+        "move the file tree left, right or below",
+    ),
+    (
+        "reset_layout",
+        Action::ResetLayout,
+        "View",
+        "restore the default pane sizes and tree position",
+    ),
+    ("help", Action::Help, "View", "show or hide this help"),
+    (
+        "command_line",
+        Action::CommandLine,
+        "Other",
+        "open the : command line",
+    ),
+    (
+        "suspend",
+        Action::Suspend,
+        "Other",
+        "suspend to the shell (fg resumes)",
+    ),
+    ("quit", Action::Quit, "Other", "quit (or close help)"),
+    ("none", Action::None, "Other", "unbind a key"),
+];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Self> {
+        ACTIONS
+            .iter()
+            .find(|(candidate, ..)| *candidate == name)
+            .map(|(_, action, ..)| *action)
+    }
+
+    /// Every bindable action with its config name, excluding `none`.
+    pub fn all() -> impl Iterator<Item = (&'static str, Action)> {
+        ACTIONS
+            .iter()
+            .map(|(name, action, ..)| (*name, *action))
+            .filter(|(_, action)| *action != Action::None)
+    }
+
+    /// Help category and one-line description.
+    pub fn describe(self) -> (&'static str, &'static str) {
+        ACTIONS
+            .iter()
+            .find(|(_, action, ..)| *action == self)
+            .map_or(("Other", ""), |(_, _, category, summary)| {
+                (category, summary)
+            })
+    }
+}
+
+const DEFAULT_BINDINGS: &[(&str, Action)] = &[
+    ("ctrl-d", Action::PageDown),
+    ("ctrl-u", Action::PageUp),
+    ("K", Action::SelectPrevFile),
+    ("J", Action::SelectNextFile),
+    ("H", Action::TreeScrollLeft),
+    ("L", Action::TreeScrollRight),
+    ("b", Action::ToggleTree),
+    ("w", Action::ToggleWrap),
+    ("i", Action::ToggleWhitespace),
+    ("+", Action::GrowTree),
+    ("-", Action::ShrinkTree),
+    ("{", Action::SplitLeft),
+    ("}", Action::SplitRight),
+    ("T", Action::CycleTreePosition),
+    ("R", Action::Refresh),
+    ("W", Action::ToggleLiveUpdates),
+    ("X", Action::LoadAnyway),
+    ("j", Action::ScrollDown),
+    ("k", Action::ScrollUp),
+    ("h", Action::ScrollLeft),
+    ("l", Action::ScrollRight),
+    ("g g", Action::GoTop),
+    ("G", Action::GoBottom),
+    ("n", Action::NextChange),
+    ("N", Action::PrevChange),
+    (":", Action::CommandLine),
+    ("?", Action::Help),
+    ("ctrl-z", Action::Suspend),
+    ("q", Action::Quit),
+];
+
+/// Applied on top of the defaults: arrows and paging keys as in `less`. `b` pages back there,
+/// so the tree toggle moves to `t`, and a single `g` jumps to the top.
+const LESS_BINDINGS: &[(&str, Action)] = &[
+    ("down", Action::ScrollDown),
+    ("up", Action::ScrollUp),
+    ("left", Action::ScrollLeft),
+    ("right", Action::ScrollRight),
+    ("enter", Action::ScrollDown),
+    ("e", Action::ScrollDown),
+    ("y", Action::ScrollUp),
+    ("ctrl-e", Action::ScrollDown),
+    ("ctrl-y", Action::ScrollUp),
+    ("space", Action::PageDown),
+    ("f", Action::PageDown),
+    ("ctrl-f", Action::PageDown),
+    ("pagedown", Action::PageDown),
+    ("b", Action::PageUp),
+    ("ctrl-b", Action::PageUp),
+    ("pageup", Action::PageUp),
+    ("t", Action::ToggleTree),
+    ("g g", Action::None),
+    ("g", Action::GoTop),
+    ("<", Action::GoTop),
+    ("home", Action::GoTop),
+    (">", Action::GoBottom),
+    ("end", Action::GoBottom),
+];
+
+pub const PRESETS: &[&str] = &["default", "less"];
+
+/// One key press as it appears in a binding: a key plus ctrl/alt.
+///
+/// Shift is folded into the character for printable keys, so `J` matches however the
+/// terminal reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn from_event(key: KeyEvent) -> Self {
+        let modifiers = match key.code {
+            KeyCode::Char(_) => key.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT),
+            _ => key.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT),
+        };
+        let code = match key.code {
+            // Terminals report ctrl-letter in either case depending on shift.
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::CONTROL) => {
+                KeyCode::Char(c.to_ascii_lowercase())
+            }
+            code => code,
+        };
+        Self { code, modifiers }
+    }
+
+    /// Parses `j`, `G`, `ctrl-d`, `alt-x`, `shift-up`, `down`, `space`, `f5`, ...
+    pub fn parse(raw: &str) -> Result<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = raw;
+        // A trailing `-` is the key itself, e.g. `ctrl--`.
+        while let Some((prefix, tail)) = rest.split_once('-').filter(|(_, tail)| !tail.is_empty()) {
+            modifiers |= match prefix.to_ascii_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => break,
+            };
+            rest = tail;
+        }
+
+        let code = match rest.to_ascii_lowercase().as_str() {
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "pageup" | "pgup" => KeyCode::PageUp,
+            "pagedown" | "pgdn" => KeyCode::PageDown,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "enter" | "return" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" | "ins" => KeyCode::Insert,
+            "space" => KeyCode::Char(' '),
+            lower => {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => match lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                        Some(n @ 1..=24) => KeyCode::F(n),
+                        _ => bail!("unknown key {:?}", rest),
+                    },
+                }
+            }
+        };
+
+        if let KeyCode::Char(c) = code {
+            if modifiers.contains(KeyModifiers::SHIFT) {
+                bail!(
+                    "write shifted characters directly (use {:?})",
+                    c.to_uppercase().to_string()
+                );
+            }
+        }
+        Ok(Self::from_event(KeyEvent::new(code, modifiers)))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (flag, name) in [
+            (KeyModifiers::CONTROL, "ctrl-"),
+            (KeyModifiers::ALT, "alt-"),
+            (KeyModifiers::SHIFT, "shift-"),
+        ] {
+            if self.modifiers.contains(flag) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            KeyCode::PageUp => f.write_str("pageup"),
+            KeyCode::PageDown => f.write_str("pagedown"),
+            KeyCode::BackTab => f.write_str("backtab"),
+            code => f.write_str(&format!("{:?}", code).to_ascii_lowercase()),
+        }
+    }
+}
+
+/// Parses a whitespace-separated key sequence such as `g g` or `ctrl-x ctrl-c`.
+pub fn parse_sequence(raw: &str) -> Result<Vec<KeyChord>> {
+    let chords = raw
+        .split_whitespace()
+        .map(KeyChord::parse)
+        .collect::<Result<Vec<_>>>()?;
+    if chords.is_empty() {
+        bail!("empty key sequence");
+    }
+    Ok(chords)
+}
+
+/// Editing keys while the `:` command line is open; these bypass the keymap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKey {
+    Insert(char),
+    Backspace,
+    ClearLine,
+    Complete,
+    CompletePrev,
+    Submit,
+    Cancel,
+    Ignore,
+}
+
+pub fn map_prompt_key(key: KeyEvent) -> PromptKey {
     if key.modifiers.contains(KeyModifiers::CONTROL) {
         return match key.code {
-            KeyCode::Char('d') => Action::PageDown,
-            KeyCode::Char('u') => Action::PageUp,
-            _ => Action::None,
+            KeyCode::Char('c') => PromptKey::Cancel,
+            KeyCode::Char('u') => PromptKey::ClearLine,
+            KeyCode::Char('h') => PromptKey::Backspace,
+            _ => PromptKey::Ignore,
         };
     }
 
     match key.code {
-        KeyCode::Char('K') => Action::SelectPrevFile,
-        KeyCode::Char('J') => Action::SelectNextFile,
-        KeyCode::Char('H') => Action::TreeScrollLeft,
-        KeyCode::Char('L') => Action::TreeScrollRight,
-        KeyCode::Char('b') => Action::ToggleTree,
-        KeyCode::Char('R') => Action::Refresh,
-        KeyCode::Char('j') => Action::ScrollDown,
-        KeyCode::Char('k') => Action::ScrollUp,
-        KeyCode::Char('h') => Action::ScrollLeft,
-        KeyCode::Char('l') => Action::ScrollRight,
-        KeyCode::Char('g') => Action::PrefixG,
-        KeyCode::Char('G') => Action::GoBottom,
-        KeyCode::Char('n') => Action::NextChange,
-        KeyCode::Char('N') => Action::PrevChange,
-        KeyCode::Char('q') => Action::Quit,
-        _ => Action::None,
+        KeyCode::Char(c) => PromptKey::Insert(c),
+        KeyCode::Backspace => PromptKey::Backspace,
+        KeyCode::Tab => PromptKey::Complete,
+        KeyCode::BackTab => PromptKey::CompletePrev,
+        KeyCode::Enter => PromptKey::Submit,
+        KeyCode::Esc => PromptKey::Cancel,
+        _ => PromptKey::Ignore,
+    }
+}
+
+/// Fixed keys inside the help overlay, on top of the keymap's scroll actions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HelpKey {
+    Close,
+    Search,
+    ScrollDown,
+    ScrollUp,
+    PageDown,
+    PageUp,
+    Top,
+    Bottom,
+}
+
+pub fn map_help_key(key: KeyEvent) -> Option<HelpKey> {
+    match key.code {
+        KeyCode::Esc => Some(HelpKey::Close),
+        KeyCode::Char('/') if key.modifiers.is_empty() => Some(HelpKey::Search),
+        KeyCode::Down => Some(HelpKey::ScrollDown),
+        KeyCode::Up => Some(HelpKey::ScrollUp),
+        KeyCode::PageDown => Some(HelpKey::PageDown),
+        KeyCode::PageUp => Some(HelpKey::PageUp),
+        KeyCode::Home => Some(HelpKey::Top),
+        KeyCode::End => Some(HelpKey::Bottom),
+        _ => None,
     }
 }
 
+/// Mouse gestures fdf reacts to; only the left button is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseKind {
+    Press,
+    Drag,
+    Release,
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+}
+
+/// Shift turns the vertical wheel sideways, for terminals and mice without a horizontal one.
+pub fn map_mouse_kind(event: &MouseEvent) -> Option<MouseKind> {
+    let shift = event.modifiers.contains(KeyModifiers::SHIFT);
+    match event.kind {
+        MouseEventKind::Down(MouseButton::Left) => Some(MouseKind::Press),
+        MouseEventKind::Drag(MouseButton::Left) => Some(MouseKind::Drag),
+        MouseEventKind::Up(MouseButton::Left) => Some(MouseKind::Release),
+        MouseEventKind::ScrollUp if shift => Some(MouseKind::WheelLeft),
+        MouseEventKind::ScrollDown if shift => Some(MouseKind::WheelRight),
+        MouseEventKind::ScrollUp => Some(MouseKind::WheelUp),
+        MouseEventKind::ScrollDown => Some(MouseKind::WheelDown),
+        MouseEventKind::ScrollLeft => Some(MouseKind::WheelLeft),
+        MouseEventKind::ScrollRight => Some(MouseKind::WheelRight),
+        _ => None,
+    }
+}
+
+/// The part of the screen under the pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseTarget {
+    /// The file tree outside its rows, such as the border or the space below the last row.
+    Tree,
+    /// Index into the app's tree rows.
+    TreeRow(usize),
+    /// The border between the tree and the diff.
+    TreeBorder,
+    /// The border between the `HEAD` and worktree panes.
+    SplitBorder,
+    Diff,
+    Scrollbar,
+}
+
+/// A mouse event resolved against the current layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseInput {
+    pub kind: MouseKind,
+    pub target: Option<MouseTarget>,
+    /// Pointer row within the scrollbar and the scrollbar height, for dragging past its ends.
+    pub track: (usize, usize),
+    /// Tree size that dragging its border to the pointer would give, in percent.
+    pub tree_percent: u16,
+    /// `HEAD` pane share that dragging the diff divider to the pointer would give, in percent.
+    pub split_percent: u16,
+}
+
+/// Outcome of feeding the keys pressed so far to a [`Keymap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Action(Action),
+    /// The keys are the start of a longer binding; wait for the next one.
+    Pending,
+    Unbound,
+}
+
+/// Key sequences mapped to actions. A sequence may not also be the prefix of another one,
+/// so resolution never has to wait on a timeout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: HashMap<Vec<KeyChord>, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::preset("default").expect("default preset exists")
+    }
+}
+
+impl Keymap {
+    pub fn preset(name: &str) -> Result<Self> {
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+        };
+        keymap.bind_all(DEFAULT_BINDINGS)?;
+        match name {
+            "default" => {}
+            "less" => keymap.bind_all(LESS_BINDINGS)?,
+            other => bail!(
+                "unknown keymap preset {:?} (expected one of: {})",
+                other,
+                PRESETS.join(", ")
+            ),
+        }
+        Ok(keymap)
+    }
+
+    fn bind_all(&mut self, bindings: &[(&str, Action)]) -> Result<()> {
+        for (keys, action) in bindings {
+            self.bind(parse_sequence(keys)?, *action);
+        }
+        Ok(())
+    }
+
+    /// Binds `keys` to `action`, replacing any previous binding; `Action::None` unbinds.
+    pub fn bind(&mut self, keys: Vec<KeyChord>, action: Action) {
+        if action == Action::None {
+            self.bindings.remove(&keys);
+        } else {
+            self.bindings.insert(keys, action);
+        }
+    }
+
+    /// Reports every binding that is a strict prefix of another, which would make it unreachable.
+    pub fn validate(&self) -> Result<()> {
+        let mut conflicts: Vec<String> = Vec::new();
+        for short in self.bindings.keys() {
+            for long in self.bindings.keys() {
+                if long.len() > short.len() && long.starts_with(short) {
+                    conflicts.push(format!(
+                        "{:?} is bound, so {:?} can never be typed",
+                        format_sequence(short),
+                        format_sequence(long)
+                    ));
+                }
+            }
+        }
+        conflicts.sort();
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(conflicts.join("\n")))
+        }
+    }
+
+    /// Sequences bound to `action`, shortest first, formatted for display.
+    pub fn sequences_for(&self, action: Action) -> Vec<String> {
+        let mut sequences: Vec<String> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(keys, _)| format_sequence(keys))
+            .collect();
+        sequences.sort_by_key(|keys| (keys.len(), keys.clone()));
+        sequences
+    }
+
+    pub fn resolve(&self, keys: &[KeyChord]) -> Resolution {
+        if let Some(action) = self.bindings.get(keys) {
+            return Resolution::Action(*action);
+        }
+        if self
+            .bindings
+            .keys()
+            .any(|bound| bound.len() > keys.len() && bound.starts_with(keys))
+        {
+            Resolution::Pending
+        } else {
+            Resolution::Unbound
+        }
+    }
+}
+
+pub fn format_sequence(keys: &[KeyChord]) -> String {
+    keys.iter()
+        .map(KeyChord::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{map_key, Action};
+    use super::{parse_sequence, Action, KeyChord, Keymap, Resolution};
     use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
+    fn map_key(key: KeyEvent) -> Action {
+        match Keymap::default().resolve(&[KeyChord::from_event(key)]) {
+            Resolution::Action(action) => action,
+            Resolution::Pending | Resolution::Unbound => Action::None,
+        }
+    }
+
     #[test]
     fn maps_b_to_toggle_tree() {
         let action = map_key(KeyEvent::new(KeyCode::Char('b'), KeyModifiers::NONE));
@@ -86,6 +696,12 @@ mod tests {
         assert!(matches!(right, Action::TreeScrollRight));
     }
 
+    #[test]
+    fn maps_shift_x_to_load_anyway() {
+        let action = map_key(KeyEvent::new(KeyCode::Char('X'), KeyModifiers::SHIFT));
+        assert!(matches!(action, Action::LoadAnyway));
+    }
+
     #[test]
     fn does_not_map_lowercase_r_to_refresh() {
         let action = map_key(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::NONE));
@@ -104,4 +720,58 @@ mod tests {
         assert!(matches!(up, Action::None));
         assert!(matches!(down, Action::None));
     }
+
+    #[test]
+    fn resolves_multi_key_sequences() {
+        let keymap = Keymap::default();
+        let g = KeyChord::parse("g").unwrap();
+
+        assert_eq!(keymap.resolve(&[g]), Resolution::Pending);
+        assert_eq!(keymap.resolve(&[g, g]), Resolution::Action(Action::GoTop));
+        assert_eq!(
+            keymap.resolve(&[g, KeyChord::parse("x").unwrap()]),
+            Resolution::Unbound
+        );
+    }
+
+    #[test]
+    fn parses_chords_and_normalizes_terminal_variants() {
+        let ctrl_d = KeyChord::parse("ctrl-d").unwrap();
+        let reported = KeyChord::from_event(KeyEvent::new(
+            KeyCode::Char('D'),
+            KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+        ));
+
+        assert_eq!(ctrl_d, reported);
+        assert_eq!(KeyChord::parse("F5").unwrap().code, KeyCode::F(5));
+        assert_eq!(KeyChord::parse("ctrl--").unwrap().code, KeyCode::Char('-'));
+        assert_eq!(parse_sequence("ctrl-x  ctrl-c").unwrap().len(), 2);
+        assert!(KeyChord::parse("hyper-x").is_err());
+        assert!(KeyChord::parse("shift-j").is_err());
+        assert!(parse_sequence("  ").is_err());
+    }
+
+    #[test]
+    fn less_preset_adds_arrows_and_paging() {
+        let keymap = Keymap::preset("less").unwrap();
+        let resolve = |raw: &str| keymap.resolve(&parse_sequence(raw).unwrap());
+
+        assert_eq!(resolve("down"), Resolution::Action(Action::ScrollDown));
+        assert_eq!(resolve("space"), Resolution::Action(Action::PageDown));
+        assert_eq!(resolve("b"), Resolution::Action(Action::PageUp));
+        assert_eq!(resolve("g"), Resolution::Action(Action::GoTop));
+        assert_eq!(resolve("t"), Resolution::Action(Action::ToggleTree));
+        assert!(keymap.validate().is_ok());
+        assert!(Keymap::preset("emacs").is_err());
+    }
+
+    #[test]
+    fn validate_reports_bindings_shadowed_by_a_prefix() {
+        let mut keymap = Keymap::default();
+        keymap.bind(parse_sequence("g").unwrap(), Action::GoTop);
+
+        let err = keymap.validate().unwrap_err().to_string();
+
+        assert!(err.contains("\"g g\""), "{}", err);
+    }
 }
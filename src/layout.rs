@@ -0,0 +1,141 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Allowed share of the screen for the file tree, in percent.
+pub const TREE_PERCENT_RANGE: (u16, u16) = (10, 80);
+/// Allowed share of the diff area for the `HEAD` pane, in percent.
+pub const SPLIT_PERCENT_RANGE: (u16, u16) = (20, 80);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TreePosition {
+    Left,
+    Right,
+    Bottom,
+}
+
+impl TreePosition {
+    pub fn next(self) -> Self {
+        match self {
+            TreePosition::Left => TreePosition::Right,
+            TreePosition::Right => TreePosition::Bottom,
+            TreePosition::Bottom => TreePosition::Left,
+        }
+    }
+}
+
+/// Pane sizes and placement chosen with keys or the mouse, kept between sessions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutSettings {
+    pub tree_position: TreePosition,
+    /// Tree width (or height, at the bottom) as a percentage of the screen.
+    pub tree_percent: u16,
+    /// `HEAD` pane width as a percentage of the diff area.
+    pub split_percent: u16,
+}
+
+impl Default for LayoutSettings {
+    fn default() -> Self {
+        Self {
+            tree_position: TreePosition::Left,
+            tree_percent: 28,
+            split_percent: 50,
+        }
+    }
+}
+
+impl LayoutSettings {
+    pub fn set_tree_percent(&mut self, percent: u16) {
+        let (min, max) = TREE_PERCENT_RANGE;
+        self.tree_percent = percent.clamp(min, max);
+    }
+
+    pub fn set_split_percent(&mut self, percent: u16) {
+        let (min, max) = SPLIT_PERCENT_RANGE;
+        self.split_percent = percent.clamp(min, max);
+    }
+
+    /// Brings values edited by hand back into range.
+    fn clamped(mut self) -> Self {
+        self.set_tree_percent(self.tree_percent);
+        self.set_split_percent(self.split_percent);
+        self
+    }
+}
+
+/// `$XDG_STATE_HOME/fdf`, falling back to `~/.local/state/fdf`; also holds the error log.
+pub fn state_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+        })?;
+    Some(base.join("fdf"))
+}
+
+pub fn state_path() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("layout.toml"))
+}
+
+/// The saved layout, or the default one if there is none. The file is written by fdf, not the
+/// user, so an unreadable one is replaced on the next save rather than reported.
+pub fn load() -> LayoutSettings {
+    state_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|text| parse(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn parse(text: &str) -> Result<LayoutSettings> {
+    let settings: LayoutSettings = toml::from_str(text)?;
+    Ok(settings.clamped())
+}
+
+pub fn save(settings: &LayoutSettings) -> Result<()> {
+    let Some(path) = state_path() else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+
+    // Write then rename, so two sessions exiting together cannot leave a torn file.
+    let temp = path.with_extension(format!("toml.{}", std::process::id()));
+    fs::write(&temp, toml::to_string(settings)?)
+        .with_context(|| format!("failed to write {}", temp.display()))?;
+    fs::rename(&temp, &path).with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, LayoutSettings, TreePosition};
+
+    #[test]
+    fn round_trips_through_toml() {
+        let settings = LayoutSettings {
+            tree_position: TreePosition::Bottom,
+            tree_percent: 40,
+            split_percent: 35,
+        };
+        let text = toml::to_string(&settings).unwrap();
+
+        assert!(text.contains("tree_position = \"bottom\""), "{}", text);
+        assert_eq!(parse(&text).unwrap(), settings);
+    }
+
+    #[test]
+    fn fills_in_missing_values_and_clamps_out_of_range_ones() {
+        let settings = parse("tree_percent = 95").unwrap();
+
+        assert_eq!(settings.tree_position, TreePosition::Left);
+        assert_eq!(settings.tree_percent, 80);
+        assert_eq!(settings.split_percent, 50);
+        assert!(parse("tree_position = \"top\"").is_err());
+    }
+}
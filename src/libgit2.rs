@@ -0,0 +1,204 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use anyhow::{anyhow, Context, Result};
+use git2::{ErrorCode, Repository, Status, StatusOptions};
+
+use crate::git::GitBackend;
+use crate::model::{ChangedFile, FileStatus};
+
+const STAGED: Status = Status::INDEX_NEW
+    .union(Status::INDEX_MODIFIED)
+    .union(Status::INDEX_DELETED)
+    .union(Status::INDEX_RENAMED)
+    .union(Status::INDEX_TYPECHANGE);
+
+const UNSTAGED: Status = Status::WT_MODIFIED
+    .union(Status::WT_DELETED)
+    .union(Status::WT_RENAMED)
+    .union(Status::WT_TYPECHANGE);
+
+const GITLINK_MODE: u32 = 0o160000;
+
+/// In-process backend built on libgit2; avoids spawning `git` for status and blob reads.
+pub struct Libgit2Backend {
+    repo_root: PathBuf,
+    // `Repository` is `Send` but not `Sync`; loads and refreshes take turns.
+    repo: Mutex<Repository>,
+}
+
+impl Libgit2Backend {
+    pub fn discover(start: &Path) -> Result<Self> {
+        let repo = Repository::discover(start).context("not inside a git repository")?;
+        let repo_root = repo
+            .workdir()
+            .ok_or_else(|| anyhow!("bare repositories have no worktree to compare"))?
+            .to_path_buf();
+        // libgit2 reports the worktree with a trailing separator.
+        let repo_root = repo_root.components().collect();
+
+        Ok(Self {
+            repo_root,
+            repo: Mutex::new(repo),
+        })
+    }
+
+    fn repo(&self) -> MutexGuard<'_, Repository> {
+        self.repo.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Runs `read` on the `HEAD` blob at `path`; `None` if HEAD is unborn or lacks the path.
+    fn with_head_blob<T>(
+        &self,
+        path: &Path,
+        read: impl FnOnce(&git2::Blob<'_>) -> T,
+    ) -> Result<Option<T>> {
+        let repo = self.repo();
+        let head = match repo.head() {
+            Ok(head) => head,
+            Err(err) if err.code() == ErrorCode::UnbornBranch => return Ok(None),
+            Err(err) => return Err(err).context("failed to resolve HEAD"),
+        };
+        let tree = head
+            .peel_to_tree()
+            .context("HEAD does not point to a tree")?;
+        let entry = match tree.get_path(path) {
+            Ok(entry) => entry,
+            Err(err) if err.code() == ErrorCode::NotFound => return Ok(None),
+            Err(err) => return Err(err).with_context(|| format!("failed to look up {:?}", path)),
+        };
+
+        let object = entry.to_object(&repo)?;
+        // Submodules and directories have no blob at HEAD.
+        Ok(object.as_blob().map(read))
+    }
+
+    /// Runs `read` on the stage-0 index blob at `path`; `None` if the index lacks it.
+    fn with_index_blob<T>(
+        &self,
+        path: &Path,
+        read: impl FnOnce(&git2::Blob<'_>) -> T,
+    ) -> Result<Option<T>> {
+        let repo = self.repo();
+        let mut index = repo.index().context("failed to open the index")?;
+        // The repository keeps its index in memory; pick up staging done since.
+        index.read(false).context("failed to read the index")?;
+        // Gitlinks point at commits of another repository and have no blob.
+        let Some(entry) = index
+            .get_path(path, 0)
+            .filter(|entry| entry.mode != GITLINK_MODE)
+        else {
+            return Ok(None);
+        };
+        let blob = repo
+            .find_blob(entry.id)
+            .with_context(|| format!("failed to read {:?} from the index", path))?;
+        Ok(Some(read(&blob)))
+    }
+}
+
+impl GitBackend for Libgit2Backend {
+    fn repo_root(&self) -> &Path {
+        &self.repo_root
+    }
+
+    fn git_dir(&self) -> Result<PathBuf> {
+        Ok(self.repo().path().components().collect())
+    }
+
+    fn collect_changed_files(&self) -> Result<Vec<ChangedFile>> {
+        let repo = self.repo();
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .include_ignored(false)
+            .renames_head_to_index(true);
+        let statuses = repo
+            .statuses(Some(&mut options))
+            .context("failed to read repository status")?;
+
+        let mut files = Vec::new();
+        for entry in statuses.iter() {
+            let status = entry.status();
+            let path = entry
+                .head_to_index()
+                .and_then(|delta| delta.new_file().path())
+                .or_else(|| {
+                    entry
+                        .index_to_workdir()
+                        .and_then(|delta| delta.new_file().path())
+                })
+                .map(Path::to_path_buf)
+                .or_else(|| entry.path().map(PathBuf::from));
+            // Untracked nested repositories are reported as directories, as with the CLI.
+            let Some(path) = path.filter(|path| !path.as_os_str().to_string_lossy().ends_with('/'))
+            else {
+                continue;
+            };
+
+            let file_status = if status.contains(Status::CONFLICTED) {
+                FileStatus {
+                    staged: true,
+                    unstaged: true,
+                    untracked: false,
+                    ..FileStatus::default()
+                }
+            } else if status == Status::WT_NEW {
+                FileStatus {
+                    staged: false,
+                    unstaged: true,
+                    untracked: true,
+                    ..FileStatus::default()
+                }
+            } else {
+                FileStatus {
+                    staged: status.intersects(STAGED),
+                    unstaged: status.intersects(UNSTAGED),
+                    untracked: false,
+                    deleted: status.intersects(Status::WT_DELETED | Status::INDEX_DELETED),
+                    deleted_in_index: status.contains(Status::INDEX_DELETED),
+                    deleted_in_worktree: status.contains(Status::WT_DELETED),
+                    ..FileStatus::default()
+                }
+            };
+
+            if file_status.staged || file_status.unstaged {
+                files.push(ChangedFile::new(path, file_status));
+            }
+        }
+
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
+
+    fn head_blob_size(&self, path: &Path) -> Result<u64> {
+        Ok(self
+            .with_head_blob(path, |blob| blob.size() as u64)?
+            .unwrap_or(0))
+    }
+
+    fn read_head_blob(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        self.with_head_blob(path, |blob| blob.content().to_vec())
+    }
+
+    fn index_blob_size(&self, path: &Path) -> Result<u64> {
+        Ok(self
+            .with_index_blob(path, |blob| blob.size() as u64)?
+            .unwrap_or(0))
+    }
+
+    fn read_index_blob(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        self.with_index_blob(path, |blob| blob.content().to_vec())
+    }
+
+    fn read_blob(&self, id: &str) -> Result<Option<Vec<u8>>> {
+        let repo = self.repo();
+        let blob = match repo.revparse_single(id) {
+            Ok(object) => object.peel_to_blob().ok(),
+            Err(err) if matches!(err.code(), ErrorCode::NotFound | ErrorCode::Ambiguous) => None,
+            Err(err) => return Err(err).with_context(|| format!("failed to read blob {}", id)),
+        };
+        Ok(blob.map(|blob| blob.content().to_vec()))
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::layout;
+
+/// The log is moved to `fdf.log.old` once it grows past this, so it never needs cleaning up.
+const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+/// `$XDG_STATE_HOME/fdf/fdf.log`.
+pub fn log_path() -> Option<PathBuf> {
+    layout::state_dir().map(|dir| dir.join("fdf.log"))
+}
+
+/// Appends an error with its full cause chain to the log at `path`. The status bar only has
+/// room for one line, so this is where the details end up; failing to write it is ignored.
+pub fn log_error(path: &Path, err: &anyhow::Error) {
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    if fs::metadata(path).is_ok_and(|meta| meta.len() > MAX_LOG_BYTES) {
+        let _ = fs::rename(path, path.with_extension("log.old"));
+    }
+
+    let Ok(mut log) = OpenOptions::new().create(true).append(true).open(path) else {
+        return;
+    };
+    let _ = writeln!(log, "{}", format_entry(SystemTime::now(), err));
+}
+
+fn format_entry(time: SystemTime, err: &anyhow::Error) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let mut entry = format!("[{}] error: {}", seconds, err);
+    for cause in err.chain().skip(1) {
+        entry.push_str(&format!("\n    caused by: {}", cause));
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use anyhow::anyhow;
+
+    use super::{format_entry, log_error, MAX_LOG_BYTES};
+
+    #[test]
+    fn entries_list_every_cause() {
+        let err = anyhow!("permission denied").context("failed to read src/secret.rs");
+        let entry = format_entry(UNIX_EPOCH + Duration::from_secs(42), &err);
+
+        assert_eq!(
+            entry,
+            "[42] error: failed to read src/secret.rs\n    caused by: permission denied"
+        );
+    }
+
+    #[test]
+    fn errors_are_appended_and_a_full_log_is_rotated() {
+        let dir = std::env::temp_dir().join(format!("fdf-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("fdf.log");
+
+        log_error(&path, &anyhow!("first"));
+        log_error(&path, &anyhow!("second"));
+        let log = fs::read_to_string(&path).unwrap();
+        assert_eq!(log.lines().count(), 2, "{}", log);
+        assert!(log.lines().next().unwrap().ends_with("error: first"));
+
+        fs::write(&path, "x".repeat(MAX_LOG_BYTES as usize + 1)).unwrap();
+        log_error(&path, &anyhow!("third"));
+        let old = fs::metadata(dir.join("fdf.log.old")).unwrap();
+        assert_eq!(old.len(), MAX_LOG_BYTES + 1);
+        let log = fs::read_to_string(&path).unwrap();
+        assert!(log.ends_with("error: third\n"), "{}", log);
+        assert_eq!(log.lines().count(), 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,58 +1,369 @@
 mod app;
+mod cli;
+mod command;
+mod compare;
+mod config;
 mod diff;
+mod export;
 mod git;
+mod help;
+mod highlight;
 mod input;
+mod layout;
+#[cfg(feature = "libgit2")]
+mod libgit2;
+mod logging;
+mod mode;
 mod model;
+mod patch;
+mod print;
+mod screen;
+mod theme;
 mod tree;
 mod ui;
+mod watch;
+mod worker;
 
-use std::io;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 use crossterm::event::{self, Event};
-use crossterm::execute;
-use crossterm::terminal::{
-    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
-};
 use ratatui::backend::CrosstermBackend;
+use ratatui::layout::Rect;
 use ratatui::Terminal;
 
-use crate::app::App;
-use crate::input::map_key;
+use crate::app::{App, FileFilter, OpenRequest};
+use crate::compare::PathsBackend;
+use crate::config::Config;
+use crate::git::GitBackend;
+use crate::patch::PatchBackend;
+use crate::theme::ColorDepth;
 
-fn main() -> Result<()> {
-    let repo_root = git::repo_root()?;
-    let mut app = App::new(repo_root)?;
+fn main() {
+    // Startup problems are usually fixed by the user, so they get a message, not a backtrace.
+    if let Err(err) = try_main() {
+        eprintln!("fdf: {:#}", err);
+        std::process::exit(1);
+    }
+}
+
+fn try_main() -> Result<()> {
+    let options = cli::parse(std::env::args().skip(1))?;
+    if options.show_help {
+        print!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    // Config mistakes are reported before the terminal switches to the alternate screen.
+    let config = config::load(options.config_path.as_deref())?;
+    let git: Arc<dyn GitBackend> = match options.paths.as_slice() {
+        [] if options.patch.is_some() => {
+            let source = options
+                .patch
+                .as_deref()
+                .expect("checked by the match guard");
+            let patch = patch::parse(&read_patch(source)?);
+            if patch.files.is_empty() {
+                bail!("no file changes found in {}", source.display());
+            }
+            Arc::new(PatchBackend::new(
+                patch,
+                git::discover(Path::new(".")).ok(),
+            )?)
+        }
+        [left, right] if options.patch.is_none() => Arc::new(PathsBackend::new(left, right)?),
+        [] => match pager_input()? {
+            Some(PipedInput::Diff(patch)) => Arc::new(PatchBackend::new(
+                patch,
+                git::discover(Path::new(".")).ok(),
+            )?),
+            Some(PipedInput::Paged) => return Ok(()),
+            None => git::discover(Path::new("."))?,
+        },
+        _ if options.patch.is_some() => bail!("--patch cannot be combined with paths to compare"),
+        paths => bail!(
+            "expected two files or directories to compare, got {}\n\n{}",
+            paths.len(),
+            cli::USAGE
+        ),
+    };
 
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    // Piped or redirected output gets the diffs as text instead of an interactive session.
+    if options.print || !io::stdout().is_terminal() {
+        return print_changes(git.as_ref(), &config, &options);
+    }
+    if options.has_print_options() {
+        bail!("--width, --context and --filter only apply to --print output");
+    }
+
+    let saved_layout = layout::load();
+    let color_depth = options.color.unwrap_or_else(ColorDepth::from_env);
+    let mut app = App::new(git, options.limits, config, saved_layout, color_depth)?;
+
+    screen::install_panic_hook();
+    screen::enter().context(
+        "could not set up the terminal; fdf needs an interactive terminal (use --print to write \
+         the diffs to stdout instead)",
+    )?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
 
     let run_result = run(&mut terminal, &mut app);
 
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-    terminal.show_cursor()?;
+    screen::leave()?;
+
+    // Losing the layout is not worth failing an otherwise clean exit over.
+    if app.layout != saved_layout {
+        if let Err(err) = layout::save(&app.layout) {
+            eprintln!("fdf: could not save the pane layout: {:#}", err);
+        }
+    }
 
     run_result
 }
 
+/// What was piped into fdf running as a pager.
+enum PipedInput {
+    Diff(patch::Patch),
+    /// Not a diff (`git log`, `git branch`); already shown through `$PAGER`.
+    Paged,
+}
+
+/// Input piped into fdf, by git running it as the pager (`pager.diff = fdf`) or by hand
+/// (`git diff | fdf`). Git only starts a pager for a terminal, and without one the piped input
+/// is left alone so scripts and CI jobs still get the repository's changes.
+///
+/// A diff is read to the end before the first frame, since the file list needs all of it.
+/// Input without a diff near the top is streamed to `$PAGER` instead of being buffered.
+fn pager_input() -> Result<Option<PipedInput>> {
+    if io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Ok(None);
+    }
+    let mut stdin = io::stdin().lock();
+    let mut input = Vec::new();
+    let diff_found = patch::read_diff_head(&mut stdin, &mut input)
+        .context("failed to read the diff from stdin")?;
+    if input.is_empty() {
+        return Ok(None);
+    }
+    if diff_found {
+        stdin
+            .read_to_end(&mut input)
+            .context("failed to read the diff from stdin")?;
+        let patch = patch::parse(&String::from_utf8_lossy(&input));
+        if !patch.files.is_empty() {
+            return Ok(Some(PipedInput::Diff(patch)));
+        }
+    }
+    page(&input, &mut stdin)?;
+    Ok(Some(PipedInput::Paged))
+}
+
+/// Shows `head` and the rest of `input` through `$PAGER` (default `less`, with git's `LESS=FRX`
+/// unless set), as git would have without fdf; straight to stdout if the pager cannot start.
+fn page(head: &[u8], input: &mut impl Read) -> Result<()> {
+    let pager = env::var("PAGER").unwrap_or_default();
+    let mut words = pager.split_whitespace();
+    // `PAGER=fdf` would only hand the input back to fdf.
+    let (program, args) = match words.next() {
+        Some(program) if Path::new(program).file_name() != Some("fdf".as_ref()) => {
+            (program, words.collect())
+        }
+        _ => ("less", Vec::new()),
+    };
+    let mut command = Command::new(program);
+    command.args(args).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+
+    let Ok(mut child) = command.spawn() else {
+        let mut stdout = io::stdout().lock();
+        return ignore_broken_pipe(
+            stdout
+                .write_all(head)
+                .and_then(|()| io::copy(input, &mut stdout).map(drop)),
+        );
+    };
+    let mut pipe = child.stdin.take().expect("stdin is piped");
+    // Quitting the pager early closes the pipe.
+    let written = ignore_broken_pipe(
+        pipe.write_all(head)
+            .and_then(|()| io::copy(input, &mut pipe).map(drop)),
+    );
+    drop(pipe);
+    child
+        .wait()
+        .with_context(|| format!("failed to run {}", program))?;
+    written
+}
+
+fn ignore_broken_pipe(result: io::Result<()>) -> Result<()> {
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        other => other.context("failed to page the input"),
+    }
+}
+
+/// The contents of a `--patch` argument; `-` is stdin.
+fn read_patch(source: &Path) -> Result<String> {
+    let input = if source == Path::new("-") {
+        let mut input = Vec::new();
+        io::stdin()
+            .read_to_end(&mut input)
+            .context("failed to read the patch from stdin")?;
+        input
+    } else {
+        fs::read(source).with_context(|| format!("failed to read {}", source.display()))?
+    };
+    Ok(String::from_utf8_lossy(&input).into_owned())
+}
+
+/// `--print`: every changed file matching the filters, side by side on stdout.
+fn print_changes(git: &dyn GitBackend, config: &Config, options: &cli::Options) -> Result<()> {
+    let filters = options
+        .filters
+        .iter()
+        .map(|pattern| FileFilter::new(pattern))
+        .collect::<Result<Vec<_>>>()?;
+    let files = git
+        .collect_changed_files()?
+        .into_iter()
+        .filter(|file| filters.is_empty() || filters.iter().any(|f| f.matches(&file.path)))
+        .collect();
+
+    let stdout = io::stdout();
+    let is_terminal = stdout.is_terminal();
+    let print_options = print::PrintOptions {
+        width: options
+            .width
+            .unwrap_or_else(|| print::default_width(is_terminal)),
+        depth: options.color.unwrap_or_else(|| {
+            if is_terminal {
+                ColorDepth::from_env()
+            } else {
+                ColorDepth::Mono
+            }
+        }),
+        context: options.context,
+    };
+
+    let result = print::print_files(
+        git,
+        files,
+        &options.limits,
+        &config.theme,
+        &print_options,
+        &mut stdout.lock(),
+    );
+    // `fdf --print | head` closing the pipe early is not a failure.
+    match result {
+        Err(err)
+            if err
+                .downcast_ref::<io::Error>()
+                .is_some_and(|err| err.kind() == io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        other => other,
+    }
+}
+
 fn run(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App) -> Result<()> {
+    // Without handlers the default actions still apply, only without restoring the terminal.
+    #[cfg(unix)]
+    let mut signals = screen::SignalWatch::spawn().ok();
+
     while !app.should_quit {
+        #[cfg(unix)]
+        for request in signals.iter_mut().flat_map(screen::SignalWatch::pending) {
+            match request {
+                screen::SignalRequest::Quit => app.should_quit = true,
+                screen::SignalRequest::Suspend => app.suspend_requested = true,
+                // The shell may have reset the terminal while fdf was stopped.
+                screen::SignalRequest::Redraw => {
+                    screen::enter()?;
+                    terminal.clear()?;
+                }
+            }
+        }
+        if app.should_quit {
+            break;
+        }
+
+        app.poll_background();
         terminal.draw(|frame| {
-            app.set_viewport_rows(ui::viewport_rows(frame.area()));
-            ui::render(frame, app);
+            let layout = ui::layout(frame.area(), app);
+            app.set_viewport(layout.diff_rows(), layout.text_width(), layout.tree_rows());
+            ui::render(frame, app, &layout);
         })?;
 
-        if event::poll(Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
-                app.on_action(map_key(key))?;
+        if event::poll(Duration::from_millis(50))? {
+            match event::read()? {
+                Event::Key(key) => {
+                    let result = app.on_key(key);
+                    app.report(result);
+                }
+                Event::Mouse(mouse) => {
+                    let size = terminal.size()?;
+                    let layout = ui::layout(Rect::new(0, 0, size.width, size.height), app);
+                    if let Some(input) = ui::mouse_input(&layout, app, mouse) {
+                        let result = app.on_mouse(input);
+                        app.report(result);
+                    }
+                }
+                _ => {}
             }
         }
+
+        if let Some(request) = app.open_request.take() {
+            let outcome = open_in_editor(terminal, app.git.repo_root(), &request);
+            let result = app.finish_open(outcome);
+            app.report(result);
+        }
+
+        #[cfg(unix)]
+        if std::mem::take(&mut app.suspend_requested) {
+            screen::suspend()?;
+            terminal.clear()?;
+        }
     }
 
     Ok(())
 }
+
+/// Hands the terminal to `$VISUAL`/`$EDITOR` (default `vi`) at the requested line, then takes
+/// it back.
+fn open_in_editor(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    repo_root: &Path,
+    request: &OpenRequest,
+) -> Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| anyhow!("$VISUAL/$EDITOR is empty"))?;
+
+    screen::leave()?;
+    let status = Command::new(program)
+        .args(words)
+        .arg(format!("+{}", request.line))
+        .arg(repo_root.join(&request.path))
+        .status();
+    screen::enter()?;
+    terminal.clear()?;
+
+    let status = status.with_context(|| format!("failed to run {}", program))?;
+    if !status.success() {
+        bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
@@ -0,0 +1,258 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+
+use crate::git::{bytes_to_content, load_file_contents, GitBackend};
+use crate::model::{ChangedFile, ContentData, LoadLimits};
+
+/// Which two versions of each file `:mode` compares.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompareMode {
+    /// `HEAD` against the worktree: everything not committed.
+    #[default]
+    Worktree,
+    /// `HEAD` against the index: what the next commit would contain.
+    Staged,
+    /// The index against the worktree: what `git add` would stage.
+    Unstaged,
+}
+
+pub const MODES: &[&str] = &["worktree", "staged", "unstaged"];
+
+impl CompareMode {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "worktree" => Ok(Self::Worktree),
+            "staged" => Ok(Self::Staged),
+            "unstaged" => Ok(Self::Unstaged),
+            _ => Err(anyhow!(
+                "unknown mode {:?}; expected one of: {}",
+                name,
+                MODES.join(", ")
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Worktree => "worktree",
+            Self::Staged => "staged",
+            Self::Unstaged => "unstaged",
+        }
+    }
+}
+
+/// Wraps a repository backend so it lists and loads the files of `mode`: the "`HEAD`" side
+/// reads the index for [`CompareMode::Unstaged`], the worktree side does for
+/// [`CompareMode::Staged`].
+pub struct ModeBackend {
+    repo: Arc<dyn GitBackend>,
+    mode: CompareMode,
+}
+
+impl ModeBackend {
+    /// The backend for `mode`; worktree comparisons use `repo` itself.
+    pub fn wrap(repo: Arc<dyn GitBackend>, mode: CompareMode) -> Arc<dyn GitBackend> {
+        match mode {
+            CompareMode::Worktree => repo,
+            mode => Arc::new(Self { repo, mode }),
+        }
+    }
+}
+
+impl GitBackend for ModeBackend {
+    fn repo_root(&self) -> &Path {
+        self.repo.repo_root()
+    }
+
+    fn git_dir(&self) -> Result<PathBuf> {
+        self.repo.git_dir()
+    }
+
+    fn collect_changed_files(&self) -> Result<Vec<ChangedFile>> {
+        let mut files = self.repo.collect_changed_files()?;
+        // Untracked files are in neither the index nor `HEAD`.
+        files.retain(|file| match self.mode {
+            CompareMode::Worktree => true,
+            CompareMode::Staged => file.status.staged,
+            CompareMode::Unstaged => file.status.unstaged && !file.status.untracked,
+        });
+        // A file can be changed in the index and deleted from the worktree, or the other way.
+        for file in &mut files {
+            file.status.deleted = match self.mode {
+                CompareMode::Worktree => file.status.deleted,
+                CompareMode::Staged => file.status.deleted_in_index,
+                CompareMode::Unstaged => file.status.deleted_in_worktree,
+            };
+        }
+        Ok(files)
+    }
+
+    fn head_blob_size(&self, path: &Path) -> Result<u64> {
+        match self.mode {
+            CompareMode::Unstaged => self.repo.index_blob_size(path),
+            _ => self.repo.head_blob_size(path),
+        }
+    }
+
+    fn read_head_blob(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        match self.mode {
+            CompareMode::Unstaged => self.repo.read_index_blob(path),
+            _ => self.repo.read_head_blob(path),
+        }
+    }
+
+    fn index_blob_size(&self, path: &Path) -> Result<u64> {
+        self.repo.index_blob_size(path)
+    }
+
+    fn read_index_blob(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        self.repo.read_index_blob(path)
+    }
+
+    fn read_blob(&self, id: &str) -> Result<Option<Vec<u8>>> {
+        self.repo.read_blob(id)
+    }
+
+    fn side_names(&self) -> [String; 2] {
+        let [head, worktree] = self.repo.side_names();
+        match self.mode {
+            CompareMode::Worktree => [head, worktree],
+            CompareMode::Staged => [head, "index".to_string()],
+            CompareMode::Unstaged => ["index".to_string(), worktree],
+        }
+    }
+
+    fn load_contents(&self, file: &mut ChangedFile, limits: &LoadLimits) -> Result<()> {
+        if self.mode != CompareMode::Staged {
+            return load_file_contents(self, file, limits);
+        }
+
+        if !file.force_load {
+            let old_bytes = self.repo.head_blob_size(&file.path)?;
+            let new_bytes = self.repo.index_blob_size(&file.path)?;
+            if old_bytes.max(new_bytes) > limits.max_file_bytes {
+                file.old_content = Some(ContentData::TooLarge { bytes: old_bytes });
+                file.new_content = Some(ContentData::TooLarge { bytes: new_bytes });
+                return Ok(());
+            }
+        }
+
+        // A side without the path (added or deleted in the index) shows as empty.
+        let old = self.repo.read_head_blob(&file.path)?;
+        let new = self.repo.read_index_blob(&file.path)?;
+        file.sides_exist = [old.is_some(), new.is_some()];
+        let read = |blob: Option<Vec<u8>>| {
+            blob.map(bytes_to_content)
+                .unwrap_or_else(|| ContentData::Text(String::new()))
+        };
+        file.old_content = Some(read(old));
+        file.new_content = Some(read(new));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::tests::{fixture_repo, git};
+    use crate::git::CliBackend;
+    use std::fs;
+
+    fn text(content: &Option<ContentData>) -> &str {
+        match content {
+            Some(ContentData::Text(text)) => text,
+            other => panic!("expected text, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn modes_split_staged_and_unstaged_changes() {
+        let root = fixture_repo("compare-modes");
+        // `staged.txt` is "two\n" at HEAD and "two!\n" in the index.
+        fs::write(root.join("staged.txt"), "two!!\n").unwrap();
+        let repo: Arc<dyn GitBackend> = Arc::new(CliBackend::new(root));
+        let listed = |mode| -> Vec<String> {
+            ModeBackend::wrap(Arc::clone(&repo), mode)
+                .collect_changed_files()
+                .unwrap()
+                .iter()
+                .map(|file| file.path.display().to_string())
+                .collect()
+        };
+        assert_eq!(listed(CompareMode::Staged), ["staged.txt"]);
+        assert_eq!(
+            listed(CompareMode::Unstaged),
+            ["deleted.txt", "modified.txt", "staged.txt"]
+        );
+        assert_eq!(listed(CompareMode::Worktree).len(), 4);
+
+        let load = |mode| {
+            let backend = ModeBackend::wrap(Arc::clone(&repo), mode);
+            let mut file = backend
+                .collect_changed_files()
+                .unwrap()
+                .into_iter()
+                .find(|file| file.path == Path::new("staged.txt"))
+                .expect("staged.txt listed");
+            backend
+                .load_contents(&mut file, &LoadLimits::default())
+                .unwrap();
+            (backend.side_names(), file)
+        };
+
+        let (names, file) = load(CompareMode::Staged);
+        assert_eq!(names, ["HEAD".to_string(), "index".to_string()]);
+        assert_eq!(
+            (text(&file.old_content), text(&file.new_content)),
+            ("two\n", "two!\n")
+        );
+        let (names, file) = load(CompareMode::Unstaged);
+        assert_eq!(names, ["index".to_string(), "worktree".to_string()]);
+        assert_eq!(
+            (text(&file.old_content), text(&file.new_content)),
+            ("two!\n", "two!!\n")
+        );
+        let (_, file) = load(CompareMode::Worktree);
+        assert_eq!(
+            (text(&file.old_content), text(&file.new_content)),
+            ("two\n", "two!!\n")
+        );
+    }
+
+    #[test]
+    fn deletions_show_on_the_side_they_happened() {
+        let root = fixture_repo("compare-deleted");
+        // `same.txt` is changed in the index and then deleted from the worktree.
+        fs::write(root.join("same.txt"), "four!\n").unwrap();
+        git(&root, &["add", "same.txt"]);
+        fs::remove_file(root.join("same.txt")).unwrap();
+        let repo: Arc<dyn GitBackend> = Arc::new(CliBackend::new(root));
+        let indicators = |mode| -> Vec<(String, &str)> {
+            ModeBackend::wrap(Arc::clone(&repo), mode)
+                .collect_changed_files()
+                .unwrap()
+                .iter()
+                .map(|file| (file.path.display().to_string(), file.status.indicator()))
+                .collect()
+        };
+
+        assert_eq!(
+            indicators(CompareMode::Staged),
+            [
+                ("same.txt".to_string(), "[SU]"),
+                ("staged.txt".to_string(), "[S]")
+            ]
+        );
+        assert_eq!(
+            indicators(CompareMode::Unstaged),
+            [
+                ("deleted.txt".to_string(), "[D]"),
+                ("modified.txt".to_string(), "[U]"),
+                ("same.txt".to_string(), "[D]")
+            ]
+        );
+        assert!(indicators(CompareMode::Worktree).contains(&("same.txt".to_string(), "[D]")));
+    }
+}
@@ -1,16 +1,27 @@
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, Default)]
 pub struct FileStatus {
     pub staged: bool,
     pub unstaged: bool,
     pub untracked: bool,
+    /// The file is gone from the right side of the comparison shown.
+    pub deleted: bool,
+    /// Deleted in the index (`D.`) or in the worktree only (`.D`), for `:mode` to pick from.
+    pub deleted_in_index: bool,
+    pub deleted_in_worktree: bool,
+    pub renamed: bool,
 }
 
 impl FileStatus {
     pub fn indicator(&self) -> &'static str {
         match (self.staged, self.unstaged, self.untracked) {
             (_, _, true) => "[N]",
+            _ if self.deleted => "[D]",
+            _ if self.renamed => "[R]",
             (true, true, _) => "[SU]",
             (true, false, _) => "[S]",
             (false, true, _) => "[U]",
@@ -23,9 +34,29 @@ impl FileStatus {
 pub struct ChangedFile {
     pub path: PathBuf,
     pub status: FileStatus,
+    /// Where a renamed file came from, for sources that track renames.
+    pub renamed_from: Option<PathBuf>,
     pub old_content: Option<ContentData>,
     pub new_content: Option<ContentData>,
+    /// Whether the left and right side have the file at all, set with the contents; a missing
+    /// side loads as empty text.
+    pub sides_exist: [bool; 2],
     pub aligned_rows: Option<Vec<AlignedRow>>,
+    pub force_load: bool,
+    pub degraded: bool,
+    /// The source set the rows from its hunks, so they are not aligned with diff options such
+    /// as ignoring whitespace.
+    pub hunks_only: bool,
+    /// Hash of both sides' contents, set once they are loaded.
+    pub content_hash: Option<u64>,
+    /// The load this one replaces after a refresh, until the file is reloaded.
+    pub previous: Option<Arc<PreviousLoad>>,
+    /// Maps worktree line numbers of `previous` to the current ones; `None` means unchanged.
+    pub line_remap: Option<LineRemap>,
+    /// Row indices whose text changed relative to `previous`.
+    pub changed_rows: HashSet<usize>,
+    /// Why the last load of this file failed; shown in place of the diff until a load succeeds.
+    pub load_error: Option<String>,
 }
 
 impl ChangedFile {
@@ -33,17 +64,216 @@ impl ChangedFile {
         Self {
             path,
             status,
+            renamed_from: None,
             old_content: None,
             new_content: None,
+            sides_exist: [true, true],
             aligned_rows: None,
+            force_load: false,
+            degraded: false,
+            hunks_only: false,
+            content_hash: None,
+            previous: None,
+            line_remap: None,
+            changed_rows: HashSet::new(),
+            load_error: None,
         }
     }
+
+    /// The path as shown in titles: `old → new` for renames.
+    pub fn display_name(&self) -> String {
+        match &self.renamed_from {
+            Some(from) => format!("{} → {}", from.display(), self.path.display()),
+            None => self.path.display().to_string(),
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.aligned_rows.is_some()
+    }
+
+    /// True if the file holds loaded data, current or from before a refresh.
+    pub fn is_resident(&self) -> bool {
+        self.is_loaded() || self.previous.is_some()
+    }
+
+    /// Approximate heap size of the loaded contents and aligned rows.
+    pub fn resident_bytes(&self) -> u64 {
+        let current = loaded_bytes(
+            &self.old_content,
+            &self.new_content,
+            self.aligned_rows.as_deref(),
+        );
+        let previous = self.previous.as_ref().map_or(0, |previous| {
+            loaded_bytes(
+                &previous.old_content,
+                &previous.new_content,
+                Some(&previous.aligned_rows),
+            )
+        });
+        current + previous
+    }
+
+    /// Drops loaded contents and rows so the file is reloaded on next use.
+    pub fn unload(&mut self) {
+        self.old_content = None;
+        self.new_content = None;
+        self.aligned_rows = None;
+        self.degraded = false;
+        self.hunks_only = false;
+        self.content_hash = None;
+        self.previous = None;
+        self.line_remap = None;
+        self.changed_rows.clear();
+    }
+
+    /// Moves the loaded state into `previous` so the next load can compare against it.
+    pub fn stash_for_reload(&mut self) {
+        let (Some(content_hash), Some(aligned_rows)) =
+            (self.content_hash, self.aligned_rows.take())
+        else {
+            return;
+        };
+
+        self.previous = Some(Arc::new(PreviousLoad {
+            content_hash,
+            old_content: self.old_content.take(),
+            new_content: self.new_content.take(),
+            aligned_rows,
+            degraded: self.degraded,
+        }));
+        self.content_hash = None;
+        self.degraded = false;
+        self.line_remap = None;
+        self.changed_rows.clear();
+    }
+}
+
+fn loaded_bytes(
+    old_content: &Option<ContentData>,
+    new_content: &Option<ContentData>,
+    rows: Option<&[AlignedRow]>,
+) -> u64 {
+    let content_bytes = |content: &Option<ContentData>| match content {
+        Some(ContentData::Text(text)) => text.len(),
+        _ => 0,
+    };
+    let row_bytes: usize = rows
+        .into_iter()
+        .flatten()
+        .map(|row| size_of::<AlignedRow>() + row.left_text.len() + row.right_text.len())
+        .sum();
+
+    (content_bytes(old_content) + content_bytes(new_content) + row_bytes) as u64
+}
+
+/// A file's loaded state from before a refresh.
+#[derive(Debug)]
+pub struct PreviousLoad {
+    pub content_hash: u64,
+    pub old_content: Option<ContentData>,
+    pub new_content: Option<ContentData>,
+    pub aligned_rows: Vec<AlignedRow>,
+    pub degraded: bool,
+}
+
+/// Relates line numbers of an earlier version of a text to the current version.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineRemap {
+    pub blocks: Vec<RemapBlock>,
+}
+
+/// One diff op between the two versions; line numbers are 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemapBlock {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub equal: bool,
+}
+
+impl LineRemap {
+    /// Maps `old_line` to the same line in the new text, or to the start of the change
+    /// block that replaced it.
+    pub fn map(&self, old_line: usize) -> usize {
+        let block = self
+            .blocks
+            .iter()
+            .find(|block| old_line < block.old_start + block.old_len);
+
+        match block {
+            Some(block) if block.equal => block.new_start + (old_line - block.old_start),
+            Some(block) => block.new_start,
+            None => self.blocks.last().map_or(old_line, |block| {
+                block.new_start + block.new_len.saturating_sub(1)
+            }),
+        }
+    }
+
+    /// Line numbers in the new text that were inserted or rewritten.
+    pub fn changed_lines(&self) -> impl Iterator<Item = usize> + '_ {
+        self.blocks
+            .iter()
+            .filter(|block| !block.equal)
+            .flat_map(|block| block.new_start..block.new_start + block.new_len)
+    }
+}
+
+pub fn content_hash(old_content: &Option<ContentData>, new_content: &Option<ContentData>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for content in [old_content, new_content] {
+        match content {
+            Some(ContentData::Text(text)) => (0u8, text).hash(&mut hasher),
+            Some(ContentData::Binary) => 1u8.hash(&mut hasher),
+            Some(ContentData::TooLarge { bytes }) => (2u8, bytes).hash(&mut hasher),
+            None => 3u8.hash(&mut hasher),
+        }
+    }
+    hasher.finish()
 }
 
 #[derive(Debug, Clone)]
 pub enum ContentData {
     Text(String),
     Binary,
+    TooLarge { bytes: u64 },
+}
+
+/// Size thresholds above which a file is not loaded (or only loaded in degraded mode).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadLimits {
+    pub max_file_bytes: u64,
+    pub max_line_chars: usize,
+    /// Budget for loaded contents and rows kept across files before the least recently
+    /// viewed ones are dropped.
+    pub max_cached_bytes: u64,
+}
+
+impl Default for LoadLimits {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: 8 * 1024 * 1024,
+            max_line_chars: 2_000,
+            max_cached_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -63,6 +293,34 @@ pub struct AlignedRow {
     pub kind: RowKind,
 }
 
+impl AlignedRow {
+    /// Screen lines the row takes when both sides wrap at `width` characters.
+    pub fn wrapped_height(&self, width: usize) -> usize {
+        let lines = |text: &str| text.chars().count().div_ceil(width.max(1));
+        lines(&self.left_text).max(lines(&self.right_text)).max(1)
+    }
+
+    pub fn line_no(&self, side: Side) -> Option<usize> {
+        match side {
+            Side::Left => self.left_line_no,
+            Side::Right => self.right_line_no,
+        }
+    }
+}
+
+/// `HEAD` (left) or worktree (right) pane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// Index of the row showing `line` on `side`, or of the next numbered row after it.
+pub fn row_for_line(rows: &[AlignedRow], side: Side, line: usize) -> Option<usize> {
+    rows.iter()
+        .position(|row| row.line_no(side).is_some_and(|no| no >= line))
+}
+
 #[derive(Debug, Clone)]
 pub struct TreeNode {
     pub name: String,
@@ -88,4 +346,6 @@ pub struct TreeRow {
     pub label: String,
     pub is_dir: bool,
     pub file_index: Option<usize>,
+    /// Repository-relative path of the file or directory.
+    pub path: PathBuf,
 }
@@ -0,0 +1,733 @@
+use std::io::{self, BufRead, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::diff::align_full_file;
+use crate::git::GitBackend;
+use crate::model::{AlignedRow, ChangedFile, ContentData, FileStatus, LoadLimits, RowKind};
+use crate::worker::placeholder_row;
+
+/// A unified diff as written by `git diff`, `git show`, `git log -p` or `git format-patch`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Patch {
+    pub files: Vec<FilePatch>,
+    /// Abbreviated ids of the `commit <id>` or mbox `From <id>` headers, in order.
+    pub commits: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilePatch {
+    pub path: PathBuf,
+    /// The path before a `rename from` line moved it.
+    pub renamed_from: Option<PathBuf>,
+    /// Abbreviated id of the commit this change belongs to, if the input had commit headers.
+    pub commit: Option<String>,
+    /// Blob id of the pre-image from the `index` line, if the file existed before.
+    pub old_blob: Option<String>,
+    pub new_file: bool,
+    pub deleted: bool,
+    pub binary: bool,
+    pub hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub new_start: usize,
+    pub lines: Vec<HunkLine>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HunkLine {
+    Context(String),
+    Removed(String),
+    Added(String),
+}
+
+impl Hunk {
+    /// Text of the hunk's pre-image and post-image.
+    fn sides(&self) -> (String, String) {
+        let mut old = String::new();
+        let mut new = String::new();
+        for line in &self.lines {
+            match line {
+                HunkLine::Context(text) => {
+                    old.push_str(text);
+                    old.push('\n');
+                    new.push_str(text);
+                    new.push('\n');
+                }
+                HunkLine::Removed(text) => {
+                    old.push_str(text);
+                    old.push('\n');
+                }
+                HunkLine::Added(text) => {
+                    new.push_str(text);
+                    new.push('\n');
+                }
+            }
+        }
+        (old, new)
+    }
+}
+
+/// Splits `input` into per-file changes. Color escapes are removed first and anything that is
+/// not part of a diff (commit messages, mail headers, diffstats) is skipped. When several commits
+/// are present, each path is put under its commit's id so the same file in two commits stays
+/// apart.
+pub fn parse(input: &str) -> Patch {
+    let input = strip_escapes(input);
+    let mut patch = Patch::default();
+    let mut current: Option<FilePatch> = None;
+    let mut in_git_header = false;
+    // Lines still expected in the current hunk, per side.
+    let mut remaining = (0, 0);
+    let mut lines = input.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        if remaining != (0, 0) {
+            if let Some(hunk) = current.as_mut().and_then(|file| file.hunks.last_mut()) {
+                let (old, new) = &mut remaining;
+                let parsed = match line.chars().next() {
+                    // Some tools strip the trailing space of empty context lines.
+                    Some(' ') | None if *old > 0 && *new > 0 => {
+                        *old -= 1;
+                        *new -= 1;
+                        Some(HunkLine::Context(line.get(1..).unwrap_or("").to_string()))
+                    }
+                    Some('-') if *old > 0 => {
+                        *old -= 1;
+                        Some(HunkLine::Removed(line[1..].to_string()))
+                    }
+                    Some('+') if *new > 0 => {
+                        *new -= 1;
+                        Some(HunkLine::Added(line[1..].to_string()))
+                    }
+                    Some('\\') => None,
+                    _ => {
+                        remaining = (0, 0);
+                        None
+                    }
+                };
+                if let Some(parsed) = parsed {
+                    hunk.lines.push(parsed);
+                    continue;
+                }
+                if remaining != (0, 0) {
+                    continue;
+                }
+            }
+        }
+
+        if let Some(id) = line
+            .strip_prefix("commit ")
+            .or_else(|| line.strip_prefix("From "))
+            .and_then(commit_id)
+        {
+            patch.files.extend(current.take());
+            patch.commits.push(id);
+            in_git_header = false;
+        } else if let Some(rest) = line.strip_prefix("diff --git ") {
+            patch.files.extend(current.take());
+            current = Some(FilePatch {
+                path: git_header_path(rest),
+                commit: patch.commits.last().cloned(),
+                ..FilePatch::default()
+            });
+            in_git_header = true;
+        } else if line.starts_with("diff --cc ") || line.starts_with("diff --combined ") {
+            // Merge commits' combined diffs have no side-by-side form.
+            patch.files.extend(current.take());
+            in_git_header = false;
+        } else if let (Some(old), Some(new)) = (
+            line.strip_prefix("--- "),
+            lines.peek().and_then(|next| next.strip_prefix("+++ ")),
+        ) {
+            lines.next();
+            if !in_git_header || current.as_ref().is_some_and(|file| !file.hunks.is_empty()) {
+                patch.files.extend(current.take());
+                in_git_header = false;
+            }
+            let file = current.get_or_insert_with(|| FilePatch {
+                commit: patch.commits.last().cloned(),
+                ..FilePatch::default()
+            });
+            let old = header_path(old, in_git_header.then_some("a/"));
+            let new = header_path(new, in_git_header.then_some("b/"));
+            file.new_file |= old.is_none();
+            file.deleted |= new.is_none();
+            if let Some(path) = new.or(old) {
+                file.path = path;
+            }
+        } else if let Some(file) = current.as_mut() {
+            if let Some(hunk) = line.strip_prefix("@@ ").and_then(parse_hunk_header) {
+                remaining = (hunk.1, hunk.3);
+                file.hunks.push(Hunk {
+                    old_start: hunk.0,
+                    new_start: hunk.2,
+                    lines: Vec::new(),
+                });
+            } else if !in_git_header || !file.hunks.is_empty() {
+                continue;
+            } else if line.starts_with("new file mode ") {
+                file.new_file = true;
+            } else if line.starts_with("deleted file mode ") {
+                file.deleted = true;
+            } else if let Some(path) = line.strip_prefix("rename from ") {
+                file.renamed_from = Some(PathBuf::from(unquote(path)));
+            } else if line.starts_with("copy from ") {
+                file.new_file = true;
+            } else if let Some(path) = line
+                .strip_prefix("rename to ")
+                .or_else(|| line.strip_prefix("copy to "))
+            {
+                file.path = PathBuf::from(unquote(path));
+            } else if let Some(ids) = line.strip_prefix("index ") {
+                file.old_blob = ids
+                    .split("..")
+                    .next()
+                    .filter(|id| id.chars().any(|ch| ch != '0'))
+                    .map(str::to_string);
+            } else if line.starts_with("Binary files ") || line == "GIT binary patch" {
+                file.binary = true;
+            }
+        }
+    }
+    patch.files.extend(current);
+
+    if patch.commits.len() > 1 {
+        for file in &mut patch.files {
+            if let Some(commit) = &file.commit {
+                file.path = Path::new(commit).join(&file.path);
+                file.renamed_from = file
+                    .renamed_from
+                    .take()
+                    .map(|from| Path::new(commit).join(from));
+            }
+        }
+    }
+    patch
+}
+
+/// Lines at the top of piped input searched for the start of a diff.
+const SNIFF_LINES: u64 = 1000;
+/// Bytes read at most while searching, for input without line breaks.
+const SNIFF_BYTES: u64 = 1 << 20;
+
+/// Reads `input` into `head` up to the first line that starts a diff (a `diff --git` header,
+/// a `+++` file line or a hunk header, colored or not), or at most [`SNIFF_LINES`] lines.
+/// True if one was found; the rest of `input` is left unread either way.
+pub fn read_diff_head(input: &mut impl BufRead, head: &mut Vec<u8>) -> io::Result<bool> {
+    let mut limited = input.take(SNIFF_BYTES);
+    for _ in 0..SNIFF_LINES {
+        let start = head.len();
+        if limited.read_until(b'\n', head)? == 0 {
+            break;
+        }
+        let line = &head[start..];
+        let starts_diff = [&b"diff --git "[..], b"+++ ", b"@@ -"]
+            .iter()
+            .any(|marker| line.windows(marker.len()).any(|window| window == *marker));
+        if starts_diff {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Drops ANSI escape sequences, which git adds when `color.ui` colors pager output.
+fn strip_escapes(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\x1b' {
+            output.push(ch);
+            continue;
+        }
+        if chars.clone().next() == Some('[') {
+            chars.next();
+            // Parameters and intermediates, up to the final byte.
+            for ch in chars.by_ref() {
+                if ('\x40'..='\x7e').contains(&ch) {
+                    break;
+                }
+            }
+        }
+    }
+    output
+}
+
+fn commit_id(rest: &str) -> Option<String> {
+    let id = rest.split_whitespace().next()?;
+    (id.len() >= 7 && id.chars().all(|ch| ch.is_ascii_hexdigit())).then(|| id[..7].to_string())
+}
+
+/// The path in `a/<path> b/<path>`; only a guess when the two differ, which the `---`/`+++`
+/// or `rename to` lines that follow correct.
+fn git_header_path(rest: &str) -> PathBuf {
+    let rest = rest.trim();
+    if let Some(both) = rest.strip_prefix("a/") {
+        let len = both.len().saturating_sub(3) / 2;
+        if both.get(len..len + 3) == Some(" b/") && both[..len] == both[len + 3..] {
+            return PathBuf::from(&both[..len]);
+        }
+    }
+    let path = rest.split_once(" b/").map_or(rest, |(_, new)| new);
+    PathBuf::from(unquote(path))
+}
+
+/// The path after `---` or `+++`, without git's `a/`/`b/` prefix or a trailing timestamp;
+/// `None` for `/dev/null`.
+fn header_path(text: &str, prefix: Option<&str>) -> Option<PathBuf> {
+    let text = unquote(text.split('\t').next().unwrap_or(text).trim_end());
+    if text == "/dev/null" {
+        return None;
+    }
+    let path = prefix
+        .and_then(|prefix| text.strip_prefix(prefix))
+        .unwrap_or(text);
+    Some(PathBuf::from(path))
+}
+
+fn unquote(text: &str) -> &str {
+    text.strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .unwrap_or(text)
+}
+
+/// `-<start>[,<count>] +<start>[,<count>] @@`, as (old start, old count, new start, new count).
+fn parse_hunk_header(rest: &str) -> Option<(usize, usize, usize, usize)> {
+    let mut ranges = rest.split_whitespace();
+    let old = ranges.next()?.strip_prefix('-')?;
+    let new = ranges.next()?.strip_prefix('+')?;
+    let range = |text: &str| -> Option<(usize, usize)> {
+        match text.split_once(',') {
+            Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+            None => Some((text.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_count) = range(old)?;
+    let (new_start, new_count) = range(new)?;
+    Some((old_start, old_count, new_start, new_count))
+}
+
+/// Side-by-side rows for the hunks of `file`, with real line numbers and a note for each
+/// stretch of lines the patch leaves out.
+pub fn hunk_rows(file: &FilePatch) -> Vec<AlignedRow> {
+    if file.binary {
+        return vec![placeholder_row("[binary file]")];
+    }
+    if file.hunks.is_empty() {
+        return vec![placeholder_row("[no content changes]")];
+    }
+
+    let mut rows = Vec::new();
+    let mut next_old = 1;
+    for hunk in &file.hunks {
+        let skipped = hunk.old_start.saturating_sub(next_old);
+        if skipped > 0 {
+            rows.push(gap_row(skipped));
+        }
+        let (old, new) = hunk.sides();
+        let old_offset = hunk.old_start.saturating_sub(1);
+        let new_offset = hunk.new_start.saturating_sub(1);
+        rows.extend(align_full_file(&old, &new).into_iter().map(|mut row| {
+            row.left_line_no = row.left_line_no.map(|no| no + old_offset);
+            row.right_line_no = row.right_line_no.map(|no| no + new_offset);
+            row
+        }));
+        next_old = hunk.old_start.max(1) + old.lines().count();
+    }
+    rows
+}
+
+/// The post-image of `pre` with `hunks` applied, or `None` if a context or removed line does
+/// not match `pre`, which means the patch was made against a different version.
+pub fn apply(pre: &str, hunks: &[Hunk]) -> Option<String> {
+    let pre_lines: Vec<&str> = pre.lines().collect();
+    let mut post = String::with_capacity(pre.len());
+    let mut push = |text: &str| {
+        post.push_str(text);
+        post.push('\n');
+    };
+    let mut next = 0;
+    for hunk in hunks {
+        let pure_insert = hunk
+            .lines
+            .iter()
+            .all(|line| matches!(line, HunkLine::Added(_)));
+        // A hunk without pre-image lines inserts after `old_start` instead of at it.
+        let start = if pure_insert {
+            hunk.old_start
+        } else {
+            hunk.old_start.checked_sub(1)?
+        };
+        if start < next || start > pre_lines.len() {
+            return None;
+        }
+        pre_lines[next..start].iter().for_each(|line| push(line));
+        next = start;
+        for line in &hunk.lines {
+            match line {
+                HunkLine::Context(text) | HunkLine::Removed(text) => {
+                    if pre_lines.get(next) != Some(&text.as_str()) {
+                        return None;
+                    }
+                    next += 1;
+                    if matches!(line, HunkLine::Context(_)) {
+                        push(text);
+                    }
+                }
+                HunkLine::Added(text) => push(text),
+            }
+        }
+    }
+    pre_lines[next..].iter().for_each(|line| push(line));
+    Some(post)
+}
+
+fn gap_row(lines: usize) -> AlignedRow {
+    let text = if lines == 1 {
+        "⋯ 1 unchanged line".to_string()
+    } else {
+        format!("⋯ {} unchanged lines", lines)
+    };
+    AlignedRow {
+        left_line_no: None,
+        right_line_no: None,
+        left_text: text.clone(),
+        right_text: text,
+        kind: RowKind::Equal,
+    }
+}
+
+/// Shows a parsed [`Patch`]: the files it touches, each as its hunks side by side, or as whole
+/// files when the repository has the pre-image and the patch applies to it.
+pub struct PatchBackend {
+    patch: Patch,
+    /// Repository to read pre-image blobs from.
+    repo: Option<Arc<dyn GitBackend>>,
+    /// Where `:open` looks for the patched files.
+    root: PathBuf,
+}
+
+impl PatchBackend {
+    pub fn new(patch: Patch, repo: Option<Arc<dyn GitBackend>>) -> Result<Self> {
+        let root = match &repo {
+            Some(repo) => repo.repo_root().to_path_buf(),
+            None => std::env::current_dir().context("failed to read the current directory")?,
+        };
+        Ok(Self { patch, repo, root })
+    }
+
+    /// Both sides in full: the pre-image blob and the patch applied to it.
+    fn full_texts(&self, file: &FilePatch, limits: &LoadLimits) -> Option<(String, String)> {
+        if file.binary {
+            return None;
+        }
+        let pre = match &file.old_blob {
+            Some(id) => {
+                let bytes = self.repo.as_ref()?.read_blob(id).ok()??;
+                if bytes.len() as u64 > limits.max_file_bytes {
+                    return None;
+                }
+                String::from_utf8(bytes).ok()?
+            }
+            None if file.new_file => String::new(),
+            None => return None,
+        };
+        let post = apply(&pre, &file.hunks)?;
+        Some((pre, post))
+    }
+
+    fn file(&self, path: &Path) -> Result<&FilePatch> {
+        self.patch
+            .files
+            .iter()
+            .find(|file| file.path == path)
+            .ok_or_else(|| anyhow!("{} is not in the patch", path.display()))
+    }
+}
+
+impl GitBackend for PatchBackend {
+    fn repo_root(&self) -> &Path {
+        &self.root
+    }
+
+    fn git_dir(&self) -> Result<PathBuf> {
+        bail!("showing a patch, not a repository")
+    }
+
+    fn collect_changed_files(&self) -> Result<Vec<ChangedFile>> {
+        let mut files: Vec<ChangedFile> = self
+            .patch
+            .files
+            .iter()
+            .map(|file| {
+                let status = FileStatus {
+                    unstaged: !file.new_file,
+                    untracked: file.new_file,
+                    deleted: file.deleted,
+                    renamed: file.renamed_from.is_some(),
+                    ..FileStatus::default()
+                };
+                let mut changed = ChangedFile::new(file.path.clone(), status);
+                changed.renamed_from = file.renamed_from.clone();
+                changed
+            })
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files.dedup_by(|a, b| a.path == b.path);
+        Ok(files)
+    }
+
+    fn head_blob_size(&self, _path: &Path) -> Result<u64> {
+        Ok(0)
+    }
+
+    fn read_head_blob(&self, _path: &Path) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    fn side_names(&self) -> [String; 2] {
+        match self.patch.commits.as_slice() {
+            [commit] => [format!("{}^", commit), commit.clone()],
+            _ => ["old".to_string(), "new".to_string()],
+        }
+    }
+
+    fn load_contents(&self, file: &mut ChangedFile, limits: &LoadLimits) -> Result<()> {
+        let patch = self.file(&file.path)?;
+        file.sides_exist = [!patch.new_file, !patch.deleted];
+        if let Some((old, new)) = self.full_texts(patch, limits) {
+            // Aligned like any other file, with the usual limits.
+            file.old_content = Some(ContentData::Text(old));
+            file.new_content = Some(ContentData::Text(new));
+            return Ok(());
+        }
+        let (old, new): (String, String) = patch.hunks.iter().map(Hunk::sides).unzip();
+        file.old_content = Some(ContentData::Text(old));
+        file.new_content = Some(ContentData::Text(new));
+        file.aligned_rows = Some(hunk_rows(patch));
+        file.hunks_only = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{apply, hunk_rows, parse, read_diff_head, PatchBackend};
+    use crate::git::GitBackend;
+    use crate::model::RowKind;
+
+    const GIT_DIFF: &str = "\
+diff --git a/src/main.rs b/src/main.rs
+index 1111111..2222222 100644
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -10,3 +10,3 @@ fn main() {
+     let a = 1;
+-    let b = 2;
++    let b = 3;
+     let c = 4;
+@@ -40,2 +40,3 @@ fn other() {
+ }
++// done
+
+diff --git a/docs/new file.md b/docs/new file.md
+new file mode 100644
+index 0000000..3333333
+--- /dev/null
++++ b/docs/new file.md
+@@ -0,0 +1,2 @@
++# Title
++-- not a header
+diff --git a/old.txt b/renamed.txt
+similarity index 90%
+rename from old.txt
+rename to renamed.txt
+diff --git a/logo.png b/logo.png
+deleted file mode 100644
+index 4444444..0000000
+Binary files a/logo.png and /dev/null differ
+";
+
+    #[test]
+    fn parses_files_statuses_and_hunks() {
+        let patch = parse(GIT_DIFF);
+        let paths: Vec<&str> = patch
+            .files
+            .iter()
+            .map(|file| file.path.to_str().unwrap())
+            .collect();
+        assert_eq!(
+            paths,
+            vec!["src/main.rs", "docs/new file.md", "renamed.txt", "logo.png"]
+        );
+
+        let main = &patch.files[0];
+        assert_eq!(main.hunks.len(), 2);
+        assert_eq!(main.hunks[1].old_start, 40);
+        assert_eq!(main.hunks[1].lines.len(), 3);
+        assert!(patch.files[1].new_file);
+        assert_eq!(patch.files[1].hunks[0].lines.len(), 2);
+        assert!(patch.files[2].hunks.is_empty());
+        assert!(patch.files[3].deleted && patch.files[3].binary);
+        assert!(parse("On branch main\nnothing to commit\n")
+            .files
+            .is_empty());
+    }
+
+    #[test]
+    fn sniffing_stops_at_the_first_diff_line_or_the_line_cap() {
+        let log = "commit 1234567\nAuthor: A <a@example.com>\n\n    Subject\n\n";
+        let input = format!("{}\x1b[1mdiff --git a/x b/x\x1b[m\n--- a/x\n", log);
+        let mut reader = input.as_bytes();
+        let mut head = Vec::new();
+        assert!(read_diff_head(&mut reader, &mut head).unwrap());
+        assert!(head.ends_with(b"diff --git a/x b/x\x1b[m\n"));
+        assert_eq!(reader, b"--- a/x\n");
+
+        let log = "commit 1234567\n".repeat(1500);
+        let mut reader = log.as_bytes();
+        let mut head = Vec::new();
+        assert!(!read_diff_head(&mut reader, &mut head).unwrap());
+        assert_eq!(head.len(), "commit 1234567\n".len() * 1000);
+        assert_eq!(reader.len(), "commit 1234567\n".len() * 500);
+    }
+
+    #[test]
+    fn hunk_rows_keep_line_numbers_and_mark_gaps() {
+        let patch = parse(GIT_DIFF);
+        let rows = hunk_rows(&patch.files[0]);
+
+        assert_eq!(rows[0].left_text, "⋯ 9 unchanged lines");
+        assert_eq!(rows[1].left_line_no, Some(10));
+        assert_eq!(rows[2].kind, RowKind::Changed);
+        assert_eq!(rows[2].right_text, "    let b = 3;");
+        assert_eq!(rows[4].left_text, "⋯ 27 unchanged lines");
+        assert_eq!(rows[5].left_line_no, Some(40));
+        assert_eq!(rows[6].kind, RowKind::Insert);
+        assert_eq!(rows[6].right_line_no, Some(41));
+        assert_eq!(rows.len(), 8);
+    }
+
+    #[test]
+    fn colored_log_output_groups_files_by_commit() {
+        let log = "\
+\x1b[33mcommit 0123456789abcdef0123456789abcdef01234567\x1b[m
+Author: A <a@example.com>
+
+    second
+
+\x1b[1mdiff --git a/a.txt b/a.txt\x1b[m
+\x1b[1m--- a/a.txt\x1b[m
+\x1b[1m+++ b/a.txt\x1b[m
+\x1b[36m@@ -1 +1 @@\x1b[m
+\x1b[31m-two\x1b[m
+\x1b[32m+three\x1b[m
+\x1b[33mcommit fedcba9876543210fedcba9876543210fedcba98\x1b[m
+
+    first
+
+diff --git a/a.txt b/a.txt
+--- a/a.txt
++++ b/a.txt
+@@ -1 +1 @@
+-one
++two
+";
+        let patch = parse(log);
+
+        assert_eq!(patch.commits, vec!["0123456", "fedcba9"]);
+        assert_eq!(patch.files.len(), 2);
+        assert_eq!(patch.files[0].path, PathBuf::from("0123456/a.txt"));
+        assert_eq!(patch.files[1].path, PathBuf::from("fedcba9/a.txt"));
+        assert_eq!(hunk_rows(&patch.files[1])[0].right_text, "two");
+    }
+
+    #[test]
+    fn format_patch_mbox_keeps_renames_and_statuses() {
+        let mbox = "\
+From 0123456789abcdef0123456789abcdef01234567 Mon Sep 17 00:00:00 2001
+From: A <a@example.com>
+Subject: [PATCH] Move notes
+
+---
+ notes.txt => docs/notes.txt | 2 +-
+ 1 file changed, 1 insertion(+), 1 deletion(-)
+
+diff --git a/notes.txt b/docs/notes.txt
+similarity index 80%
+rename from notes.txt
+rename to docs/notes.txt
+index 5555555..6666666 100644
+--- a/notes.txt
++++ b/docs/notes.txt
+@@ -1,2 +1,2 @@
+ keep
+-old
++new
+diff --git a/gone.txt b/gone.txt
+deleted file mode 100644
+index 7777777..0000000
+--- a/gone.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+-- 
+2.39.5
+";
+        let patch = parse(mbox);
+        assert_eq!(patch.commits, vec!["0123456"]);
+        assert_eq!(
+            patch.files[0].renamed_from,
+            Some(PathBuf::from("notes.txt"))
+        );
+        assert_eq!(patch.files[0].old_blob.as_deref(), Some("5555555"));
+        assert_eq!(patch.files[0].hunks[0].lines.len(), 3);
+
+        let backend = PatchBackend::new(patch, None).unwrap();
+        let files = backend.collect_changed_files().unwrap();
+        let shown: Vec<(String, &str)> = files
+            .iter()
+            .map(|file| (file.display_name(), file.status.indicator()))
+            .collect();
+        assert_eq!(
+            shown,
+            vec![
+                ("notes.txt → docs/notes.txt".to_string(), "[R]"),
+                ("gone.txt".to_string(), "[D]"),
+            ]
+        );
+        assert_eq!(backend.side_names(), ["0123456^", "0123456"]);
+    }
+
+    #[test]
+    fn apply_rebuilds_the_post_image_only_when_the_context_matches() {
+        let patch = parse(
+            "\
+--- a/list.txt
++++ b/list.txt
+@@ -2,2 +2,2 @@
+ b
+-c
++C
+@@ -4,0 +5,1 @@
++d2
+",
+        );
+        let hunks = &patch.files[0].hunks;
+
+        assert_eq!(
+            apply("a\nb\nc\nd\ne\n", hunks).as_deref(),
+            Some("a\nb\nC\nd\nd2\ne\n")
+        );
+        assert_eq!(apply("a\nb\nx\nd\ne\n", hunks), None);
+        assert_eq!(apply("a\n", hunks), None);
+    }
+}
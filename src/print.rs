@@ -0,0 +1,396 @@
+use std::env;
+use std::io::Write;
+use std::ops::Range;
+use std::sync::atomic::AtomicBool;
+
+use anyhow::{bail, Result};
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+
+use crate::diff::DiffOptions;
+use crate::git::GitBackend;
+use crate::highlight::{self, HighlightedRow};
+use crate::model::{AlignedRow, ChangedFile, LoadLimits, RowKind};
+use crate::theme::{ColorDepth, Theme};
+use crate::ui;
+use crate::worker;
+
+/// Narrowest output that still fits a line number and a few characters per side.
+pub const MIN_WIDTH: u16 = 20;
+/// Width when stdout is not a terminal and `$COLUMNS` is not set.
+const DEFAULT_WIDTH: u16 = 160;
+
+/// How `--print` lays out files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrintOptions {
+    /// Total line width: both sides and the separator between them.
+    pub width: u16,
+    /// `Mono` writes plain text without escape codes.
+    pub depth: ColorDepth,
+    /// Unchanged rows kept around each change; `None` prints whole files.
+    pub context: Option<usize>,
+}
+
+/// The terminal's width, else `$COLUMNS`, else 160 columns.
+pub fn default_width(is_terminal: bool) -> u16 {
+    let terminal = is_terminal
+        .then(|| crossterm::terminal::size().ok())
+        .flatten()
+        .map(|(width, _)| width);
+    terminal
+        .or_else(|| env::var("COLUMNS").ok()?.trim().parse().ok())
+        .unwrap_or(DEFAULT_WIDTH)
+        .max(MIN_WIDTH)
+}
+
+/// Loads and writes each file in turn. A file that fails to load gets an error line in place of
+/// its rows and the others are still printed; the failures are then reported as one error.
+pub fn print_files(
+    git: &dyn GitBackend,
+    files: Vec<ChangedFile>,
+    limits: &LoadLimits,
+    theme: &Theme,
+    options: &PrintOptions,
+    out: &mut impl Write,
+) -> Result<()> {
+    let cancel = AtomicBool::new(false);
+    let mut failed = 0;
+
+    for (idx, mut file) in files.into_iter().enumerate() {
+        if idx > 0 {
+            writeln!(out)?;
+        }
+        match worker::prepare_file(
+            git,
+            &mut file,
+            limits,
+            DiffOptions::default(),
+            None,
+            &cancel,
+        ) {
+            Ok(()) => write_file(&file, theme, options, out)?,
+            Err(err) => {
+                failed += 1;
+                write_header(&file, theme, options, out)?;
+                let style = Style::default().fg(theme.colors.error);
+                write_text_line(&format!("error: {:#}", err), style, options, out)?;
+            }
+        }
+    }
+
+    out.flush()?;
+    if failed > 0 {
+        bail!("{} file(s) could not be loaded", failed);
+    }
+    Ok(())
+}
+
+/// A header line, then one line per row with `HEAD` on the left and the worktree on the right.
+fn write_file(
+    file: &ChangedFile,
+    theme: &Theme,
+    options: &PrintOptions,
+    out: &mut impl Write,
+) -> Result<()> {
+    write_header(file, theme, options, out)?;
+    let rows = file.aligned_rows.as_deref().unwrap_or_default();
+
+    let highlighted: Vec<HighlightedRow> = if file.degraded || options.depth == ColorDepth::Mono {
+        rows.iter().map(HighlightedRow::plain).collect()
+    } else {
+        highlight::highlight_rows(&file.path, rows, &theme.syntax)
+    };
+    let side = (options.width.max(MIN_WIDTH) - 1) / 2;
+    let unchanged = vec![false; rows.len()];
+    let (left, right) = ui::build_visible_rows(
+        &theme.colors,
+        options.depth,
+        rows,
+        &highlighted,
+        &unchanged,
+        Some(0),
+        usize::from(side - 5),
+    );
+
+    let fold_style = Style::default().fg(theme.colors.muted);
+    let mut next = 0;
+    for range in shown_ranges(rows, options.context) {
+        write_fold(range.start - next, fold_style, options, out)?;
+        for idx in range.clone() {
+            let buffer = row_buffer(&left[idx], &right[idx], rows[idx].kind, side, theme);
+            write_buffer(&buffer, options, out)?;
+        }
+        next = range.end;
+    }
+    write_fold(rows.len() - next, fold_style, options, out)?;
+    Ok(())
+}
+
+fn write_header(
+    file: &ChangedFile,
+    theme: &Theme,
+    options: &PrintOptions,
+    out: &mut impl Write,
+) -> Result<()> {
+    let mut title = format!("{} {}", file.status.indicator(), file.display_name());
+    if file.degraded {
+        title.push_str(" [degraded: no highlighting, chunked diff]");
+    }
+    let style = Style::default()
+        .fg(theme.colors.heading)
+        .add_modifier(Modifier::BOLD);
+    write_text_line(&title, style, options, out)
+}
+
+fn write_fold(
+    count: usize,
+    style: Style,
+    options: &PrintOptions,
+    out: &mut impl Write,
+) -> Result<()> {
+    match count {
+        0 => Ok(()),
+        1 => write_text_line("     ⋯ 1 unchanged line", style, options, out),
+        _ => {
+            let text = format!("     ⋯ {} unchanged lines", count);
+            write_text_line(&text, style, options, out)
+        }
+    }
+}
+
+fn write_text_line(
+    text: &str,
+    style: Style,
+    options: &PrintOptions,
+    out: &mut impl Write,
+) -> Result<()> {
+    let mut buffer = Buffer::empty(Rect::new(0, 0, options.width.max(MIN_WIDTH), 1));
+    buffer.set_stringn(0, 0, text, usize::from(buffer.area.width), style);
+    write_buffer(&buffer, options, out)
+}
+
+/// Rows that `context` leaves unfolded, as sorted, non-touching ranges.
+fn shown_ranges(rows: &[AlignedRow], context: Option<usize>) -> Vec<Range<usize>> {
+    let Some(context) = context else {
+        return std::iter::once(0..rows.len()).collect();
+    };
+
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (idx, row) in rows.iter().enumerate() {
+        if row.kind == RowKind::Equal {
+            continue;
+        }
+        let range = idx.saturating_sub(context)..(idx + context + 1).min(rows.len());
+        match ranges.last_mut() {
+            Some(last) if last.end >= range.start => last.end = range.end,
+            _ => ranges.push(range),
+        }
+    }
+    ranges
+}
+
+/// Draws one row the way the diff panes do, with `-`, `+` or `~` in the marker column so
+/// changes stay visible without colors.
+fn row_buffer(
+    left: &Line<'_>,
+    right: &Line<'_>,
+    kind: RowKind,
+    side: u16,
+    theme: &Theme,
+) -> Buffer {
+    let mut buffer = Buffer::empty(Rect::new(0, 0, side * 2 + 1, 1));
+    buffer.set_line(0, 0, left, side);
+    buffer.set_string(side, 0, "│", Style::default().fg(theme.colors.muted));
+    buffer.set_line(side + 1, 0, right, side);
+
+    let (left_sign, right_sign) = match kind {
+        RowKind::Equal => (" ", " "),
+        RowKind::Changed => ("~", "~"),
+        RowKind::Insert => (" ", "+"),
+        RowKind::Delete => ("-", " "),
+    };
+    buffer[(4, 0)].set_symbol(left_sign);
+    buffer[(side + 5, 0)].set_symbol(right_sign);
+    buffer
+}
+
+/// Writes the first line of `buffer` with SGR escapes, colors reduced to `options.depth`, or as
+/// plain text without trailing blanks in mono.
+fn write_buffer(buffer: &Buffer, options: &PrintOptions, out: &mut impl Write) -> Result<()> {
+    let plain = options.depth == ColorDepth::Mono;
+    let mut line = String::new();
+    let mut current = None;
+    let mut hidden = 0;
+
+    for cell in buffer.content().iter().take(usize::from(buffer.area.width)) {
+        // Cells covered by a wide character before them are not drawn.
+        if hidden > 0 {
+            hidden -= 1;
+            continue;
+        }
+        hidden = Span::raw(cell.symbol()).width().saturating_sub(1);
+
+        let (fg, bg) = options.depth.reduce_pair(cell.fg, cell.bg);
+        let style = (fg, bg, cell.modifier);
+        if !plain && current != Some(style) {
+            line.push_str(&sgr(style.0, style.1, style.2));
+            current = Some(style);
+        }
+        line.push_str(cell.symbol());
+    }
+
+    if plain {
+        line.truncate(line.trim_end().len());
+    } else {
+        line.push_str("\x1b[0m");
+    }
+    writeln!(out, "{}", line)?;
+    Ok(())
+}
+
+/// A reset followed by whatever `fg`, `bg` and `modifier` add to the default style.
+fn sgr(fg: Color, bg: Color, modifier: Modifier) -> String {
+    let mut codes = vec!["0".to_string()];
+    for (flag, code) in [
+        (Modifier::BOLD, "1"),
+        (Modifier::DIM, "2"),
+        (Modifier::ITALIC, "3"),
+        (Modifier::UNDERLINED, "4"),
+        (Modifier::REVERSED, "7"),
+        (Modifier::CROSSED_OUT, "9"),
+    ] {
+        if modifier.contains(flag) {
+            codes.push(code.to_string());
+        }
+    }
+    codes.extend(color_code(fg, 30));
+    codes.extend(color_code(bg, 40));
+    format!("\x1b[{}m", codes.join(";"))
+}
+
+/// `base` is 30 for foreground and 40 for background colors.
+fn color_code(color: Color, base: u8) -> Option<String> {
+    let named = |offset: u8| Some((base + offset).to_string());
+    let bright = |offset: u8| Some((base + 60 + offset).to_string());
+    match color {
+        Color::Reset => None,
+        Color::Black => named(0),
+        Color::Red => named(1),
+        Color::Green => named(2),
+        Color::Yellow => named(3),
+        Color::Blue => named(4),
+        Color::Magenta => named(5),
+        Color::Cyan => named(6),
+        Color::Gray => named(7),
+        Color::DarkGray => bright(0),
+        Color::LightRed => bright(1),
+        Color::LightGreen => bright(2),
+        Color::LightYellow => bright(3),
+        Color::LightBlue => bright(4),
+        Color::LightMagenta => bright(5),
+        Color::LightCyan => bright(6),
+        Color::White => bright(7),
+        Color::Indexed(index) => Some(format!("{};5;{}", base + 8, index)),
+        Color::Rgb(r, g, b) => Some(format!("{};2;{};{};{}", base + 8, r, g, b)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use ratatui::style::{Color, Modifier};
+
+    use super::{sgr, shown_ranges, write_file, PrintOptions};
+    use crate::diff::align_full_file;
+    use crate::model::{ChangedFile, FileStatus};
+    use crate::theme::{ColorDepth, Theme};
+
+    fn changed_file(old: &str, new: &str) -> ChangedFile {
+        let mut file = ChangedFile::new(
+            PathBuf::from("notes.txt"),
+            FileStatus {
+                unstaged: true,
+                ..FileStatus::default()
+            },
+        );
+        file.aligned_rows = Some(align_full_file(old, new));
+        file
+    }
+
+    fn render(file: &ChangedFile, options: &PrintOptions) -> String {
+        let mut out = Vec::new();
+        write_file(file, &Theme::default(), options, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn plain_output_marks_changes_side_by_side() {
+        let file = changed_file("one\ntwo\nthree\n", "one\n2\nthree\nfour\n");
+        let options = PrintOptions {
+            width: 31,
+            depth: ColorDepth::Mono,
+            context: None,
+        };
+
+        assert_eq!(
+            render(&file, &options),
+            "[U] notes.txt\n\
+             \x20  1 one       │   1 one\n\
+             \x20  2~two       │   2~2\n\
+             \x20  3 three     │   3 three\n\
+             \x20              │   4+four\n"
+        );
+    }
+
+    #[test]
+    fn context_folds_unchanged_rows() {
+        let old: String = (1..=20).map(|n| format!("line {}\n", n)).collect();
+        let new = old.replace("line 10\n", "line ten\n");
+        let file = changed_file(&old, &new);
+        let options = PrintOptions {
+            width: 41,
+            depth: ColorDepth::Mono,
+            context: Some(1),
+        };
+        let output = render(&file, &options);
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines.len(), 6, "{}", output);
+        assert_eq!(lines[1], "     ⋯ 8 unchanged lines");
+        assert!(lines[3].contains("~line 10"), "{}", output);
+        assert_eq!(lines[5], "     ⋯ 9 unchanged lines");
+
+        let rows = file.aligned_rows.as_deref().unwrap();
+        assert_eq!(shown_ranges(rows, Some(1)), vec![8..11]);
+        assert_eq!(shown_ranges(rows, Some(9)), vec![0..19]);
+        assert_eq!(shown_ranges(rows, None), vec![0..20]);
+    }
+
+    #[test]
+    fn colored_output_uses_the_row_backgrounds() {
+        let file = changed_file("a\n", "b\n");
+        let options = PrintOptions {
+            width: 31,
+            depth: ColorDepth::TrueColor,
+            context: None,
+        };
+        let output = render(&file, &options);
+        let Color::Rgb(r, g, b) = Theme::default().colors.changed_bg else {
+            panic!("the dark theme uses RGB backgrounds");
+        };
+
+        assert!(
+            output.contains(&format!("48;2;{};{};{}", r, g, b)),
+            "{:?}",
+            output
+        );
+        assert!(output.ends_with("\x1b[0m\n"), "{:?}", output);
+        assert_eq!(
+            sgr(Color::Indexed(196), Color::DarkGray, Modifier::BOLD),
+            "\x1b[0;1;38;5;196;100m"
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use std::io;
+use std::panic;
+use std::thread;
+
+use crossterm::cursor::Show;
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+
+/// Raw mode, the alternate screen and mouse capture.
+pub fn enter() -> io::Result<()> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)
+}
+
+/// Undoes [`enter`]; safe to call more than once.
+pub fn leave() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(
+        io::stdout(),
+        DisableMouseCapture,
+        LeaveAlternateScreen,
+        Show
+    )
+}
+
+/// Restores the terminal before the panic message is printed, so it lands on the normal
+/// screen of a usable shell. A panic on a background thread ends fdf as well, since the view
+/// cannot go on without the terminal.
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = leave();
+        default_hook(info);
+        if thread::current().name() != Some("main") {
+            std::process::exit(101);
+        }
+    }));
+}
+
+#[cfg(unix)]
+pub use self::unix::{suspend, SignalRequest, SignalWatch};
+
+#[cfg(unix)]
+mod unix {
+    use std::io;
+
+    use signal_hook::consts::{SIGCONT, SIGHUP, SIGINT, SIGTERM, SIGTSTP};
+    use signal_hook::iterator::Signals;
+    use signal_hook::low_level::emulate_default_handler;
+
+    use super::{enter, leave};
+
+    /// What a signal asks the event loop to do.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SignalRequest {
+        /// `SIGTERM`, `SIGHUP` or `SIGINT`: quit as if `q` was pressed.
+        Quit,
+        /// `SIGTSTP` from outside; Ctrl-Z itself arrives as a key in raw mode.
+        Suspend,
+        /// `SIGCONT` after a stop fdf did not see coming, such as `SIGSTOP`.
+        Redraw,
+    }
+
+    /// Signals caught for the event loop, which checks them between events.
+    pub struct SignalWatch(Signals);
+
+    impl SignalWatch {
+        pub fn spawn() -> io::Result<Self> {
+            Signals::new([SIGTERM, SIGHUP, SIGINT, SIGTSTP, SIGCONT]).map(Self)
+        }
+
+        pub fn pending(&mut self) -> Vec<SignalRequest> {
+            self.0
+                .pending()
+                .filter_map(|signal| match signal {
+                    SIGTERM | SIGHUP | SIGINT => Some(SignalRequest::Quit),
+                    SIGTSTP => Some(SignalRequest::Suspend),
+                    SIGCONT => Some(SignalRequest::Redraw),
+                    _ => None,
+                })
+                .collect()
+        }
+    }
+
+    /// Gives the terminal back to the shell and stops the process until `fg` continues it.
+    pub fn suspend() -> io::Result<()> {
+        leave()?;
+        emulate_default_handler(SIGTSTP)?;
+        enter()
+    }
+}
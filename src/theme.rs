@@ -0,0 +1,494 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{anyhow, bail, Context, Result};
+use ratatui::style::Color;
+use syntect::highlighting::{Theme as SyntaxTheme, ThemeSet};
+
+/// Built-in themes, in the order `:theme` completion offers them.
+pub const THEMES: &[&str] = &["dark", "light", "high-contrast", "colorblind"];
+
+/// Interface colors; token colors come from the syntax theme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub insert_bg: Color,
+    pub delete_bg: Color,
+    pub changed_bg: Color,
+    /// Marks rows changed since the last refresh, pending keys and help keys.
+    pub emphasis: Color,
+    /// Line numbers.
+    pub gutter: Color,
+    pub tree_dir: Color,
+    /// Selected tree row and completion candidate.
+    pub selection_bg: Color,
+    pub scrollbar_track: Color,
+    pub scrollbar_change: Color,
+    pub scrollbar_thumb: Color,
+    /// Help section headings.
+    pub heading: Color,
+    /// Other completion candidates.
+    pub muted: Color,
+    pub error: Color,
+    /// Insert, delete and changed row backgrounds on 16-color terminals, where the tints above
+    /// reduce to one color; used unless a configured background is already a named color.
+    pub row_bgs_16: [Color; 3],
+}
+
+/// Names accepted in `[theme.colors]`.
+pub const COLOR_SLOTS: &[&str] = &[
+    "insert_bg",
+    "delete_bg",
+    "changed_bg",
+    "emphasis",
+    "gutter",
+    "tree_dir",
+    "selection_bg",
+    "scrollbar_track",
+    "scrollbar_change",
+    "scrollbar_thumb",
+    "heading",
+    "muted",
+    "error",
+];
+
+impl Palette {
+    fn dark() -> Self {
+        Self {
+            insert_bg: Color::Rgb(18, 60, 36),
+            delete_bg: Color::Rgb(72, 24, 24),
+            changed_bg: Color::Rgb(63, 54, 18),
+            emphasis: Color::Yellow,
+            gutter: Color::Reset,
+            tree_dir: Color::Blue,
+            selection_bg: Color::DarkGray,
+            scrollbar_track: Color::DarkGray,
+            scrollbar_change: Color::LightGreen,
+            scrollbar_thumb: Color::Green,
+            heading: Color::Cyan,
+            muted: Color::DarkGray,
+            error: Color::Red,
+            row_bgs_16: [Color::Green, Color::Red, Color::Yellow],
+        }
+    }
+
+    fn light() -> Self {
+        Self {
+            insert_bg: Color::Rgb(218, 251, 225),
+            delete_bg: Color::Rgb(255, 224, 224),
+            changed_bg: Color::Rgb(255, 244, 196),
+            emphasis: Color::Rgb(176, 96, 0),
+            gutter: Color::Rgb(110, 110, 110),
+            tree_dir: Color::Rgb(0, 80, 170),
+            selection_bg: Color::Rgb(200, 210, 225),
+            scrollbar_track: Color::Rgb(190, 190, 190),
+            scrollbar_change: Color::Rgb(40, 150, 70),
+            scrollbar_thumb: Color::Rgb(0, 100, 40),
+            heading: Color::Rgb(0, 110, 140),
+            muted: Color::Rgb(120, 120, 120),
+            error: Color::Rgb(190, 0, 0),
+            row_bgs_16: [Color::Green, Color::Red, Color::Yellow],
+        }
+    }
+
+    fn high_contrast() -> Self {
+        Self {
+            insert_bg: Color::Rgb(0, 100, 0),
+            delete_bg: Color::Rgb(140, 0, 0),
+            changed_bg: Color::Rgb(100, 90, 0),
+            emphasis: Color::LightYellow,
+            gutter: Color::White,
+            tree_dir: Color::LightCyan,
+            selection_bg: Color::Blue,
+            scrollbar_track: Color::Gray,
+            scrollbar_change: Color::LightYellow,
+            scrollbar_thumb: Color::White,
+            heading: Color::LightCyan,
+            muted: Color::Gray,
+            error: Color::LightRed,
+            row_bgs_16: [Color::Green, Color::Red, Color::Yellow],
+        }
+    }
+
+    /// Blue for insertions and orange for deletions, distinguishable with red-green color
+    /// vision deficiencies.
+    fn colorblind() -> Self {
+        Self {
+            insert_bg: Color::Rgb(16, 52, 96),
+            delete_bg: Color::Rgb(100, 54, 0),
+            changed_bg: Color::Rgb(64, 44, 86),
+            emphasis: Color::LightYellow,
+            gutter: Color::Reset,
+            tree_dir: Color::LightBlue,
+            selection_bg: Color::DarkGray,
+            scrollbar_track: Color::DarkGray,
+            scrollbar_change: Color::Rgb(230, 159, 0),
+            scrollbar_thumb: Color::Rgb(86, 180, 233),
+            heading: Color::Rgb(86, 180, 233),
+            muted: Color::DarkGray,
+            error: Color::Rgb(230, 159, 0),
+            row_bgs_16: [Color::Blue, Color::Yellow, Color::Magenta],
+        }
+    }
+
+    pub fn set(&mut self, slot: &str, color: Color) -> Result<()> {
+        let target = match slot {
+            "insert_bg" => &mut self.insert_bg,
+            "delete_bg" => &mut self.delete_bg,
+            "changed_bg" => &mut self.changed_bg,
+            "emphasis" => &mut self.emphasis,
+            "gutter" => &mut self.gutter,
+            "tree_dir" => &mut self.tree_dir,
+            "selection_bg" => &mut self.selection_bg,
+            "scrollbar_track" => &mut self.scrollbar_track,
+            "scrollbar_change" => &mut self.scrollbar_change,
+            "scrollbar_thumb" => &mut self.scrollbar_thumb,
+            "heading" => &mut self.heading,
+            "muted" => &mut self.muted,
+            "error" => &mut self.error,
+            _ => bail!(
+                "unknown color {:?} (expected one of {})",
+                slot,
+                COLOR_SLOTS.join(", ")
+            ),
+        };
+        *target = color;
+        Ok(())
+    }
+}
+
+/// A named palette plus the syntect theme for token colors.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub name: String,
+    pub colors: Palette,
+    pub syntax: Arc<SyntaxTheme>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::builtin("dark").expect("the dark theme is built in")
+    }
+}
+
+impl Theme {
+    pub fn builtin(name: &str) -> Result<Self> {
+        let (colors, syntax) = match name {
+            "dark" => (Palette::dark(), "base16-ocean.dark"),
+            "light" => (Palette::light(), "InspiredGitHub"),
+            "high-contrast" => (Palette::high_contrast(), "base16-eighties.dark"),
+            "colorblind" => (Palette::colorblind(), "base16-ocean.dark"),
+            _ => bail!(
+                "unknown theme {:?} (expected one of {})",
+                name,
+                THEMES.join(", ")
+            ),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            colors,
+            syntax: builtin_syntax(syntax)?,
+        })
+    }
+}
+
+/// One of syntect's bundled themes, such as `Solarized (light)`.
+pub fn builtin_syntax(name: &str) -> Result<Arc<SyntaxTheme>> {
+    static THEME_SET: OnceLock<ThemeSet> = OnceLock::new();
+    let themes = THEME_SET.get_or_init(ThemeSet::load_defaults);
+    themes
+        .themes
+        .get(name)
+        .map(|theme| Arc::new(theme.clone()))
+        .ok_or_else(|| {
+            let mut names: Vec<&str> = themes.themes.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            anyhow!(
+                "unknown syntax theme {:?} (expected one of {})",
+                name,
+                names.join(", ")
+            )
+        })
+}
+
+/// A TextMate/Sublime `.tmTheme` file.
+pub fn load_syntax_file(path: &Path) -> Result<Arc<SyntaxTheme>> {
+    ThemeSet::get_theme(path)
+        .map(Arc::new)
+        .with_context(|| format!("failed to load {}", path.display()))
+}
+
+/// Colors the terminal can show. Themes are written in 24-bit color and reduced to this when
+/// drawing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+    /// No colors; diff rows are told apart by bold, underline and reverse video.
+    Mono,
+}
+
+/// Names accepted by `--color`; `auto` detects the depth from the environment.
+pub const COLOR_DEPTHS: &[&str] = &["auto", "truecolor", "256", "16", "mono"];
+
+/// The 16 ANSI colors as xterm draws them by default.
+const ANSI_16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// Steps of the 6x6x6 color cube in the 256-color palette.
+const CUBE_STEPS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl ColorDepth {
+    /// `None` for `auto`.
+    pub fn from_name(name: &str) -> Result<Option<Self>> {
+        match name {
+            "auto" => Ok(None),
+            "truecolor" | "24bit" => Ok(Some(ColorDepth::TrueColor)),
+            "256" => Ok(Some(ColorDepth::Ansi256)),
+            "16" => Ok(Some(ColorDepth::Ansi16)),
+            "mono" | "none" => Ok(Some(ColorDepth::Mono)),
+            _ => bail!(
+                "unknown color depth {:?} (expected one of {})",
+                name,
+                COLOR_DEPTHS.join(", ")
+            ),
+        }
+    }
+
+    /// Reads `NO_COLOR`, `COLORTERM` and `TERM`.
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok();
+        ColorDepth::detect(
+            var("NO_COLOR").is_some_and(|value| !value.is_empty()),
+            var("COLORTERM").as_deref(),
+            var("TERM").as_deref(),
+        )
+    }
+
+    pub fn detect(no_color: bool, colorterm: Option<&str>, term: Option<&str>) -> Self {
+        if no_color || term == Some("dumb") {
+            return ColorDepth::Mono;
+        }
+        if matches!(colorterm, Some("truecolor" | "24bit")) {
+            return ColorDepth::TrueColor;
+        }
+        match term {
+            Some(term) if term.contains("direct") => ColorDepth::TrueColor,
+            Some(term) if term.contains("256color") => ColorDepth::Ansi256,
+            _ => ColorDepth::Ansi16,
+        }
+    }
+
+    /// The closest color this depth can show; `Reset` (the terminal's own color) in mono.
+    pub fn reduce(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::TrueColor, _) | (_, Color::Reset) => color,
+            (ColorDepth::Mono, _) => Color::Reset,
+            (ColorDepth::Ansi256, Color::Rgb(r, g, b)) => Color::Indexed(nearest_256(r, g, b)),
+            (ColorDepth::Ansi256, _) => color,
+            (ColorDepth::Ansi16, Color::Rgb(r, g, b)) => nearest_16(r, g, b),
+            (ColorDepth::Ansi16, Color::Indexed(index)) => {
+                let (r, g, b) = indexed_rgb(index);
+                nearest_16(r, g, b)
+            }
+            (ColorDepth::Ansi16, _) => color,
+        }
+    }
+
+    /// Like [`reduce`](Self::reduce), but keeps a faint tint's hue at 256 colors by staying in
+    /// the color cube, where the nearest gray would otherwise win.
+    pub fn reduce_tint(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::Ansi256, Color::Rgb(r, g, b)) => Color::Indexed(nearest_cube(r, g, b)),
+            _ => self.reduce(color),
+        }
+    }
+
+    /// Reduces a cell's colors; text that would land on a background of the same color is
+    /// drawn in black, or in white on black.
+    pub fn reduce_pair(self, fg: Color, bg: Color) -> (Color, Color) {
+        let (fg, bg) = (self.reduce(fg), self.reduce(bg));
+        match bg {
+            Color::Reset => (fg, bg),
+            _ if fg != bg => (fg, bg),
+            Color::Black => (Color::White, bg),
+            _ => (Color::Black, bg),
+        }
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).unsigned_abs();
+    d(r1, r2).pow(2) + d(g1, g2).pow(2) + d(b1, b2).pow(2)
+}
+
+fn nearest_16(r: u8, g: u8, b: u8) -> Color {
+    ANSI_16
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb, (r, g, b)))
+        .map_or(Color::Reset, |(color, _)| *color)
+}
+
+/// Index of the closer of the nearest color cube entry and the nearest gray.
+fn nearest_256(r: u8, g: u8, b: u8) -> u8 {
+    let cube = nearest_cube(r, g, b);
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_idx = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray = 232 + gray_idx;
+
+    if distance(indexed_rgb(gray), (r, g, b)) < distance(indexed_rgb(cube), (r, g, b)) {
+        gray
+    } else {
+        cube
+    }
+}
+
+fn nearest_cube(r: u8, g: u8, b: u8) -> u8 {
+    let step = |value: u8| {
+        (0..CUBE_STEPS.len())
+            .min_by_key(|idx| (CUBE_STEPS[*idx] as i32 - value as i32).abs())
+            .unwrap_or(0)
+    };
+    let (ri, gi, bi) = (step(r), step(g), step(b));
+    (16 + 36 * ri + 6 * gi + bi) as u8
+}
+
+fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_16[index as usize].1,
+        16..=231 => {
+            let index = index - 16;
+            (
+                CUBE_STEPS[(index / 36) as usize],
+                CUBE_STEPS[(index / 6 % 6) as usize],
+                CUBE_STEPS[(index % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + 10 * (index - 232);
+            (level, level, level)
+        }
+    }
+}
+
+/// `#rrggbb`, a palette index `0`-`255`, or a name such as `lightblue` or `reset`.
+pub fn parse_color(text: &str) -> Result<Color> {
+    Color::from_str(text.trim()).map_err(|_| anyhow!("invalid color {:?}", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::Color;
+
+    use super::{builtin_syntax, parse_color, ColorDepth, Palette, Theme, COLOR_SLOTS, THEMES};
+
+    #[test]
+    fn every_builtin_theme_loads() {
+        for name in THEMES {
+            let theme = Theme::builtin(name).unwrap();
+            assert_eq!(theme.name, *name);
+        }
+        assert!(Theme::builtin("neon").is_err());
+        assert!(builtin_syntax("Solarized (light)").is_ok());
+    }
+
+    #[test]
+    fn detects_color_depth_from_the_environment() {
+        let detect = ColorDepth::detect;
+        assert_eq!(
+            detect(false, Some("truecolor"), Some("xterm-256color")),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            detect(false, None, Some("screen-256color")),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(detect(false, None, Some("xterm")), ColorDepth::Ansi16);
+        assert_eq!(detect(false, None, None), ColorDepth::Ansi16);
+        assert_eq!(detect(false, None, Some("dumb")), ColorDepth::Mono);
+        assert_eq!(
+            detect(true, Some("truecolor"), Some("xterm")),
+            ColorDepth::Mono
+        );
+    }
+
+    #[test]
+    fn reduces_rgb_to_the_nearest_palette_color() {
+        let insert_bg = Color::Rgb(18, 60, 36);
+        assert_eq!(ColorDepth::TrueColor.reduce(insert_bg), insert_bg);
+        assert_eq!(
+            ColorDepth::Ansi256.reduce(Color::Rgb(255, 0, 0)),
+            Color::Indexed(196)
+        );
+        assert_eq!(
+            ColorDepth::Ansi256.reduce(Color::Rgb(128, 128, 128)),
+            Color::Indexed(244)
+        );
+        // Dark tints all land on black, so rows use `Palette::row_bgs_16` at this depth.
+        assert_eq!(ColorDepth::Ansi16.reduce(insert_bg), Color::Black);
+        assert_eq!(
+            ColorDepth::Ansi16.reduce(Color::Rgb(250, 10, 10)),
+            Color::LightRed
+        );
+        assert_eq!(ColorDepth::Ansi16.reduce(Color::Indexed(21)), Color::Blue);
+        assert_eq!(ColorDepth::Ansi256.reduce(insert_bg), Color::Indexed(235));
+        assert_eq!(
+            ColorDepth::Ansi256.reduce_tint(insert_bg),
+            Color::Indexed(22)
+        );
+        assert_eq!(ColorDepth::Mono.reduce(Color::Yellow), Color::Reset);
+    }
+
+    #[test]
+    fn text_stays_visible_on_a_background_of_its_reduced_color() {
+        let green = Color::Rgb(10, 200, 10);
+        assert_eq!(
+            ColorDepth::Ansi16.reduce_pair(green, Color::Green),
+            (Color::Black, Color::Green)
+        );
+        assert_eq!(
+            ColorDepth::Ansi16.reduce_pair(Color::Rgb(5, 5, 5), Color::Black),
+            (Color::White, Color::Black)
+        );
+        assert_eq!(
+            ColorDepth::Ansi16.reduce_pair(Color::Red, Color::Green),
+            (Color::Red, Color::Green)
+        );
+        assert_eq!(
+            ColorDepth::Mono.reduce_pair(Color::Red, Color::Green),
+            (Color::Reset, Color::Reset)
+        );
+    }
+
+    #[test]
+    fn colors_parse_and_every_slot_is_settable() {
+        assert_eq!(parse_color("#0a0B0c").unwrap(), Color::Rgb(10, 11, 12));
+        assert_eq!(parse_color("236").unwrap(), Color::Indexed(236));
+        assert_eq!(parse_color(" light-blue ").unwrap(), Color::LightBlue);
+        assert!(parse_color("#12345").is_err());
+
+        let mut palette = Palette::dark();
+        for slot in COLOR_SLOTS {
+            palette.set(slot, Color::Magenta).unwrap();
+        }
+        assert_eq!(palette.scrollbar_thumb, Color::Magenta);
+        assert!(palette.set("border", Color::Red).is_err());
+    }
+}
@@ -1,36 +1,50 @@
-use std::collections::BTreeMap;
-use std::path::{Component, PathBuf};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Component, Path, PathBuf};
 
 use crate::model::{ChangedFile, TreeNode, TreeRow};
 
-pub fn build_tree(files: &[ChangedFile]) -> TreeNode {
+/// Builds the path tree of the files accepted by `keep`; indices still refer to `files`.
+pub fn build_tree(files: &[ChangedFile], keep: impl Fn(&ChangedFile) -> bool) -> TreeNode {
     let mut root = TreeNode::root();
     for (idx, file) in files.iter().enumerate() {
-        insert_path(&mut root, &file.path, idx);
+        if keep(file) {
+            insert_path(&mut root, &file.path, idx);
+        }
     }
     sort_node(&mut root);
     root
 }
 
-pub fn flatten_tree(root: &TreeNode, files: &[ChangedFile]) -> Vec<TreeRow> {
+/// Flattens the tree into display rows, listing `collapsed` directories without their contents.
+pub fn flatten_tree(
+    root: &TreeNode,
+    files: &[ChangedFile],
+    collapsed: &HashSet<PathBuf>,
+) -> Vec<TreeRow> {
     let mut rows = Vec::new();
-    flatten_recursive(root, 0, files, &mut rows);
+    flatten_recursive(root, Path::new(""), 0, files, collapsed, &mut rows);
     rows
 }
 
 fn flatten_recursive(
     node: &TreeNode,
+    parent: &Path,
     depth: usize,
     files: &[ChangedFile],
+    collapsed: &HashSet<PathBuf>,
     rows: &mut Vec<TreeRow>,
 ) {
     for child in &node.children {
+        let path = parent.join(&child.name);
+        let is_collapsed = child.is_dir && collapsed.contains(&path);
         let label = if let Some(file_idx) = child.file_index {
             let indicator = files
                 .get(file_idx)
                 .map(|f| f.status.indicator())
                 .unwrap_or("[ ]");
             format!("{} {}", indicator, child.name)
+        } else if is_collapsed {
+            format!("{}/ …", child.name)
         } else {
             format!("{}/", child.name)
         };
@@ -40,15 +54,16 @@ fn flatten_recursive(
             label,
             is_dir: child.is_dir,
             file_index: child.file_index,
+            path: path.clone(),
         });
 
-        if child.is_dir {
-            flatten_recursive(child, depth + 1, files, rows);
+        if child.is_dir && !is_collapsed {
+            flatten_recursive(child, &path, depth + 1, files, collapsed, rows);
         }
     }
 }
 
-fn insert_path(root: &mut TreeNode, path: &PathBuf, file_idx: usize) {
+fn insert_path(root: &mut TreeNode, path: &Path, file_idx: usize) {
     let mut current = root;
     let components: Vec<String> = path
         .components()
@@ -1,54 +1,373 @@
-use std::cell::RefCell;
-use std::path::Path;
-use std::sync::OnceLock;
-
-use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use crossterm::event::MouseEvent;
+use ratatui::layout::{Constraint, Direction, Layout, Position, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
-use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph};
 use ratatui::Frame;
-use syntect::easy::HighlightLines;
-use syntect::highlighting::{FontStyle, Style as SyntectStyle, Theme, ThemeSet};
-use syntect::parsing::{SyntaxReference, SyntaxSet};
 
-use crate::app::App;
-use crate::model::{AlignedRow, RowKind};
+use crate::app::{App, Status};
+use crate::command::Prompt;
+use crate::help::{help_lines, HelpLine, HelpView};
+use crate::highlight::HighlightedRow;
+use crate::input::{map_mouse_kind, MouseInput, MouseTarget};
+use crate::layout::TreePosition;
+use crate::model::{format_bytes, AlignedRow, ChangedFile, RowKind};
+use crate::theme::{ColorDepth, Palette};
+
+/// Where each pane goes for the current terminal size; shared by drawing and mouse hit-testing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaneLayout {
+    /// Tree and diff together.
+    pub main: Rect,
+    pub tree: Option<Rect>,
+    pub left: Rect,
+    pub right: Rect,
+    pub scrollbar: Rect,
+    /// Status bar, or the `:` prompt while it is open.
+    pub status: Rect,
+}
+
+impl PaneLayout {
+    /// Text rows inside the diff panes' borders.
+    pub fn diff_rows(&self) -> usize {
+        self.left.height.saturating_sub(2).max(1) as usize
+    }
+
+    /// Characters of text per diff line: borders, line number and marker take the rest. Both
+    /// panes use the narrower one's width so their rows stay side by side.
+    pub fn text_width(&self) -> usize {
+        self.left.width.min(self.right.width).saturating_sub(8) as usize
+    }
+
+    /// Text rows inside the tree's border.
+    pub fn tree_rows(&self) -> usize {
+        self.tree
+            .map_or(1, |tree| tree.height.saturating_sub(2).max(1) as usize)
+    }
+}
+
+pub fn layout(area: Rect, app: &App) -> PaneLayout {
+    let (main, status) = split_status_line(area);
+    let settings = app.layout;
+    let (tree, diff) = if app.show_tree {
+        let tree_size = Constraint::Percentage(settings.tree_percent);
+        let diff_size = Constraint::Percentage(100 - settings.tree_percent);
+        let (direction, constraints) = match settings.tree_position {
+            TreePosition::Left => (Direction::Horizontal, [tree_size, diff_size]),
+            TreePosition::Right => (Direction::Horizontal, [diff_size, tree_size]),
+            TreePosition::Bottom => (Direction::Vertical, [diff_size, tree_size]),
+        };
+        let chunks = Layout::default()
+            .direction(direction)
+            .constraints(constraints)
+            .split(main);
+        match settings.tree_position {
+            TreePosition::Left => (Some(chunks[0]), chunks[1]),
+            TreePosition::Right | TreePosition::Bottom => (Some(chunks[1]), chunks[0]),
+        }
+    } else {
+        (None, main)
+    };
+    let diff_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(settings.split_percent),
+            Constraint::Percentage(100 - settings.split_percent),
+            Constraint::Length(1),
+        ])
+        .split(diff);
+
+    PaneLayout {
+        main,
+        tree,
+        left: diff_chunks[0],
+        right: diff_chunks[1],
+        scrollbar: diff_chunks[2],
+        status,
+    }
+}
+
+pub fn render(frame: &mut Frame<'_>, app: &App, layout: &PaneLayout) {
+    match &app.prompt {
+        Some(prompt) => render_prompt(frame, app, prompt, layout.status),
+        None => render_status_bar(frame, app, layout.status),
+    }
+
+    if let Some(tree) = layout.tree {
+        render_tree(frame, app, tree);
+    }
+    render_diff(frame, app, layout);
+
+    if let Some(help) = &app.help {
+        render_help(frame, app, help, layout.main);
+    }
 
-#[derive(Debug)]
-struct HighlightedRow {
-    left: Vec<Span<'static>>,
-    right: Vec<Span<'static>>,
+    reduce_colors(frame, app.color_depth);
 }
 
-#[derive(Debug)]
-struct FileHighlightCache {
-    file_path: String,
-    epoch: u64,
-    rows_ptr: usize,
-    rows_len: usize,
-    rows: Vec<HighlightedRow>,
+/// Brings every drawn color, syntax tokens included, within what the terminal can show.
+fn reduce_colors(frame: &mut Frame<'_>, depth: ColorDepth) {
+    if depth == ColorDepth::TrueColor {
+        return;
+    }
+    for cell in frame.buffer_mut().content.iter_mut() {
+        (cell.fg, cell.bg) = depth.reduce_pair(cell.fg, cell.bg);
+    }
 }
 
-thread_local! {
-    static HIGHLIGHT_CACHE: RefCell<Option<FileHighlightCache>> = const { RefCell::new(None) };
+/// Selected tree row and completion candidate; reverse video when there are no colors.
+fn selection_style(app: &App) -> Style {
+    let style = Style::default().add_modifier(Modifier::BOLD);
+    match app.color_depth {
+        ColorDepth::Mono => style.add_modifier(Modifier::REVERSED),
+        _ => style.bg(app.theme.colors.selection_bg),
+    }
 }
 
-pub fn render(frame: &mut Frame<'_>, app: &App) {
-    if app.show_tree {
-        let chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Percentage(28), Constraint::Percentage(72)])
-            .split(frame.area());
+/// Resolves a mouse event to the pane under the pointer; `None` for gestures fdf ignores.
+pub fn mouse_input(layout: &PaneLayout, app: &App, event: MouseEvent) -> Option<MouseInput> {
+    let kind = map_mouse_kind(&event)?;
+    let position = Position::new(event.column, event.row);
+
+    let main = layout.main;
+    let on_tree_border = layout.tree.is_some_and(|tree| {
+        // Either side of the line between the panes grabs the border.
+        let (pointer, edge) = match app.layout.tree_position {
+            TreePosition::Left => (event.column + 1, tree.right()),
+            TreePosition::Right => (event.column + 1, tree.x),
+            TreePosition::Bottom => (event.row + 1, tree.y),
+        };
+        main.contains(position) && (pointer == edge || pointer == edge + 1)
+    });
+    let diff_rows = layout.left.y..layout.left.bottom();
+    let on_split_border = diff_rows.contains(&event.row)
+        && (event.column + 1 == layout.left.right() || event.column == layout.left.right());
+
+    let target = match layout.tree {
+        _ if on_tree_border => Some(MouseTarget::TreeBorder),
+        _ if on_split_border => Some(MouseTarget::SplitBorder),
+        Some(tree) if tree.contains(position) => {
+            let row = (event.row as usize)
+                .checked_sub(tree.y as usize + 1)
+                .filter(|row| event.row + 1 < tree.bottom() && *row < layout.tree_rows())
+                .map(|row| row + app.tree_v_scroll)
+                .filter(|row| *row < app.tree_rows.len());
+            Some(row.map_or(MouseTarget::Tree, MouseTarget::TreeRow))
+        }
+        _ if layout.scrollbar.contains(position) => Some(MouseTarget::Scrollbar),
+        _ if layout.left.contains(position) || layout.right.contains(position) => {
+            Some(MouseTarget::Diff)
+        }
+        _ => None,
+    };
+
+    let track = (
+        event.row.saturating_sub(layout.scrollbar.y) as usize,
+        layout.scrollbar.height as usize,
+    );
+    let percent = |part: u16, whole: u16| (part as u32 * 100 / whole.max(1) as u32).min(100) as u16;
+    let tree_percent = match app.layout.tree_position {
+        TreePosition::Left => percent((event.column + 1).saturating_sub(main.x), main.width),
+        TreePosition::Right => percent(main.right().saturating_sub(event.column), main.width),
+        TreePosition::Bottom => percent(main.bottom().saturating_sub(event.row), main.height),
+    };
+    let split_percent = percent(
+        (event.column + 1).saturating_sub(layout.left.x),
+        layout.left.width + layout.right.width,
+    );
+
+    Some(MouseInput {
+        kind,
+        target,
+        track,
+        tree_percent,
+        split_percent,
+    })
+}
+
+/// Keybinding overlay centered over `area`, generated from the active keymap.
+fn render_help(frame: &mut Frame<'_>, app: &App, help: &HelpView, area: Rect) {
+    let colors = &app.theme.colors;
+    let width = (area.width * 7 / 10).max(50).min(area.width);
+    let height = (area.height * 8 / 10).max(10).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+
+    let entries = help_lines(&app.keymap, &help.query);
+    let key_width = entries
+        .iter()
+        .filter_map(|line| match line {
+            HelpLine::Entry { keys, .. } => Some(keys.chars().count()),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+        .min(24);
+    let mut lines: Vec<Line<'static>> = entries
+        .into_iter()
+        .map(|line| match line {
+            HelpLine::Heading(heading) => Line::styled(
+                heading,
+                Style::default()
+                    .fg(colors.heading)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            HelpLine::Entry { keys, summary } => Line::from(vec![
+                Span::styled(
+                    format!("  {:<width$}  ", keys, width = key_width),
+                    Style::default().fg(colors.emphasis),
+                ),
+                Span::raw(summary),
+            ]),
+            HelpLine::Blank => Line::default(),
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::from(format!("nothing matches {:?}", help.query)));
+    }
 
-        render_tree(frame, app, chunks[0]);
-        render_diff(frame, app, chunks[1]);
+    let search = if help.searching || !help.query.is_empty() {
+        format!(" /{} ", help.query)
     } else {
-        render_diff(frame, app, frame.area());
+        " / search  Esc close ".to_string()
+    };
+    let inner_height = popup.height.saturating_sub(2) as usize;
+    let scroll = help
+        .scroll
+        .min(lines.len().saturating_sub(inner_height))
+        .min(u16::MAX as usize) as u16;
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(lines).scroll((scroll, 0)).block(
+            Block::default()
+                .title(" Help ")
+                .title_bottom(Line::from(search).right_aligned())
+                .borders(Borders::ALL),
+        ),
+        popup,
+    );
+    if help.searching {
+        // On the trailing space of the right-aligned ` /query ` title.
+        frame.set_cursor_position((
+            popup.right().saturating_sub(2),
+            popup.bottom().saturating_sub(1),
+        ));
+    }
+}
+
+/// Takes the last line for the status bar, which the `:` prompt replaces while open.
+fn split_status_line(area: Rect) -> (Rect, Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(area);
+    (chunks[0], chunks[1])
+}
+
+fn render_prompt(frame: &mut Frame<'_>, app: &App, prompt: &Prompt, area: Rect) {
+    frame.set_cursor_position((
+        area.x
+            .saturating_add(1 + prompt.input.chars().count() as u16)
+            .min(area.right().saturating_sub(1)),
+        area.y,
+    ));
+    let mut spans = vec![Span::raw(format!(":{}", prompt.input))];
+    // Remaining candidates after the input, the current one highlighted.
+    if let Some(completion) = &prompt.completion {
+        spans.push(Span::raw("   "));
+        for (idx, candidate) in completion.candidates.iter().enumerate() {
+            let style = if idx == completion.selected {
+                selection_style(app)
+            } else {
+                Style::default().fg(app.theme.colors.muted)
+            };
+            spans.push(Span::styled(candidate.clone(), style));
+            spans.push(Span::raw(" "));
+        }
+    }
+    frame.render_widget(Paragraph::new(Line::from(spans)), area);
+}
+
+/// Position and modes on the left; pending keys and the last message on the right, which
+/// keeps its room when the line is too narrow for both.
+fn render_status_bar(frame: &mut Frame<'_>, app: &App, area: Rect) {
+    let colors = &app.theme.colors;
+    let status = app.status();
+
+    let mut right = Vec::new();
+    if !app.pending.is_empty() {
+        right.push(Span::styled(
+            app.pending.describe(),
+            Style::default().fg(colors.emphasis),
+        ));
     }
+    if let Some(message) = &app.message {
+        let style = if message.is_error {
+            Style::default()
+                .fg(colors.error)
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+        if !right.is_empty() {
+            right.push(Span::raw("  "));
+        }
+        right.push(Span::styled(message.text.clone(), style));
+    }
+    right.push(Span::raw(" "));
+    let right = Line::from(right);
+    let right_width = (right.width() as u16).min(area.width);
+
+    let left = Line::from(vec![
+        Span::raw(format!(" {}", status_position(&status))),
+        Span::styled(
+            format!("  {}", status.modes.join(" · ")),
+            Style::default().fg(colors.muted),
+        ),
+    ]);
+    let left_area = Rect {
+        width: area.width - right_width,
+        ..area
+    };
+    let right_area = Rect {
+        x: left_area.right(),
+        width: right_width,
+        ..area
+    };
+    frame.render_widget(Paragraph::new(left), left_area);
+    frame.render_widget(Paragraph::new(right.right_aligned()), right_area);
 }
 
-pub fn viewport_rows(area: Rect) -> usize {
-    area.height.saturating_sub(2).max(1) as usize
+/// `file 7/42  hunk 3/11  L120 R124  +12 -3`, leaving out what is not known yet.
+fn status_position(status: &Status) -> String {
+    let mut parts = Vec::new();
+    if let Some((pos, total)) = status.file {
+        parts.push(format!("file {}/{}", pos, total));
+    }
+    if let Some((current, total)) = status.hunk {
+        let current = current.map_or("-".to_string(), |idx| idx.to_string());
+        parts.push(format!("hunk {}/{}", current, total));
+    }
+    if status.left_line.is_some() || status.right_line.is_some() {
+        let line = |no: Option<usize>| no.map_or("-".to_string(), |no| no.to_string());
+        parts.push(format!(
+            "L{} R{}",
+            line(status.left_line),
+            line(status.right_line)
+        ));
+    }
+    if let Some((adds, dels)) = status.stats {
+        parts.push(format!("+{} -{}", adds, dels));
+    }
+    if parts.is_empty() {
+        parts.push("no changes".to_string());
+    }
+    parts.join("  ")
 }
 
 fn render_tree(frame: &mut Frame<'_>, app: &App, area: Rect) {
@@ -65,7 +384,7 @@ fn render_tree(frame: &mut Frame<'_>, app: &App, area: Rect) {
                 .take(content_width)
                 .collect();
             let style = if row.is_dir {
-                Style::default().fg(Color::Blue)
+                Style::default().fg(app.theme.colors.tree_dir)
             } else {
                 Style::default()
             };
@@ -73,63 +392,75 @@ fn render_tree(frame: &mut Frame<'_>, app: &App, area: Rect) {
         })
         .collect();
 
-    let mut state = ListState::default();
-    if let Some(selected) = selected_tree_row_idx(app) {
-        state.select(Some(selected));
+    // The app owns the tree's scroll offset, so the wheel can move the selection out of view.
+    let visible = app.tree_v_scroll..app.tree_v_scroll + area.height.saturating_sub(2) as usize;
+    let mut state = ListState::default().with_offset(app.tree_v_scroll);
+    state.select(selected_tree_row_idx(app).filter(|row| visible.contains(row)));
+
+    let mut title = "Changed Files".to_string();
+    if let Some(filter) = &app.filter {
+        title.push_str(&format!(" [filter: {}]", filter.pattern));
+    }
+    if app.watcher.is_some() && !app.live_updates {
+        title.push_str(" [live updates paused]");
     }
 
     let list = List::new(items)
-        .block(
-            Block::default()
-                .title("Changed Files")
-                .borders(Borders::ALL),
-        )
-        .highlight_style(
-            Style::default()
-                .bg(Color::DarkGray)
-                .add_modifier(Modifier::BOLD),
-        );
+        .block(Block::default().title(title).borders(Borders::ALL))
+        .highlight_style(selection_style(app));
 
     frame.render_stateful_widget(list, area, &mut state);
 }
 
-fn render_diff(frame: &mut Frame<'_>, app: &App, area: Rect) {
-    let right_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage(50),
-            Constraint::Percentage(50),
-            Constraint::Length(1),
-        ])
-        .split(area);
-
+fn render_diff(frame: &mut Frame<'_>, app: &App, layout: &PaneLayout) {
     let selected_file = app.selected_file();
     let title = selected_file
-        .map(|file| file.path.to_string_lossy().to_string())
+        .map(ChangedFile::display_name)
         .unwrap_or_else(|| "No changes".to_string());
 
-    let syntax = selected_file
-        .map(|file| syntax_for_path(&file.path, syntax_set()))
-        .unwrap_or_else(|| syntax_set().find_syntax_plain_text());
-
     let (left_lines, right_lines) = if let Some(file) = selected_file {
         if let Some(rows) = file.aligned_rows.as_ref() {
-            with_highlighted_rows_for_file(
-                &file.path,
-                rows,
-                syntax,
-                app.highlight_epoch,
-                |highlighted_rows| {
-                    build_visible_rows(
-                        rows,
-                        highlighted_rows,
-                        app.v_scroll,
-                        app.h_scroll,
-                        right_chunks[0].width as usize,
-                        right_chunks[0].height as usize,
-                    )
-                },
+            let viewport_height = layout.diff_rows();
+            let start = app.v_scroll.min(rows.len());
+            let end = (start + viewport_height).min(rows.len());
+            let window = &rows[start..end];
+            let text_width = layout.text_width();
+
+            let highlighted_rows = if file.degraded {
+                window.iter().map(HighlightedRow::plain).collect()
+            } else {
+                app.highlights.window(
+                    &file.path,
+                    file.content_hash.unwrap_or_default(),
+                    rows,
+                    app.highlight_epoch,
+                    start,
+                    end,
+                )
+            };
+            let changed: Vec<bool> = (start..end)
+                .map(|idx| file.changed_rows.contains(&idx))
+                .collect();
+            build_visible_rows(
+                &app.theme.colors,
+                app.color_depth,
+                window,
+                &highlighted_rows,
+                &changed,
+                (!app.wrap).then_some(app.h_scroll),
+                text_width,
             )
+        } else if app.is_loading() {
+            let loading = format!("Loading {}...", title);
+            (vec![Line::from(loading.clone())], vec![Line::from(loading)])
+        } else if let Some(err) = &file.load_error {
+            let style = Style::default().fg(app.theme.colors.error);
+            let lines = vec![
+                Line::styled(err.clone(), style),
+                Line::default(),
+                Line::from("Select the file again or refresh to retry."),
+            ];
+            (lines.clone(), lines)
         } else {
             (
                 vec![Line::from("No changed files")],
@@ -143,74 +474,147 @@ fn render_diff(frame: &mut Frame<'_>, app: &App, area: Rect) {
         )
     };
 
+    let title = match selected_file {
+        Some(file) if file.degraded => format!(
+            "{} [degraded: no highlighting, chunked diff; limits {} / {} chars per line]",
+            title,
+            format_bytes(app.limits.max_file_bytes),
+            app.limits.max_line_chars
+        ),
+        _ if app.is_loading() => format!("{} [loading]", title),
+        Some(file) if file.load_error.is_some() => format!("{} [load failed]", title),
+        _ => title,
+    };
+
+    let [left_name, right_name] = app.git.side_names();
     let left = Paragraph::new(left_lines).block(
         Block::default()
-            .title(format!("HEAD | {}", title))
+            .title(format!("{} | {}", left_name, title))
             .borders(Borders::ALL),
     );
 
     let right = Paragraph::new(right_lines).block(
         Block::default()
-            .title(format!("WORKTREE | {}", title))
+            .title(format!("{} | {}", right_name, title))
             .borders(Borders::ALL),
     );
 
-    frame.render_widget(left, right_chunks[0]);
-    frame.render_widget(right, right_chunks[1]);
+    frame.render_widget(left, layout.left);
+    frame.render_widget(right, layout.right);
 
     render_scrollbar(
         frame,
-        right_chunks[2],
+        layout.scrollbar,
+        &app.theme.colors,
         app.selected_rows().map(|rows| rows.as_slice()),
         app.v_scroll,
         app.viewport_rows,
     );
 }
 
-fn build_visible_rows(
+/// Styled `HEAD` and worktree lines for `rows`: a 4-column line number, a marker column and
+/// at most `text_width` characters of text from `h_scroll` on. Without `h_scroll` long lines
+/// wrap instead, and the shorter side of a row is padded so the next row starts level.
+pub fn build_visible_rows(
+    colors: &Palette,
+    depth: ColorDepth,
     rows: &[AlignedRow],
     highlighted_rows: &[HighlightedRow],
-    v_scroll: usize,
-    h_scroll: usize,
-    pane_width: usize,
-    pane_height: usize,
+    changed_since_refresh: &[bool],
+    h_scroll: Option<usize>,
+    text_width: usize,
 ) -> (Vec<Line<'static>>, Vec<Line<'static>>) {
-    let viewport_height = pane_height.saturating_sub(2).max(1);
-    let max_visible = rows.len().saturating_sub(v_scroll).min(viewport_height);
-    let end_idx = (v_scroll + max_visible)
-        .min(rows.len())
-        .min(highlighted_rows.len());
-    let mut left = Vec::with_capacity(max_visible);
-    let mut right = Vec::with_capacity(max_visible);
-
-    let content_width = pane_width.saturating_sub(8);
-
-    for idx in v_scroll..end_idx {
-        let row = &rows[idx];
-        let highlighted = &highlighted_rows[idx];
-
+    let mut left = Vec::with_capacity(rows.len());
+    let mut right = Vec::with_capacity(rows.len());
+
+    for ((row, highlighted), changed) in
+        rows.iter().zip(highlighted_rows).zip(changed_since_refresh)
+    {
+        let base = row_style(colors, depth, row.kind);
+        let Some(h_scroll) = h_scroll else {
+            let mut left_lines = wrapped_diff_lines(
+                colors,
+                row.left_line_no,
+                base,
+                *changed,
+                &highlighted.left,
+                text_width,
+            );
+            let mut right_lines = wrapped_diff_lines(
+                colors,
+                row.right_line_no,
+                base,
+                *changed,
+                &highlighted.right,
+                text_width,
+            );
+            let height = left_lines.len().max(right_lines.len());
+            let blank = styled_diff_line(colors, None, base, false, &[], 0, 0);
+            left_lines.resize(height, blank.clone());
+            right_lines.resize(height, blank);
+            left.extend(left_lines);
+            right.extend(right_lines);
+            continue;
+        };
         left.push(styled_diff_line(
+            colors,
             row.left_line_no,
-            row.kind,
+            base,
+            *changed,
             &highlighted.left,
             h_scroll,
-            content_width,
+            text_width,
         ));
         right.push(styled_diff_line(
+            colors,
             row.right_line_no,
-            row.kind,
+            base,
+            *changed,
             &highlighted.right,
             h_scroll,
-            content_width,
+            text_width,
         ));
     }
 
     (left, right)
 }
 
+/// One side of a row broken into lines of `width` characters; only the first shows the line
+/// number and marker.
+fn wrapped_diff_lines(
+    colors: &Palette,
+    line_no: Option<usize>,
+    base: Style,
+    changed_since_refresh: bool,
+    highlighted_spans: &[Span<'static>],
+    width: usize,
+) -> Vec<Line<'static>> {
+    let width = width.max(1);
+    let chars: usize = highlighted_spans
+        .iter()
+        .map(|span| span.content.chars().count())
+        .sum();
+    (0..chars.div_ceil(width).max(1))
+        .map(|idx| {
+            let first = idx == 0;
+            styled_diff_line(
+                colors,
+                line_no.filter(|_| first),
+                base,
+                changed_since_refresh && first,
+                highlighted_spans,
+                idx * width,
+                width,
+            )
+        })
+        .collect()
+}
+
 fn styled_diff_line(
+    colors: &Palette,
     line_no: Option<usize>,
-    kind: RowKind,
+    base: Style,
+    changed_since_refresh: bool,
     highlighted_spans: &[Span<'static>],
     h_scroll: usize,
     max_chars: usize,
@@ -218,68 +622,25 @@ fn styled_diff_line(
     let number = line_no
         .map(|n| format!("{:>4}", n))
         .unwrap_or_else(|| "    ".to_string());
-    let base = row_style(kind);
-
-    let mut spans = Vec::new();
-    spans.push(Span::styled(format!("{} ", number), base));
-    spans.extend(clip_spans(highlighted_spans, h_scroll, max_chars));
-
-    Line::from(spans)
-}
-
-fn with_highlighted_rows_for_file<R>(
-    file_path: &Path,
-    rows: &[AlignedRow],
-    syntax: &SyntaxReference,
-    epoch: u64,
-    f: impl FnOnce(&[HighlightedRow]) -> R,
-) -> R {
-    let file_path_key = file_path.to_string_lossy().into_owned();
-    let rows_ptr = rows.as_ptr() as usize;
-    let rows_len = rows.len();
-
-    HIGHLIGHT_CACHE.with(|cache_cell| {
-        let mut cache = cache_cell.borrow_mut();
-        let cache_miss = cache.as_ref().map_or(true, |existing| {
-            existing.epoch != epoch
-                || existing.file_path != file_path_key
-                || existing.rows_ptr != rows_ptr
-                || existing.rows_len != rows_len
-        });
-
-        if cache_miss {
-            let computed_rows = build_highlighted_rows(rows, syntax);
-            *cache = Some(FileHighlightCache {
-                file_path: file_path_key,
-                epoch,
-                rows_ptr,
-                rows_len,
-                rows: computed_rows,
-            });
-        }
-
-        let highlighted_rows = cache
-            .as_ref()
-            .map(|entry| entry.rows.as_slice())
-            .unwrap_or(&[]);
-        f(highlighted_rows)
-    })
-}
-
-fn build_highlighted_rows(rows: &[AlignedRow], syntax: &SyntaxReference) -> Vec<HighlightedRow> {
-    let mut left_highlighter = HighlightLines::new(syntax, syntax_theme());
-    let mut right_highlighter = HighlightLines::new(syntax, syntax_theme());
-    let mut highlighted_rows = Vec::with_capacity(rows.len());
-
-    for row in rows {
-        let base = row_style(row.kind);
-        highlighted_rows.push(HighlightedRow {
-            left: highlight_line(&row.left_text, &mut left_highlighter, base),
-            right: highlight_line(&row.right_text, &mut right_highlighter, base),
-        });
+    let mut spans = vec![Span::styled(number, base.fg(colors.gutter))];
+    if changed_since_refresh {
+        spans.push(Span::styled(
+            "•",
+            base.fg(colors.emphasis).add_modifier(Modifier::BOLD),
+        ));
+    } else {
+        spans.push(Span::styled(" ", base));
     }
+    spans.extend(
+        clip_spans(highlighted_spans, h_scroll, max_chars)
+            .into_iter()
+            .map(|span| {
+                let style = base.patch(span.style);
+                span.style(style)
+            }),
+    );
 
-    highlighted_rows
+    Line::from(spans)
 }
 
 fn clip_spans(spans: &[Span<'static>], offset: usize, max_chars: usize) -> Vec<Span<'static>> {
@@ -318,128 +679,29 @@ fn clip_spans(spans: &[Span<'static>], offset: usize, max_chars: usize) -> Vec<S
     clipped
 }
 
-fn highlight_line(
-    line: &str,
-    highlighter: &mut HighlightLines<'_>,
-    base: Style,
-) -> Vec<Span<'static>> {
-    let mut line_for_highlight = String::with_capacity(line.len() + 1);
-    line_for_highlight.push_str(line);
-    line_for_highlight.push('\n');
-
-    let ranges = match highlighter.highlight_line(&line_for_highlight, syntax_set()) {
-        Ok(ranges) => ranges,
-        Err(_) => return vec![Span::styled(line.to_string(), base)],
-    };
-
-    let mut spans: Vec<Span<'static>> = ranges
-        .into_iter()
-        .map(|(style, text)| Span::styled(text.to_string(), syntect_to_ratatui_style(style, base)))
-        .collect();
-    trim_trailing_newline(&mut spans);
-
-    if spans.is_empty() {
-        vec![Span::styled(String::new(), base)]
-    } else {
-        spans
+/// Without colors, rows are told apart by inserts in bold, deletes in reverse video and changes
+/// underlined; with 16 colors, by the palette's fixed fallback backgrounds; with 256, by
+/// backgrounds that keep their hue instead of fading to gray.
+fn row_style(colors: &Palette, depth: ColorDepth, kind: RowKind) -> Style {
+    if depth == ColorDepth::Mono {
+        let modifier = match kind {
+            RowKind::Equal => Modifier::empty(),
+            RowKind::Changed => Modifier::UNDERLINED,
+            RowKind::Insert => Modifier::BOLD,
+            RowKind::Delete => Modifier::REVERSED,
+        };
+        return Style::default().add_modifier(modifier);
     }
-}
-
-fn trim_trailing_newline(spans: &mut Vec<Span<'static>>) {
-    let Some(last_idx) = spans.iter().rposition(|span| !span.content.is_empty()) else {
-        return;
+    let [insert_16, delete_16, changed_16] = colors.row_bgs_16;
+    let (bg, fallback) = match kind {
+        RowKind::Equal => return Style::default(),
+        RowKind::Changed => (colors.changed_bg, changed_16),
+        RowKind::Insert => (colors.insert_bg, insert_16),
+        RowKind::Delete => (colors.delete_bg, delete_16),
     };
-
-    if !spans[last_idx].content.ends_with('\n') {
-        return;
-    }
-
-    let mut content = spans[last_idx].content.to_string();
-    content.pop();
-
-    if content.is_empty() {
-        spans.remove(last_idx);
-    } else {
-        let style = spans[last_idx].style;
-        spans[last_idx] = Span::styled(content, style);
-    }
-}
-
-fn syntax_set() -> &'static SyntaxSet {
-    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
-    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
-}
-
-fn syntax_theme() -> &'static Theme {
-    static THEME: OnceLock<Theme> = OnceLock::new();
-    THEME.get_or_init(|| {
-        let themes = ThemeSet::load_defaults();
-        themes
-            .themes
-            .get("base16-ocean.dark")
-            .or_else(|| themes.themes.values().next())
-            .cloned()
-            .expect("syntect default themes should not be empty")
-    })
-}
-
-fn syntax_for_path<'a>(path: &Path, syntax_set: &'a SyntaxSet) -> &'a SyntaxReference {
-    if let Some(file_name) = path.file_name().and_then(|name| name.to_str()) {
-        if let Some(syntax) = syntax_set.find_syntax_by_token(file_name) {
-            return syntax;
-        }
-
-        let lower_file_name = file_name.to_ascii_lowercase();
-        if lower_file_name != file_name {
-            if let Some(syntax) = syntax_set.find_syntax_by_token(&lower_file_name) {
-                return syntax;
-            }
-        }
-    }
-
-    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
-        if let Some(syntax) = syntax_set.find_syntax_by_extension(extension) {
-            return syntax;
-        }
-
-        let lower_extension = extension.to_ascii_lowercase();
-        if lower_extension != extension {
-            if let Some(syntax) = syntax_set.find_syntax_by_extension(&lower_extension) {
-                return syntax;
-            }
-        }
-    }
-
-    syntax_set.find_syntax_plain_text()
-}
-
-fn syntect_to_ratatui_style(style: SyntectStyle, base: Style) -> Style {
-    // Preserve diff semantic background while letting syntect own token foreground color.
-    let mut mapped = base.fg(Color::Rgb(
-        style.foreground.r,
-        style.foreground.g,
-        style.foreground.b,
-    ));
-
-    if style.font_style.contains(FontStyle::BOLD) {
-        mapped = mapped.add_modifier(Modifier::BOLD);
-    }
-    if style.font_style.contains(FontStyle::ITALIC) {
-        mapped = mapped.add_modifier(Modifier::ITALIC);
-    }
-    if style.font_style.contains(FontStyle::UNDERLINE) {
-        mapped = mapped.add_modifier(Modifier::UNDERLINED);
-    }
-
-    mapped
-}
-
-fn row_style(kind: RowKind) -> Style {
-    match kind {
-        RowKind::Equal => Style::default(),
-        RowKind::Changed => Style::default().bg(Color::Rgb(63, 54, 18)),
-        RowKind::Insert => Style::default().bg(Color::Rgb(18, 60, 36)),
-        RowKind::Delete => Style::default().bg(Color::Rgb(72, 24, 24)),
+    match (depth, bg) {
+        (ColorDepth::Ansi16, Color::Rgb(..) | Color::Indexed(_)) => Style::default().bg(fallback),
+        _ => Style::default().bg(depth.reduce_tint(bg)),
     }
 }
 
@@ -452,6 +714,7 @@ fn selected_tree_row_idx(app: &App) -> Option<usize> {
 fn render_scrollbar(
     frame: &mut Frame<'_>,
     area: Rect,
+    colors: &Palette,
     rows: Option<&[AlignedRow]>,
     v_scroll: usize,
     viewport_rows: usize,
@@ -461,7 +724,7 @@ fn render_scrollbar(
     }
 
     let height = area.height as usize;
-    let mut glyphs = vec![Line::styled("│", Style::default().fg(Color::DarkGray)); height];
+    let mut glyphs = vec![Line::styled("│", Style::default().fg(colors.scrollbar_track)); height];
 
     if let Some(rows) = rows {
         if !rows.is_empty() {
@@ -473,7 +736,7 @@ fn render_scrollbar(
                 }
                 let pos = idx.saturating_mul(height) / total;
                 if pos < glyphs.len() {
-                    glyphs[pos] = Line::styled("╵", Style::default().fg(Color::LightGreen));
+                    glyphs[pos] = Line::styled("╵", Style::default().fg(colors.scrollbar_change));
                 }
             }
 
@@ -485,7 +748,7 @@ fn render_scrollbar(
 
             for pos in thumb_start..thumb_start + thumb_len {
                 if pos < glyphs.len() {
-                    glyphs[pos] = Line::styled("█", Style::default().fg(Color::Green));
+                    glyphs[pos] = Line::styled("█", Style::default().fg(colors.scrollbar_thumb));
                 }
             }
         }
@@ -498,104 +761,36 @@ fn render_scrollbar(
 mod tests {
     use std::path::Path;
 
-    use ratatui::style::{Color, Modifier, Style};
+    use ratatui::style::{Color, Style};
     use ratatui::text::Span;
-    use syntect::easy::HighlightLines;
-    use syntect::highlighting::{Color as SyntectColor, FontStyle, Style as SyntectStyle};
 
+    use crate::highlight::{HighlightCache, HighlightedRow};
     use crate::model::{AlignedRow, RowKind};
+    use crate::theme::{ColorDepth, Theme, THEMES};
 
-    use super::{
-        clip_spans, highlight_line, syntax_for_path, syntax_set, syntax_theme,
-        syntect_to_ratatui_style, with_highlighted_rows_for_file,
-    };
-
-    #[test]
-    fn resolves_rust_syntax_by_extension() {
-        let syntax = syntax_for_path(Path::new("src/main.rs"), syntax_set());
-        assert_eq!(syntax.name, "Rust");
-    }
-
-    #[test]
-    fn falls_back_to_plain_text_syntax_for_unknown_extension() {
-        let syntax = syntax_for_path(Path::new("assets/file.unknown"), syntax_set());
-        assert_eq!(syntax.name, syntax_set().find_syntax_plain_text().name);
-    }
-
-    #[test]
-    fn maps_syntect_styles_to_ratatui_styles() {
-        let syntect_style = SyntectStyle {
-            foreground: SyntectColor {
-                r: 1,
-                g: 2,
-                b: 3,
-                a: 255,
-            },
-            background: SyntectColor {
-                r: 10,
-                g: 20,
-                b: 30,
-                a: 255,
-            },
-            font_style: FontStyle::BOLD | FontStyle::ITALIC,
-        };
-
-        let mapped = syntect_to_ratatui_style(syntect_style, Style::default());
-
-        assert_eq!(mapped.fg, Some(Color::Rgb(1, 2, 3)));
-        assert!(mapped.add_modifier.contains(Modifier::BOLD));
-        assert!(mapped.add_modifier.contains(Modifier::ITALIC));
-    }
-
-    #[test]
-    fn preserves_existing_diff_background_color() {
-        let syntect_style = SyntectStyle {
-            foreground: SyntectColor {
-                r: 200,
-                g: 100,
-                b: 50,
-                a: 255,
-            },
-            background: SyntectColor {
-                r: 10,
-                g: 20,
-                b: 30,
-                a: 255,
-            },
-            font_style: FontStyle::BOLD,
-        };
-
-        let mapped =
-            syntect_to_ratatui_style(syntect_style, Style::default().bg(Color::Rgb(50, 51, 52)));
-
-        assert_eq!(mapped.fg, Some(Color::Rgb(200, 100, 50)));
-        assert_eq!(mapped.bg, Some(Color::Rgb(50, 51, 52)));
-        assert!(mapped.add_modifier.contains(Modifier::BOLD));
-    }
-
-    #[test]
-    fn highlight_line_produces_spans() {
-        let syntax = syntax_for_path(Path::new("src/main.rs"), syntax_set());
-        let mut highlighter = HighlightLines::new(syntax, syntax_theme());
-        let spans = highlight_line(
-            "fn main() { let x = 1; }",
-            &mut highlighter,
-            Style::default(),
-        );
-
-        assert!(!spans.is_empty());
-        assert!(spans.iter().all(|span| !span.content.is_empty()));
-    }
+    use super::{build_visible_rows, clip_spans, row_style, status_position, styled_diff_line};
+    use crate::app::Status;
 
     #[test]
     fn clip_spans_matches_plain_text_clipping() {
         let text = "let greeting = \"hello world\";";
-        let syntax = syntax_for_path(Path::new("src/main.rs"), syntax_set());
-        let mut highlighter = HighlightLines::new(syntax, syntax_theme());
-        let highlighted = highlight_line(text, &mut highlighter, Style::default());
+        let rows = vec![AlignedRow {
+            left_line_no: Some(1),
+            right_line_no: Some(1),
+            left_text: text.to_string(),
+            right_text: text.to_string(),
+            kind: RowKind::Equal,
+        }];
 
-        let clipped = clip_spans(&highlighted, 4, 14);
-        let clipped_text: String = clipped
+        let highlighted = HighlightCache::spawn(Theme::default().syntax).window_blocking(
+            Path::new("clip_test.rs"),
+            0,
+            &rows,
+            0,
+            0,
+            1,
+        );
+        let clipped_text = clip_spans(&highlighted[0].left, 4, 14)
             .iter()
             .map(|span| span.content.as_ref())
             .collect::<Vec<_>>()
@@ -622,86 +817,163 @@ mod tests {
     }
 
     #[test]
-    fn highlight_line_does_not_emit_trailing_newline() {
-        let syntax = syntax_for_path(Path::new("src/main.rs"), syntax_set());
-        let mut highlighter = HighlightLines::new(syntax, syntax_theme());
-        let text = "let value = 1;";
+    fn wrapped_rows_continue_below_and_pad_the_shorter_side() {
+        let rows = vec![
+            AlignedRow {
+                left_line_no: Some(1),
+                right_line_no: Some(1),
+                left_text: "abc".to_string(),
+                right_text: "abcdefghij".to_string(),
+                kind: RowKind::Changed,
+            },
+            AlignedRow {
+                left_line_no: Some(2),
+                right_line_no: Some(2),
+                left_text: "x".to_string(),
+                right_text: "x".to_string(),
+                kind: RowKind::Equal,
+            },
+        ];
+        let highlighted: Vec<HighlightedRow> = rows.iter().map(HighlightedRow::plain).collect();
+        let text = |line: &ratatui::text::Line<'_>| -> String {
+            line.spans
+                .iter()
+                .map(|span| span.content.as_ref())
+                .collect()
+        };
 
-        let spans = highlight_line(text, &mut highlighter, Style::default());
-        let rendered: String = spans
-            .iter()
-            .map(|span| span.content.as_ref())
-            .collect::<Vec<_>>()
-            .join("");
+        let colors = Theme::default().colors;
 
-        assert_eq!(rendered, text);
+        let (left, right) = build_visible_rows(
+            &colors,
+            ColorDepth::TrueColor,
+            &rows,
+            &highlighted,
+            &[false, false],
+            None,
+            4,
+        );
+        let right: Vec<String> = right.iter().map(text).collect();
+        assert_eq!(right, ["   1 abcd", "     efgh", "     ij", "   2 x"]);
+        let left: Vec<String> = left.iter().map(text).collect();
+        assert_eq!(left, ["   1 abc", "     ", "     ", "   2 x"]);
+        assert_eq!(rows[0].wrapped_height(4), 3);
+
+        let (_, right) = build_visible_rows(
+            &colors,
+            ColorDepth::TrueColor,
+            &rows,
+            &highlighted,
+            &[false, false],
+            Some(2),
+            4,
+        );
+        assert_eq!(text(&right[0]), "   1 cdef");
+        assert_eq!(right.len(), 2);
     }
 
     #[test]
-    fn highlight_state_advances_across_empty_line() {
-        let syntax = syntax_for_path(Path::new("src/main.rs"), syntax_set());
-        let mut highlighter = HighlightLines::new(syntax, syntax_theme());
+    fn styled_diff_line_keeps_token_color_over_row_background() {
+        let spans = vec![Span::styled(
+            "x".to_string(),
+            Style::default().fg(Color::Red),
+        )];
+
+        let colors = Theme::builtin("light").unwrap().colors;
+        let base = row_style(&colors, ColorDepth::TrueColor, RowKind::Insert);
+        let line = styled_diff_line(&colors, Some(1), base, false, &spans, 0, 10);
+        let token = &line.spans[2];
+
+        assert_eq!(token.style.fg, Some(Color::Red));
+        assert_eq!(token.style.bg, Some(colors.insert_bg));
+        assert_eq!(line.spans[0].style.fg, Some(colors.gutter));
+    }
 
-        let comment_spans = highlight_line("// comment", &mut highlighter, Style::default());
-        let comment_style = comment_spans
-            .iter()
-            .find(|span| span.content.contains("comment"))
-            .expect("expected comment token")
-            .style;
+    #[test]
+    fn styled_diff_line_marks_rows_changed_since_refresh() {
+        let spans = vec![Span::raw("x")];
 
-        let _ = highlight_line("", &mut highlighter, Style::default());
-        let code_spans = highlight_line("let value = 1;", &mut highlighter, Style::default());
-        let let_style = code_spans
-            .iter()
-            .find(|span| span.content.contains("let"))
-            .expect("expected let token")
-            .style;
+        let colors = Theme::default().colors;
+        let base = row_style(&colors, ColorDepth::TrueColor, RowKind::Equal);
+        let marked = styled_diff_line(&colors, Some(1), base, true, &spans, 0, 10);
+        let plain = styled_diff_line(&colors, Some(1), base, false, &spans, 0, 10);
 
-        assert_ne!(let_style, comment_style);
+        assert_eq!(marked.spans[1].content.as_ref(), "•");
+        assert_eq!(plain.spans[1].content.as_ref(), " ");
+        assert_eq!(marked.width(), plain.width());
     }
 
     #[test]
-    fn highlight_cache_invalidates_when_epoch_changes() {
-        let syntax = syntax_for_path(Path::new("cache_epoch_test.rs"), syntax_set());
-        let file_path = Path::new("cache_epoch_test.rs");
-        let mut rows = vec![AlignedRow {
-            left_line_no: Some(1),
-            right_line_no: Some(1),
-            left_text: "let value = 1;".to_string(),
-            right_text: "let value = 1;".to_string(),
-            kind: RowKind::Equal,
-        }];
+    fn status_position_leaves_out_what_is_not_loaded() {
+        let mut status = Status {
+            file: Some((7, 42)),
+            ..Status::default()
+        };
+        assert_eq!(status_position(&status), "file 7/42");
+
+        status.hunk = Some((None, 11));
+        status.right_line = Some(3);
+        status.stats = Some((12, 3));
+        assert_eq!(
+            status_position(&status),
+            "file 7/42  hunk -/11  L- R3  +12 -3"
+        );
 
-        let first = with_highlighted_rows_for_file(file_path, &rows, syntax, 10, |highlighted| {
-            highlighted[0]
-                .left
-                .iter()
-                .map(|span| span.content.as_ref())
-                .collect::<Vec<_>>()
-                .join("")
-        });
+        assert_eq!(status_position(&Status::default()), "no changes");
+    }
 
-        rows[0].left_text = "let changed = 2;".to_string();
+    #[test]
+    fn mono_rows_differ_by_modifier_alone() {
+        let colors = Theme::default().colors;
+        let styles: Vec<Style> = [
+            RowKind::Equal,
+            RowKind::Changed,
+            RowKind::Insert,
+            RowKind::Delete,
+        ]
+        .into_iter()
+        .map(|kind| row_style(&colors, ColorDepth::Mono, kind))
+        .collect();
 
-        let stale = with_highlighted_rows_for_file(file_path, &rows, syntax, 10, |highlighted| {
-            highlighted[0]
-                .left
-                .iter()
-                .map(|span| span.content.as_ref())
-                .collect::<Vec<_>>()
-                .join("")
-        });
-        let refreshed =
-            with_highlighted_rows_for_file(file_path, &rows, syntax, 11, |highlighted| {
-                highlighted[0]
-                    .left
-                    .iter()
-                    .map(|span| span.content.as_ref())
-                    .collect::<Vec<_>>()
-                    .join("")
-            });
-
-        assert_eq!(stale, first);
-        assert_eq!(refreshed, "let changed = 2;");
+        for (idx, style) in styles.iter().enumerate() {
+            assert_eq!(style.bg, None);
+            assert!(styles[idx + 1..].iter().all(|other| other != style));
+        }
+    }
+
+    #[test]
+    fn row_kinds_stay_distinct_at_every_depth_and_theme() {
+        let depths = [
+            ColorDepth::TrueColor,
+            ColorDepth::Ansi256,
+            ColorDepth::Ansi16,
+            ColorDepth::Mono,
+        ];
+        for name in THEMES {
+            let colors = Theme::builtin(name).unwrap().colors;
+            for depth in depths {
+                let shown: Vec<_> = [
+                    RowKind::Equal,
+                    RowKind::Changed,
+                    RowKind::Insert,
+                    RowKind::Delete,
+                ]
+                .into_iter()
+                .map(|kind| {
+                    let style = row_style(&colors, depth, kind);
+                    (style.bg.map(|bg| depth.reduce(bg)), style.add_modifier)
+                })
+                .collect();
+                for (idx, style) in shown.iter().enumerate() {
+                    assert!(
+                        shown[idx + 1..].iter().all(|other| other != style),
+                        "{} at {:?}: {:?}",
+                        name,
+                        depth,
+                        shown
+                    );
+                }
+            }
+        }
     }
 }
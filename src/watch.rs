@@ -0,0 +1,348 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
+use notify::event::{CreateKind, EventKind};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Quiet period after the last event before a batch is reported.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Upper bound on how long a steady stream of events can delay a batch.
+const MAX_BATCH_DELAY: Duration = Duration::from_secs(1);
+
+/// One debounced batch of relevant filesystem changes.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Changes {
+    /// Repository-relative worktree paths that were touched.
+    pub paths: BTreeSet<PathBuf>,
+    /// `HEAD` or the branch it points to moved (commit, checkout, reset, fetch into the
+    /// current branch), so every file's left side may be stale.
+    pub head_changed: bool,
+    /// The index changed, which can alter staged/unstaged status without touching the worktree.
+    pub index_changed: bool,
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        self.paths.is_empty() && !self.head_changed && !self.index_changed
+    }
+}
+
+/// Watches the worktree, the git index, `HEAD` and branch refs, reporting debounced
+/// [`Changes`].
+pub struct RepoWatcher {
+    changes: Receiver<Changes>,
+}
+
+impl RepoWatcher {
+    pub fn spawn(repo_root: &Path, git_dir: &Path) -> Result<Self> {
+        let (event_tx, event_rx) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(event_tx).context("failed to start file watcher")?;
+
+        // Directories are watched one by one so ignored trees such as `target/` or
+        // `node_modules/` never consume watch descriptors.
+        for dir in watched_dirs(repo_root) {
+            watcher
+                .watch(&dir, RecursiveMode::NonRecursive)
+                .with_context(|| format!("failed to watch {}", dir.display()))?;
+        }
+        // A commit rewrites the branch ref (or `packed-refs`) and `logs/HEAD`, not `HEAD`. Linked
+        // worktrees keep their `HEAD` in `git_dir` and the refs in the common directory.
+        let common_dir = common_dir(git_dir);
+        let git_dirs = [
+            (git_dir.to_path_buf(), RecursiveMode::NonRecursive),
+            (git_dir.join("logs"), RecursiveMode::NonRecursive),
+            (common_dir.clone(), RecursiveMode::NonRecursive),
+            (
+                common_dir.join("refs").join("heads"),
+                RecursiveMode::Recursive,
+            ),
+        ];
+        for (idx, (dir, mode)) in git_dirs.iter().enumerate() {
+            let seen = git_dirs[..idx].iter().any(|(earlier, _)| earlier == dir);
+            if !seen && (dir == git_dir || dir.is_dir()) {
+                watcher
+                    .watch(dir, *mode)
+                    .with_context(|| format!("failed to watch {}", dir.display()))?;
+            }
+        }
+
+        let filter = EventFilter::new(repo_root, git_dir, &common_dir);
+        let (changes_tx, changes) = mpsc::channel();
+        thread::spawn(move || run_debouncer(watcher, &event_rx, &filter, &changes_tx));
+
+        Ok(Self { changes })
+    }
+
+    /// Returns all batches reported since the last call, merged into one.
+    pub fn try_recv(&self) -> Option<Changes> {
+        let mut merged: Option<Changes> = None;
+        for batch in self.changes.try_iter() {
+            let merged = merged.get_or_insert_with(Changes::default);
+            merged.paths.extend(batch.paths);
+            merged.head_changed |= batch.head_changed;
+            merged.index_changed |= batch.index_changed;
+        }
+        merged
+    }
+}
+
+/// The directory holding refs shared by all worktrees: `git_dir` itself unless it is a linked
+/// worktree's, whose `commondir` file points at the main repository's.
+fn common_dir(git_dir: &Path) -> PathBuf {
+    match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(relative) => {
+            let dir = git_dir.join(relative.trim());
+            fs::canonicalize(&dir).unwrap_or(dir)
+        }
+        Err(_) => git_dir.to_path_buf(),
+    }
+}
+
+fn watched_dirs(repo_root: &Path) -> Vec<PathBuf> {
+    WalkBuilder::new(repo_root)
+        .hidden(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_dir()))
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+type NotifyEvents = Receiver<notify::Result<notify::Event>>;
+
+fn run_debouncer(
+    mut watcher: RecommendedWatcher,
+    events: &NotifyEvents,
+    filter: &EventFilter,
+    changes: &Sender<Changes>,
+) {
+    let mut pending = Changes::default();
+    let mut batch_started: Option<Instant> = None;
+
+    loop {
+        let timeout = match batch_started {
+            Some(started) => DEBOUNCE.min(MAX_BATCH_DELAY.saturating_sub(started.elapsed())),
+            None => Duration::from_secs(3600),
+        };
+
+        match events.recv_timeout(timeout) {
+            Ok(Ok(event)) => {
+                if matches!(event.kind, EventKind::Create(CreateKind::Folder)) {
+                    for dir in &event.paths {
+                        if !filter.is_ignored(dir, true) {
+                            let _ = watcher.watch(dir, RecursiveMode::NonRecursive);
+                        }
+                    }
+                }
+                filter.record(&event, &mut pending);
+                if !pending.is_empty() && batch_started.is_none() {
+                    batch_started = Some(Instant::now());
+                }
+            }
+            // Dropped events only cost a refresh; keep watching.
+            Ok(Err(_)) => {}
+            Err(RecvTimeoutError::Timeout) => {
+                if batch_started.take().is_some()
+                    && changes.send(std::mem::take(&mut pending)).is_err()
+                {
+                    return;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Decides which raw events matter: worktree paths not excluded by git, plus `index`, `HEAD`
+/// and the refs `HEAD` resolves through.
+struct EventFilter {
+    repo_root: PathBuf,
+    git_dir: PathBuf,
+    common_dir: PathBuf,
+    gitignore: Gitignore,
+}
+
+impl EventFilter {
+    fn new(repo_root: &Path, git_dir: &Path, common_dir: &Path) -> Self {
+        let mut builder = GitignoreBuilder::new(repo_root);
+        // Missing files are fine; nested `.gitignore` files are already honoured by
+        // `watched_dirs`, which never watches ignored directories.
+        let _ = builder.add(repo_root.join(".gitignore"));
+        let _ = builder.add(git_dir.join("info").join("exclude"));
+        let gitignore = builder.build().unwrap_or_else(|_| Gitignore::empty());
+
+        Self {
+            repo_root: repo_root.to_path_buf(),
+            git_dir: git_dir.to_path_buf(),
+            common_dir: common_dir.to_path_buf(),
+            gitignore,
+        }
+    }
+
+    /// The branch `HEAD` points to, as a path relative to the common directory (`refs/heads/
+    /// main`); read on each event so a checkout to another branch is followed.
+    fn head_ref(&self) -> Option<PathBuf> {
+        let head = fs::read_to_string(self.git_dir.join("HEAD")).ok()?;
+        head.strip_prefix("ref: ")
+            .map(|name| PathBuf::from(name.trim()))
+    }
+
+    fn moves_head(&self, path: &Path) -> bool {
+        let in_git_dir = path.strip_prefix(&self.git_dir).ok();
+        let in_common_dir = path.strip_prefix(&self.common_dir).ok();
+        in_git_dir
+            .is_some_and(|inside| inside == Path::new("HEAD") || inside == Path::new("logs/HEAD"))
+            || in_common_dir.is_some_and(|inside| {
+                inside == Path::new("packed-refs")
+                    || (inside.starts_with("refs") && self.head_ref().as_deref() == Some(inside))
+            })
+    }
+
+    fn record(&self, event: &notify::Event, changes: &mut Changes) {
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+
+        for path in &event.paths {
+            if path.starts_with(&self.git_dir) || path.starts_with(&self.common_dir) {
+                if self.moves_head(path) {
+                    changes.head_changed = true;
+                } else if path == &self.git_dir.join("index") {
+                    changes.index_changed = true;
+                }
+                continue;
+            }
+
+            let Ok(relative) = path.strip_prefix(&self.repo_root) else {
+                continue;
+            };
+            if relative.as_os_str().is_empty() || self.is_ignored(path, path.is_dir()) {
+                continue;
+            }
+            changes.paths.insert(relative.to_path_buf());
+        }
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        path.strip_prefix(&self.repo_root).is_ok()
+            && self
+                .gitignore
+                .matched_path_or_any_parents(path, is_dir)
+                .is_ignore()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use notify::event::{AccessKind, CreateKind, DataChange, EventKind, ModifyKind};
+
+    use super::{common_dir, watched_dirs, Changes, EventFilter};
+
+    fn event(kind: EventKind, path: PathBuf) -> notify::Event {
+        notify::Event::new(kind).add_path(path)
+    }
+
+    fn scratch_repo(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("fdf-watch-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(".git/info")).expect("create git dir");
+        fs::create_dir_all(root.join("src")).expect("create src");
+        fs::create_dir_all(root.join("target/debug")).expect("create target");
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").expect("write gitignore");
+        root
+    }
+
+    #[test]
+    fn records_worktree_paths_and_git_state_but_skips_ignored_files() {
+        let root = scratch_repo("filter");
+        let git_dir = root.join(".git");
+        let filter = EventFilter::new(&root, &git_dir, &git_dir);
+        let modify = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+        let mut changes = Changes::default();
+
+        filter.record(&event(modify, root.join("src/main.rs")), &mut changes);
+        filter.record(&event(modify, root.join("debug.log")), &mut changes);
+        filter.record(&event(modify, root.join("target/debug/fdf")), &mut changes);
+        filter.record(&event(modify, git_dir.join("index")), &mut changes);
+        filter.record(&event(modify, git_dir.join("index.lock")), &mut changes);
+        filter.record(
+            &event(EventKind::Access(AccessKind::Any), root.join("src/lib.rs")),
+            &mut changes,
+        );
+
+        assert_eq!(
+            changes.paths.iter().collect::<Vec<_>>(),
+            vec![Path::new("src/main.rs")]
+        );
+        assert!(changes.index_changed);
+        assert!(!changes.head_changed);
+
+        filter.record(
+            &event(EventKind::Create(CreateKind::File), git_dir.join("HEAD")),
+            &mut changes,
+        );
+        assert!(changes.head_changed);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn commits_on_the_current_branch_move_head_in_linked_worktrees_too() {
+        let root = scratch_repo("refs");
+        let common = root.join(".git");
+        let linked = common.join("worktrees/topic");
+        fs::create_dir_all(&linked).expect("create linked git dir");
+        fs::write(linked.join("commondir"), "../..\n").expect("write commondir");
+        fs::write(linked.join("HEAD"), "ref: refs/heads/topic\n").expect("write linked HEAD");
+        fs::write(common.join("HEAD"), "ref: refs/heads/main\n").expect("write HEAD");
+        let modify = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+        let moved = |filter: &EventFilter, path: PathBuf| {
+            let mut changes = Changes::default();
+            filter.record(&event(modify, path), &mut changes);
+            changes.head_changed
+        };
+
+        let main = EventFilter::new(&root, &common, &common_dir(&common));
+        assert!(moved(&main, common.join("refs/heads/main")));
+        assert!(moved(&main, common.join("packed-refs")));
+        assert!(moved(&main, common.join("logs/HEAD")));
+        assert!(!moved(&main, common.join("refs/heads/topic")));
+
+        let common_dir = common_dir(&linked);
+        assert_eq!(common_dir, fs::canonicalize(&common).unwrap());
+        let worktree = EventFilter::new(&root, &linked, &common_dir);
+        assert!(moved(&worktree, common_dir.join("refs/heads/topic")));
+        assert!(moved(&worktree, linked.join("logs/HEAD")));
+        assert!(!moved(&worktree, common_dir.join("refs/heads/main")));
+        assert!(!moved(&worktree, common.join("logs/HEAD")));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn watched_dirs_skip_git_dir_and_ignored_trees() {
+        let root = scratch_repo("dirs");
+
+        let dirs = watched_dirs(&root);
+
+        assert!(dirs.contains(&root));
+        assert!(dirs.contains(&root.join("src")));
+        assert!(!dirs.iter().any(|dir| dir.starts_with(root.join("target"))));
+        assert!(!dirs.iter().any(|dir| dir.starts_with(root.join(".git"))));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
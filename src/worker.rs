@@ -0,0 +1,424 @@
+use std::collections::{HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::Result;
+
+use crate::diff::{
+    align_chunked, align_full_file, align_with, line_remap, DiffOptions, DEGRADED_CHUNK_LINES,
+};
+use crate::git::GitBackend;
+use crate::model::{
+    content_hash, format_bytes, AlignedRow, ChangedFile, ContentData, LineRemap, LoadLimits,
+    PreviousLoad, RowKind,
+};
+
+/// Handle for one in-flight load; dropping it does not cancel the job, [`LoadTicket::cancel`] does.
+#[derive(Debug)]
+pub struct LoadTicket {
+    pub file_idx: usize,
+    pub path: PathBuf,
+    pub generation: u64,
+    cancel: Arc<AtomicBool>,
+}
+
+impl LoadTicket {
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+pub struct LoadResult {
+    pub file_idx: usize,
+    pub generation: u64,
+    pub outcome: Result<ChangedFile>,
+}
+
+struct LoadJob {
+    file_idx: usize,
+    generation: u64,
+    prefetch: bool,
+    options: DiffOptions,
+    file: ChangedFile,
+    cancel: Arc<AtomicBool>,
+}
+
+/// Loads and diffs files on a background thread.
+pub struct Loader {
+    jobs: Sender<LoadJob>,
+    results: Receiver<LoadResult>,
+    next_generation: u64,
+    /// Applied to jobs queued from now on.
+    pub options: DiffOptions,
+}
+
+impl Loader {
+    /// `load_hint` tells how to load a file over the size limit anyway, for its placeholder.
+    pub fn spawn(git: Arc<dyn GitBackend>, limits: LoadLimits, load_hint: Option<String>) -> Self {
+        let (jobs, job_rx) = mpsc::channel::<LoadJob>();
+        let (result_tx, results) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut queue = VecDeque::new();
+            loop {
+                if queue.is_empty() {
+                    match job_rx.recv() {
+                        Ok(job) => queue.push_back(job),
+                        Err(_) => return,
+                    }
+                }
+                queue.extend(job_rx.try_iter());
+                queue.retain(|job: &LoadJob| !job.cancel.load(Ordering::Relaxed));
+
+                // Loads for the selected file jump ahead of queued prefetches.
+                let next = queue.iter().position(|job| !job.prefetch).unwrap_or(0);
+                if let Some(job) = queue.remove(next) {
+                    run_job(git.as_ref(), &limits, load_hint.as_deref(), job, &result_tx);
+                }
+            }
+        });
+
+        Self {
+            jobs,
+            results,
+            next_generation: 0,
+            options: DiffOptions::default(),
+        }
+    }
+
+    /// Queues `file` for loading. Only path, status, `force_load` and `previous` are taken
+    /// from it.
+    pub fn request(&mut self, file_idx: usize, file: &ChangedFile) -> LoadTicket {
+        self.enqueue(file_idx, file, false)
+    }
+
+    /// Like [`Loader::request`], but runs only when no regular request is waiting.
+    pub fn prefetch(&mut self, file_idx: usize, file: &ChangedFile) -> LoadTicket {
+        self.enqueue(file_idx, file, true)
+    }
+
+    fn enqueue(&mut self, file_idx: usize, file: &ChangedFile, prefetch: bool) -> LoadTicket {
+        let generation = self.next_generation;
+        self.next_generation = self.next_generation.wrapping_add(1);
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let mut job_file = ChangedFile::new(file.path.clone(), file.status.clone());
+        job_file.force_load = file.force_load;
+        job_file.previous = file.previous.clone();
+
+        // A closed channel means the worker is gone; the ticket then simply never completes.
+        let _ = self.jobs.send(LoadJob {
+            file_idx,
+            generation,
+            prefetch,
+            options: self.options,
+            file: job_file,
+            cancel: Arc::clone(&cancel),
+        });
+
+        LoadTicket {
+            file_idx,
+            path: file.path.clone(),
+            generation,
+            cancel,
+        }
+    }
+
+    pub fn try_recv(&self) -> Option<LoadResult> {
+        self.results.try_recv().ok()
+    }
+}
+
+fn run_job(
+    git: &dyn GitBackend,
+    limits: &LoadLimits,
+    load_hint: Option<&str>,
+    job: LoadJob,
+    results: &Sender<LoadResult>,
+) {
+    let LoadJob {
+        file_idx,
+        generation,
+        options,
+        mut file,
+        cancel,
+        ..
+    } = job;
+
+    if cancel.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let outcome = prepare_file(git, &mut file, limits, options, load_hint, &cancel);
+    if cancel.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let _ = results.send(LoadResult {
+        file_idx,
+        generation,
+        outcome: outcome.map(|()| file),
+    });
+}
+
+/// Reads both sides of `file` and computes its aligned rows, honoring `limits`.
+///
+/// A file over the limits gets placeholder rows, ending with `load_hint` if there is one.
+/// When `file.previous` holds a load from before a refresh, unchanged contents reuse its rows
+/// and changed contents record which rows differ and how worktree lines moved.
+pub fn prepare_file(
+    git: &dyn GitBackend,
+    file: &mut ChangedFile,
+    limits: &LoadLimits,
+    options: DiffOptions,
+    load_hint: Option<&str>,
+    cancel: &AtomicBool,
+) -> Result<()> {
+    if file.old_content.is_none() || file.new_content.is_none() {
+        git.load_contents(file, limits)?;
+    }
+    let hash = content_hash(&file.old_content, &file.new_content);
+    file.content_hash = Some(hash);
+    let previous = file.previous.take();
+
+    if file.aligned_rows.is_some() || cancel.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    if let Some(previous) = previous.as_deref().filter(|prev| prev.content_hash == hash) {
+        file.aligned_rows = Some(previous.aligned_rows.clone());
+        file.degraded = previous.degraded;
+        return Ok(());
+    }
+
+    let rows = match (&file.old_content, &file.new_content) {
+        (Some(ContentData::Text(old)), Some(ContentData::Text(new))) => {
+            file.degraded = file.force_load
+                || exceeds_line_limit(old, limits.max_line_chars)
+                || exceeds_line_limit(new, limits.max_line_chars);
+            if file.degraded {
+                align_with(options, old, new, |old, new| {
+                    align_chunked(old, new, DEGRADED_CHUNK_LINES)
+                })
+            } else {
+                align_with(options, old, new, align_full_file)
+            }
+        }
+        (
+            Some(ContentData::TooLarge { bytes: old }),
+            Some(ContentData::TooLarge { bytes: new }),
+        ) => too_large_rows(*old, *new, limits, &git.side_names(), load_hint),
+        _ => vec![placeholder_row("[binary or non-utf8 file]")],
+    };
+    file.aligned_rows = Some(rows);
+
+    if let Some(previous) = previous.filter(|_| !file.degraded) {
+        mark_changes(file, &previous);
+    }
+    Ok(())
+}
+
+fn mark_changes(file: &mut ChangedFile, previous: &PreviousLoad) {
+    let left = remap_between(&previous.old_content, &file.old_content);
+    let right = remap_between(&previous.new_content, &file.new_content);
+    let changed_lines = |remap: &Option<LineRemap>| -> HashSet<usize> {
+        remap.iter().flat_map(LineRemap::changed_lines).collect()
+    };
+    let left_changed = changed_lines(&left);
+    let right_changed = changed_lines(&right);
+
+    file.changed_rows = file
+        .aligned_rows
+        .iter()
+        .flatten()
+        .enumerate()
+        .filter(|(_, row)| {
+            row.left_line_no
+                .is_some_and(|no| left_changed.contains(&no))
+                || row
+                    .right_line_no
+                    .is_some_and(|no| right_changed.contains(&no))
+        })
+        .map(|(idx, _)| idx)
+        .collect();
+    file.line_remap = right;
+}
+
+/// Line mapping between two versions of one side, or `None` if that side did not change.
+fn remap_between(before: &Option<ContentData>, after: &Option<ContentData>) -> Option<LineRemap> {
+    match (before, after) {
+        (Some(ContentData::Text(before)), Some(ContentData::Text(after))) if before != after => {
+            Some(line_remap(before, after))
+        }
+        _ => None,
+    }
+}
+
+fn exceeds_line_limit(text: &str, max_line_chars: usize) -> bool {
+    text.lines()
+        .any(|line| line.len() > max_line_chars && line.chars().count() > max_line_chars)
+}
+
+fn too_large_rows(
+    old_bytes: u64,
+    new_bytes: u64,
+    limits: &LoadLimits,
+    [left, right]: &[String; 2],
+    load_hint: Option<&str>,
+) -> Vec<AlignedRow> {
+    let mut rows = vec![
+        placeholder_row(&format!(
+            "[file too large: {} {}, {} {}]",
+            left,
+            format_bytes(old_bytes),
+            right,
+            format_bytes(new_bytes)
+        )),
+        placeholder_row(&format!(
+            "[limit: {} per file, {} chars per line]",
+            format_bytes(limits.max_file_bytes),
+            limits.max_line_chars
+        )),
+    ];
+    rows.extend(load_hint.map(|hint| placeholder_row(&format!("[{}]", hint))));
+    rows
+}
+
+/// A note shown on both sides in place of file text.
+pub fn placeholder_row(text: &str) -> AlignedRow {
+    AlignedRow {
+        left_line_no: None,
+        right_line_no: None,
+        left_text: text.to_string(),
+        right_text: text.to_string(),
+        kind: RowKind::Changed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicBool;
+
+    use super::{exceeds_line_limit, prepare_file, too_large_rows};
+    use crate::diff::DiffOptions;
+    use crate::git::CliBackend;
+    use crate::model::{format_bytes, ChangedFile, ContentData, FileStatus, LoadLimits, RowKind};
+
+    #[test]
+    fn too_large_placeholder_reports_sizes_and_limits() {
+        let limits = LoadLimits {
+            max_file_bytes: 1024,
+            max_line_chars: 80,
+            ..LoadLimits::default()
+        };
+
+        let sides = ["index".to_string(), "worktree".to_string()];
+        let rows = too_large_rows(512, 3 * 1024 * 1024, &limits, &sides, Some("press X"));
+        let text: Vec<&str> = rows.iter().map(|row| row.right_text.as_str()).collect();
+
+        assert_eq!(text[0], "[file too large: index 512 B, worktree 3.0 MB]");
+        assert!(text[1].contains("1.0 KB") && text[1].contains("80 chars"));
+        assert_eq!(text[2], "[press X]");
+        assert_eq!(too_large_rows(0, 0, &limits, &sides, None).len(), 2);
+        assert_eq!(format_bytes(900), "900 B");
+    }
+
+    #[test]
+    fn line_limit_counts_characters_not_bytes() {
+        assert!(!exceeds_line_limit("ééé\nab", 3));
+        assert!(exceeds_line_limit("ok\nabcd", 3));
+    }
+
+    #[test]
+    fn prepare_file_degrades_long_lines_without_touching_git() {
+        let mut file = ChangedFile::new(PathBuf::from("bundle.js"), FileStatus::default());
+        file.old_content = Some(ContentData::Text("x".repeat(50)));
+        file.new_content = Some(ContentData::Text("y".repeat(50)));
+        let limits = LoadLimits {
+            max_file_bytes: 1024,
+            max_line_chars: 10,
+            ..LoadLimits::default()
+        };
+
+        prepare_file(
+            &CliBackend::new(PathBuf::new()),
+            &mut file,
+            &limits,
+            DiffOptions::default(),
+            None,
+            &AtomicBool::new(false),
+        )
+        .expect("preloaded contents should align");
+
+        assert!(file.degraded);
+        let rows = file.aligned_rows.expect("rows computed");
+        assert_eq!(rows[0].kind, RowKind::Changed);
+    }
+
+    fn reload(previous: &ChangedFile, old: &str, new: &str) -> ChangedFile {
+        let mut stale = previous.clone();
+        stale.stash_for_reload();
+
+        let mut file = ChangedFile::new(previous.path.clone(), FileStatus::default());
+        file.previous = stale.previous;
+        file.old_content = Some(ContentData::Text(old.to_string()));
+        file.new_content = Some(ContentData::Text(new.to_string()));
+        prepare_file(
+            &CliBackend::new(PathBuf::new()),
+            &mut file,
+            &LoadLimits::default(),
+            DiffOptions::default(),
+            None,
+            &AtomicBool::new(false),
+        )
+        .expect("preloaded contents should align");
+        file
+    }
+
+    #[test]
+    fn reload_marks_changed_rows_and_remaps_worktree_lines() {
+        let first = reload(
+            &ChangedFile::new(PathBuf::from("lib.rs"), FileStatus::default()),
+            "a\nb\nc\n",
+            "a\nb\nc\n",
+        );
+
+        let second = reload(&first, "a\nb\nc\n", "top\na\nb\nC\n");
+
+        let mut changed: Vec<usize> = second.changed_rows.iter().copied().collect();
+        changed.sort_unstable();
+        let rows = second.aligned_rows.as_ref().expect("rows computed");
+        let changed_text: Vec<&str> = changed
+            .iter()
+            .map(|idx| rows[*idx].right_text.as_str())
+            .collect();
+        assert_eq!(changed_text, vec!["top", "C"]);
+        let remap = second.line_remap.as_ref().expect("worktree side changed");
+        assert_eq!(remap.map(2), 3);
+    }
+
+    #[test]
+    fn unchanged_contents_reuse_previous_rows() {
+        let first = reload(
+            &ChangedFile::new(PathBuf::from("same.rs"), FileStatus::default()),
+            "x\n",
+            "y\n",
+        );
+        let mut marker = first.clone();
+        marker.aligned_rows.as_mut().expect("rows computed")[0].right_text = "cached".to_string();
+
+        let second = reload(&marker, "x\n", "y\n");
+
+        assert_eq!(
+            second.aligned_rows.expect("rows reused")[0].right_text,
+            "cached"
+        );
+        assert!(second.changed_rows.is_empty());
+        assert!(second.line_remap.is_none());
+    }
+}